@@ -25,4 +25,854 @@ $4016 	JOY1 	Joystick 1 data (R) and joystick strobe (W)
 $4017 	JOY2 	Joystick 2 data (R) and frame counter control (W) 
 
 http://wiki.nesdev.com/w/index.php/2A03
-*/
\ No newline at end of file
+*/
+
+/* Emulates the 2A03 Audio Processing Unit.
+ * Two pulse channels, a triangle, a noise channel and the delta modulation
+ *  channel (DMC). Timing follows fogleman/nes and nesdev "APU":
+ *  - step() is called once per CPU cycle.
+ *  - The triangle timer is clocked every CPU cycle, the others every other.
+ *  - Envelopes, sweeps and length counters are clocked by the frame
+ *    sequencer ($4017), through the step_* functions.
+ *  - The frame IRQ and DMC IRQ are level signals, NES::step polls
+ *    irq_pending() and asserts the CPU IRQ line while either is set.
+ * The DMC reads its samples through the CPU bus, so MEM does the fetching
+ *  (see MEM::step_apu) and stalls the CPU for it.
+ */
+
+pub use ::log::*;
+
+use crate::core::audio::{mix, mix_scaled};
+
+/// Length counter load values, indexed by the top 5 bits of $4003/$4007/$400B/$400F.
+pub static LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20,  2, 40,  4, 80,  6, 160,  8, 60, 10, 14, 12, 26, 14,
+    12,  16, 24, 18, 48, 20, 96, 22, 192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+/// Pulse duty cycle sequences.
+pub static DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+/// Triangle output sequence.
+pub static TRIANGLE_TABLE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10,  9,  8,  7,  6,  5,  4,  3,  2,  1,  0,
+     0,  1,  2,  3,  4,  5,  6,  7,  8,  9, 10, 11, 12, 13, 14, 15,
+];
+
+/// Noise timer periods (NTSC), in APU cycles.
+pub static NOISE_TABLE: [u16; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+
+/// DMC timer periods (NTSC), in CPU cycles.
+pub static DMC_TABLE: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+
+/// Channel names, in the order channel_levels() returns them.
+pub const CHANNEL_NAMES: [&str; 5] = ["pulse1", "pulse2", "triangle", "noise", "dmc"];
+
+/// A sound channel that can be muted, soloed and rescaled. Expansion is
+///  whatever sound hardware the cartridge has (see MAP::expansion_audio).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Channel {
+    Pulse1,
+    Pulse2,
+    Triangle,
+    Noise,
+    Dmc,
+    Expansion,
+}
+
+impl Channel {
+    pub const ALL: [Channel; 6] = [Channel::Pulse1, Channel::Pulse2, Channel::Triangle,
+                                   Channel::Noise, Channel::Dmc, Channel::Expansion];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Channel::Pulse1     => "pulse1",
+            Channel::Pulse2     => "pulse2",
+            Channel::Triangle   => "triangle",
+            Channel::Noise      => "noise",
+            Channel::Dmc        => "dmc",
+            Channel::Expansion  => "expansion",
+        }
+    }
+
+    /// Looks a channel up by name(), ignoring case.
+    pub fn from_name(name: &str) -> Option<Channel> {
+        Channel::ALL.iter().cloned().find(|c| c.name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// Mute, solo and volume for each Channel. While any channel is soloed,
+///  only soloed channels play.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelControls {
+    pub volume: [f32; 6],
+    pub muted:  [bool; 6],
+    pub solo:   [bool; 6],
+}
+
+impl ChannelControls {
+    pub fn new() -> ChannelControls {
+        ChannelControls { volume: [1.0; 6], muted: [false; 6], solo: [false; 6] }
+    }
+
+    /// What a channel's output is multiplied by.
+    pub fn gain(&self, channel: Channel) -> f32 {
+        let i = channel as usize;
+        let audible = if self.solo.iter().any(|&s| s) { self.solo[i] } else { !self.muted[i] };
+        if audible { self.volume[i] } else { 0.0 }
+    }
+
+    /// True when nothing is changed, so the plain mixer can be used.
+    pub fn is_default(&self) -> bool {
+        *self == ChannelControls::new()
+    }
+}
+
+impl Default for ChannelControls {
+    fn default() -> ChannelControls { ChannelControls::new() }
+}
+
+/// Frame sequencer steps, in CPU cycles since the last reset ($4017 write).
+///  Quarter frames clock envelopes and the triangle linear counter, half
+///  frames also clock length counters and sweeps.
+pub const FRAME_QUARTER_1: u32 = 7457;
+pub const FRAME_HALF_1:    u32 = 14913;
+pub const FRAME_QUARTER_3: u32 = 22371;
+/// 4-step mode: the IRQ flag is set on the last three cycles of the sequence.
+pub const FRAME_4_STEP_IRQ:  u32 = 29828;
+pub const FRAME_4_STEP_HALF: u32 = 29829;
+pub const FRAME_4_STEP_END:  u32 = 29830;
+/// 5-step mode: no IRQ, and the last step comes a step later.
+pub const FRAME_5_STEP_HALF: u32 = 37281;
+pub const FRAME_5_STEP_END:  u32 = 37282;
+
+/// CPU cycles the DMC steals from the CPU for each sample byte it fetches.
+pub const DMC_STALL_CYCLES: u16 = 4;
+
+//~ENVELOPE~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+/// Volume envelope, shared by the pulse and noise channels.
+#[derive(Debug, Default, Clone)]
+pub struct Envelope {
+    pub enabled:    bool,   //False means constant volume.
+    pub looping:    bool,
+    pub start:      bool,
+    pub period:     u8,
+    pub value:      u8,
+    pub volume:     u8,     //Decay level, 0-15.
+    pub constant:   u8,     //Constant volume, 0-15.
+}
+
+impl Envelope {
+    /// Handles the ddLC.VVVV style control byte ($4000/$4004/$400C).
+    fn write_control(&mut self, val: u8) {
+        self.looping  = (val >> 5) & 1 == 1;
+        self.enabled  = (val >> 4) & 1 == 0;
+        self.period   = val & 0x0F;
+        self.constant = val & 0x0F;
+        self.start    = true;
+    }
+
+    /// Clocked by the frame sequencer (quarter frames).
+    pub fn step(&mut self) {
+        if self.start {
+            self.volume = 15;
+            self.value = self.period;
+            self.start = false;
+        }
+        else if self.value > 0 {
+            self.value -= 1;
+        }
+        else {
+            if self.volume > 0 {
+                self.volume -= 1;
+            }
+            else if self.looping {
+                self.volume = 15;
+            }
+            self.value = self.period;
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        if self.enabled { self.volume } else { self.constant }
+    }
+}
+
+//~PULSE~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+/// Pulse (square) channel, $4000-$4003 and $4004-$4007.
+#[derive(Debug, Default, Clone)]
+pub struct Pulse {
+    pub enabled:        bool,
+    /// 1 or 2, pulse 1 negates its sweep with one's complement.
+    pub channel:        u8,
+    pub length_enabled: bool,
+    pub length_value:   u8,
+    pub timer_period:   u16,
+    pub timer_value:    u16,
+    pub duty_mode:      u8,
+    pub duty_value:     u8,
+    pub sweep_reload:   bool,
+    pub sweep_enabled:  bool,
+    pub sweep_negate:   bool,
+    pub sweep_shift:    u8,
+    pub sweep_period:   u8,
+    pub sweep_value:    u8,
+    pub envelope:       Envelope,
+}
+
+impl Pulse {
+    pub fn new(channel: u8) -> Pulse {
+        Pulse { channel, ..Default::default() }
+    }
+
+    /// $4000/$4004: DDLC VVVV
+    pub fn write_control(&mut self, val: u8) {
+        self.duty_mode = (val >> 6) & 3;
+        self.length_enabled = (val >> 5) & 1 == 0;
+        self.envelope.write_control(val);
+    }
+
+    /// $4001/$4005: EPPP NSSS
+    pub fn write_sweep(&mut self, val: u8) {
+        self.sweep_enabled = (val >> 7) & 1 == 1;
+        self.sweep_period = ((val >> 4) & 7) + 1;
+        self.sweep_negate = (val >> 3) & 1 == 1;
+        self.sweep_shift = val & 7;
+        self.sweep_reload = true;
+    }
+
+    /// $4002/$4006: LLLL LLLL
+    pub fn write_timer_low(&mut self, val: u8) {
+        self.timer_period = (self.timer_period & 0xFF00) | val as u16;
+    }
+
+    /// $4003/$4007: llll lHHH
+    pub fn write_timer_high(&mut self, val: u8) {
+        if self.enabled {
+            self.length_value = LENGTH_TABLE[(val >> 3) as usize];
+        }
+        self.timer_period = (self.timer_period & 0x00FF) | ((val as u16 & 7) << 8);
+        self.envelope.start = true;
+        self.duty_value = 0;
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.length_value = 0;
+        }
+    }
+
+    /// Clocked every APU cycle (every other CPU cycle).
+    pub fn step_timer(&mut self) {
+        if self.timer_value == 0 {
+            self.timer_value = self.timer_period;
+            self.duty_value = (self.duty_value + 1) % 8;
+        }
+        else {
+            self.timer_value -= 1;
+        }
+    }
+
+    pub fn step_envelope(&mut self) {
+        self.envelope.step();
+    }
+
+    /// Clocked by the frame sequencer (half frames).
+    pub fn step_sweep(&mut self) {
+        if self.sweep_reload {
+            if self.sweep_enabled && self.sweep_value == 0 {
+                self.sweep();
+            }
+            self.sweep_value = self.sweep_period;
+            self.sweep_reload = false;
+        }
+        else if self.sweep_value > 0 {
+            self.sweep_value -= 1;
+        }
+        else {
+            if self.sweep_enabled {
+                self.sweep();
+            }
+            self.sweep_value = self.sweep_period;
+        }
+    }
+
+    /// Clocked by the frame sequencer (half frames).
+    pub fn step_length(&mut self) {
+        if self.length_enabled && self.length_value > 0 {
+            self.length_value -= 1;
+        }
+    }
+
+    /// The period the sweep unit is aiming for.
+    fn sweep_target(&self) -> u16 {
+        let delta = self.timer_period >> self.sweep_shift;
+        if self.sweep_negate {
+            //Pulse 1 adds the one's complement, pulse 2 the two's complement.
+            let delta = if self.channel == 1 { delta + 1 } else { delta };
+            self.timer_period.saturating_sub(delta)
+        }
+        else {
+            self.timer_period + delta
+        }
+    }
+
+    fn sweep(&mut self) {
+        let target = self.sweep_target();
+        if self.sweep_shift > 0 && target <= 0x7FF {
+            self.timer_period = target;
+        }
+    }
+
+    /// Current output level, 0-15.
+    pub fn output(&self) -> u8 {
+        if !self.enabled
+            || self.length_value == 0
+            || DUTY_TABLE[self.duty_mode as usize][self.duty_value as usize] == 0
+            || self.timer_period < 8
+            || self.sweep_target() > 0x7FF {
+            return 0;
+        }
+        self.envelope.output()
+    }
+}
+
+//~TRIANGLE~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+/// Triangle channel, $4008-$400B.
+#[derive(Debug, Default, Clone)]
+pub struct Triangle {
+    pub enabled:        bool,
+    pub length_enabled: bool,
+    pub length_value:   u8,
+    pub timer_period:   u16,
+    pub timer_value:    u16,
+    pub duty_value:     u8,
+    pub counter_period: u8,
+    pub counter_value:  u8,
+    pub counter_reload: bool,
+}
+
+impl Triangle {
+    /// $4008: CRRR RRRR
+    pub fn write_control(&mut self, val: u8) {
+        self.length_enabled = (val >> 7) & 1 == 0;
+        self.counter_period = val & 0x7F;
+    }
+
+    /// $400A: LLLL LLLL
+    pub fn write_timer_low(&mut self, val: u8) {
+        self.timer_period = (self.timer_period & 0xFF00) | val as u16;
+    }
+
+    /// $400B: llll lHHH
+    pub fn write_timer_high(&mut self, val: u8) {
+        if self.enabled {
+            self.length_value = LENGTH_TABLE[(val >> 3) as usize];
+        }
+        self.timer_period = (self.timer_period & 0x00FF) | ((val as u16 & 7) << 8);
+        self.timer_value = self.timer_period;
+        self.counter_reload = true;
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.length_value = 0;
+        }
+    }
+
+    /// Clocked every CPU cycle.
+    pub fn step_timer(&mut self) {
+        if self.timer_value == 0 {
+            self.timer_value = self.timer_period;
+            if self.length_value > 0 && self.counter_value > 0 {
+                self.duty_value = (self.duty_value + 1) % 32;
+            }
+        }
+        else {
+            self.timer_value -= 1;
+        }
+    }
+
+    pub fn step_length(&mut self) {
+        if self.length_enabled && self.length_value > 0 {
+            self.length_value -= 1;
+        }
+    }
+
+    /// Clocked by the frame sequencer (quarter frames).
+    pub fn step_counter(&mut self) {
+        if self.counter_reload {
+            self.counter_value = self.counter_period;
+        }
+        else if self.counter_value > 0 {
+            self.counter_value -= 1;
+        }
+        if self.length_enabled {
+            self.counter_reload = false;
+        }
+    }
+
+    /// Current output level, 0-15. When silenced the sequencer just holds
+    ///  its position, which is what the hardware does.
+    pub fn output(&self) -> u8 {
+        TRIANGLE_TABLE[self.duty_value as usize]
+    }
+}
+
+//~NOISE~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+/// Noise channel, $400C-$400F.
+#[derive(Debug, Clone)]
+pub struct Noise {
+    pub enabled:        bool,
+    /// Short mode, taps bit 6 instead of bit 1 for 93 step sequences.
+    pub mode:           bool,
+    pub shift_register: u16,
+    pub length_enabled: bool,
+    pub length_value:   u8,
+    pub timer_period:   u16,
+    pub timer_value:    u16,
+    pub envelope:       Envelope,
+}
+
+impl Default for Noise {
+    fn default() -> Noise {
+        Noise {
+            enabled:        false,
+            mode:           false,
+            shift_register: 1,
+            length_enabled: false,
+            length_value:   0,
+            timer_period:   0,
+            timer_value:    0,
+            envelope:       Envelope::default(),
+        }
+    }
+}
+
+impl Noise {
+    /// $400C: --LC VVVV
+    pub fn write_control(&mut self, val: u8) {
+        self.length_enabled = (val >> 5) & 1 == 0;
+        self.envelope.write_control(val);
+    }
+
+    /// $400E: M--- PPPP
+    pub fn write_period(&mut self, val: u8) {
+        self.mode = val & 0x80 == 0x80;
+        self.timer_period = NOISE_TABLE[(val & 0x0F) as usize];
+    }
+
+    /// $400F: llll l---
+    pub fn write_length(&mut self, val: u8) {
+        if self.enabled {
+            self.length_value = LENGTH_TABLE[(val >> 3) as usize];
+        }
+        self.envelope.start = true;
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.length_value = 0;
+        }
+    }
+
+    /// Clocked every APU cycle (every other CPU cycle).
+    pub fn step_timer(&mut self) {
+        if self.timer_value == 0 {
+            self.timer_value = self.timer_period;
+            let shift = if self.mode { 6 } else { 1 };
+            let feedback = (self.shift_register & 1) ^ ((self.shift_register >> shift) & 1);
+            self.shift_register >>= 1;
+            self.shift_register |= feedback << 14;
+        }
+        else {
+            self.timer_value -= 1;
+        }
+    }
+
+    pub fn step_envelope(&mut self) {
+        self.envelope.step();
+    }
+
+    pub fn step_length(&mut self) {
+        if self.length_enabled && self.length_value > 0 {
+            self.length_value -= 1;
+        }
+    }
+
+    /// Current output level, 0-15.
+    pub fn output(&self) -> u8 {
+        if !self.enabled || self.length_value == 0 || self.shift_register & 1 == 1 {
+            return 0;
+        }
+        self.envelope.output()
+    }
+}
+
+//~DMC~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+/// Delta modulation channel, $4010-$4013.
+/// Plays 1-bit delta encoded samples that it reads from $C000-$FFFF.
+#[derive(Debug, Default, Clone)]
+pub struct DMC {
+    pub enabled:            bool,
+    /// 7-bit output level.
+    pub value:              u8,
+    pub irq_enabled:        bool,
+    /// Set when a non-looping sample finishes with IRQs enabled.
+    pub irq_flag:           bool,
+    pub looping:            bool,
+    pub sample_address:     u16,
+    pub sample_length:      u16,
+    pub current_address:    u16,
+    pub current_length:     u16,
+    /// Byte waiting to be played, filled by the memory reader.
+    pub sample_buffer:      Option<u8>,
+    pub shift_register:     u8,
+    pub bit_count:          u8,
+    pub silence:            bool,
+    pub tick_period:        u16,
+    pub tick_value:         u16,
+}
+
+impl DMC {
+    /// $4010: IL-- RRRR
+    pub fn write_control(&mut self, val: u8) {
+        self.irq_enabled = val & 0x80 == 0x80;
+        if !self.irq_enabled {
+            self.irq_flag = false;
+        }
+        self.looping = val & 0x40 == 0x40;
+        self.tick_period = DMC_TABLE[(val & 0x0F) as usize];
+    }
+
+    /// $4011: -DDD DDDD
+    pub fn write_value(&mut self, val: u8) {
+        self.value = val & 0x7F;
+    }
+
+    /// $4012: sample address is $C000 + $40*val
+    pub fn write_address(&mut self, val: u8) {
+        self.sample_address = 0xC000 | ((val as u16) << 6);
+    }
+
+    /// $4013: sample length is $10*val + 1 bytes
+    pub fn write_length(&mut self, val: u8) {
+        self.sample_length = ((val as u16) << 4) | 1;
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.irq_flag = false;
+        if !enabled {
+            self.current_length = 0;
+        }
+        else if self.current_length == 0 {
+            self.restart();
+        }
+    }
+
+    fn restart(&mut self) {
+        self.current_address = self.sample_address;
+        self.current_length = self.sample_length;
+    }
+
+    /// The address the memory reader wants to read, if its buffer is empty
+    ///  and there's sample left. MEM reads it and hands it to fill_buffer.
+    pub fn fetch_address(&self) -> Option<u16> {
+        if self.sample_buffer.is_none() && self.current_length > 0 {
+            Some(self.current_address)
+        }
+        else {
+            None
+        }
+    }
+
+    /// Hands the DMC the byte it asked for in fetch_address.
+    pub fn fill_buffer(&mut self, val: u8) {
+        self.sample_buffer = Some(val);
+        self.current_address = if self.current_address == 0xFFFF { 0x8000 } else { self.current_address + 1 };
+        self.current_length -= 1;
+        if self.current_length == 0 {
+            if self.looping {
+                self.restart();
+            }
+            else if self.irq_enabled {
+                self.irq_flag = true;
+            }
+        }
+    }
+
+    /// Clocked every CPU cycle.
+    pub fn step_timer(&mut self) {
+        if self.tick_value == 0 {
+            self.tick_value = self.tick_period;
+            self.step_output();
+        }
+        else {
+            self.tick_value -= 1;
+        }
+    }
+
+    fn step_output(&mut self) {
+        if !self.silence {
+            if self.shift_register & 1 == 1 {
+                if self.value <= 125 {
+                    self.value += 2;
+                }
+            }
+            else if self.value >= 2 {
+                self.value -= 2;
+            }
+        }
+        self.shift_register >>= 1;
+
+        if self.bit_count > 0 {
+            self.bit_count -= 1;
+        }
+        if self.bit_count == 0 {
+            //New output cycle.
+            self.bit_count = 8;
+            match self.sample_buffer.take() {
+                Some(byte) => {
+                    self.silence = false;
+                    self.shift_register = byte;
+                }
+                None => self.silence = true,
+            }
+        }
+    }
+
+    /// Current output level, 0-127.
+    pub fn output(&self) -> u8 {
+        self.value
+    }
+}
+
+//~APU~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+#[allow(non_snake_case)]
+pub struct APU {
+    pub pulse1:     Pulse,
+    pub pulse2:     Pulse,
+    pub triangle:   Triangle,
+    pub noise:      Noise,
+    pub dmc:        DMC,
+    /// CPU cycles run since power on.
+    pub cycle:      u64,
+    /// $4017 bit 7: 5-step sequence instead of 4-step.
+    pub five_step:  bool,
+    /// $4017 bit 6: the frame IRQ is never raised.
+    pub irq_inhibit: bool,
+    /// Frame IRQ flag, $4015 bit 6. Cleared by reading $4015.
+    pub frame_irq:  bool,
+    /// CPU cycles into the current frame sequence.
+    pub frame_cycle: u32,
+    /// Mute/solo/volume, applied by output().
+    pub controls:   ChannelControls,
+}
+
+impl APU {
+    pub fn new() -> APU {
+        APU {
+            pulse1:     Pulse::new(1),
+            pulse2:     Pulse::new(2),
+            triangle:   Triangle::default(),
+            noise:      Noise::default(),
+            dmc:        DMC::default(),
+            cycle:      0,
+            five_step:  false,
+            irq_inhibit: false,
+            frame_irq:  false,
+            frame_cycle: 0,
+            controls:   ChannelControls::new(),
+        }
+    }
+
+    /// What the reset line does: the channels are silenced, the frame
+    ///  sequence restarts in the mode $4017 last set, and pending IRQs are
+    ///  dropped. Channel controls are host settings, and are kept.
+    pub fn reset(&mut self) {
+        self.write_control(0);
+        self.dmc.irq_flag = false;
+        self.frame_irq = false;
+        self.frame_cycle = 0;
+        self.triangle.duty_value = 0;
+    }
+
+    /// Runs one CPU cycle worth of APU.
+    pub fn step(&mut self) {
+        self.cycle += 1;
+        self.triangle.step_timer();
+        self.dmc.step_timer();
+        if self.cycle.is_multiple_of(2) {
+            self.pulse1.step_timer();
+            self.pulse2.step_timer();
+            self.noise.step_timer();
+        }
+        self.step_frame_counter();
+    }
+
+    /// Advances the frame sequencer one CPU cycle.
+    fn step_frame_counter(&mut self) {
+        self.frame_cycle += 1;
+        match (self.frame_cycle, self.five_step) {
+            (FRAME_QUARTER_1, _) | (FRAME_QUARTER_3, _) => self.quarter_frame(),
+            (FRAME_HALF_1, _) => self.half_frame(),
+            (FRAME_4_STEP_IRQ, false) => self.raise_frame_irq(),
+            (FRAME_4_STEP_HALF, false) => {
+                self.raise_frame_irq();
+                self.half_frame();
+            }
+            (FRAME_4_STEP_END, false) => {
+                self.raise_frame_irq();
+                self.frame_cycle = 0;
+            }
+            (FRAME_5_STEP_HALF, true) => self.half_frame(),
+            (FRAME_5_STEP_END, true) => self.frame_cycle = 0,
+            _ => {}
+        }
+    }
+
+    /// Clocks envelopes and the triangle's linear counter.
+    fn quarter_frame(&mut self) {
+        self.pulse1.step_envelope();
+        self.pulse2.step_envelope();
+        self.triangle.step_counter();
+        self.noise.step_envelope();
+    }
+
+    /// Clocks everything a quarter frame does, plus length counters and sweeps.
+    fn half_frame(&mut self) {
+        self.quarter_frame();
+        self.pulse1.step_length();
+        self.pulse1.step_sweep();
+        self.pulse2.step_length();
+        self.pulse2.step_sweep();
+        self.triangle.step_length();
+        self.noise.step_length();
+    }
+
+    fn raise_frame_irq(&mut self) {
+        if !self.irq_inhibit {
+            self.frame_irq = true;
+        }
+    }
+
+    /// The mixed level of all five channels, 0.0 to about 1.0, after the
+    ///  channel controls.
+    pub fn output(&self) -> f32 {
+        let outputs = [self.pulse1.output(), self.pulse2.output(), self.triangle.output(),
+                       self.noise.output(), self.dmc.output()];
+        if self.controls.is_default() {
+            return mix(outputs[0], outputs[1], outputs[2], outputs[3], outputs[4]);
+        }
+        let mut gains = [0.0; 5];
+        for (gain, &channel) in gains.iter_mut().zip(Channel::ALL.iter()) {
+            *gain = self.controls.gain(channel);
+        }
+        mix_scaled(outputs, gains)
+    }
+
+    /// Each channel's level as if it were the only one playing, in
+    ///  CHANNEL_NAMES order. For recording channels separately, so the
+    ///  channel controls don't apply.
+    pub fn channel_levels(&self) -> [f32; 5] {
+        [
+            mix(self.pulse1.output(), 0, 0, 0, 0),
+            mix(0, self.pulse2.output(), 0, 0, 0),
+            mix(0, 0, self.triangle.output(), 0, 0),
+            mix(0, 0, 0, self.noise.output(), 0),
+            mix(0, 0, 0, 0, self.dmc.output()),
+        ]
+    }
+
+    /// True while the APU holds the CPU IRQ line low (frame or DMC IRQ).
+    pub fn irq_pending(&self) -> bool {
+        self.frame_irq || self.dmc.irq_flag
+    }
+
+    /// Handles CPU writes to $4000-$4013, $4015 and $4017.
+    pub fn write_register(&mut self, address: u16, val: u8) {
+        match address {
+            0x4000 => self.pulse1.write_control(val),
+            0x4001 => self.pulse1.write_sweep(val),
+            0x4002 => self.pulse1.write_timer_low(val),
+            0x4003 => self.pulse1.write_timer_high(val),
+            0x4004 => self.pulse2.write_control(val),
+            0x4005 => self.pulse2.write_sweep(val),
+            0x4006 => self.pulse2.write_timer_low(val),
+            0x4007 => self.pulse2.write_timer_high(val),
+            0x4008 => self.triangle.write_control(val),
+            0x400A => self.triangle.write_timer_low(val),
+            0x400B => self.triangle.write_timer_high(val),
+            0x400C => self.noise.write_control(val),
+            0x400E => self.noise.write_period(val),
+            0x400F => self.noise.write_length(val),
+            0x4010 => self.dmc.write_control(val),
+            0x4011 => self.dmc.write_value(val),
+            0x4012 => self.dmc.write_address(val),
+            0x4013 => self.dmc.write_length(val),
+            0x4015 => self.write_control(val),
+            0x4017 => self.write_frame_counter(val),
+            _      => {}
+        }
+    }
+
+    /// $4015 (write): ---D NT21, channel enables.
+    fn write_control(&mut self, val: u8) {
+        self.pulse1.set_enabled(val & 0x01 != 0);
+        self.pulse2.set_enabled(val & 0x02 != 0);
+        self.triangle.set_enabled(val & 0x04 != 0);
+        self.noise.set_enabled(val & 0x08 != 0);
+        self.dmc.set_enabled(val & 0x10 != 0);
+    }
+
+    /// $4017 (write): MI-- ----, sequencer mode and IRQ inhibit.
+    ///  The sequence restarts, and 5-step mode clocks a half frame at once.
+    ///  (Hardware delays the restart by 3-4 cycles, we don't.)
+    fn write_frame_counter(&mut self, val: u8) {
+        self.five_step = val & 0x80 != 0;
+        self.irq_inhibit = val & 0x40 != 0;
+        if self.irq_inhibit {
+            self.frame_irq = false;
+        }
+        self.frame_cycle = 0;
+        if self.five_step {
+            self.half_frame();
+        }
+    }
+
+    /// $4015 (read): IF-D NT21, length counter and IRQ status.
+    ///  Reading acknowledges the frame IRQ.
+    pub fn read_status(&mut self) -> u8 {
+        let mut result = 0;
+        if self.pulse1.length_value > 0   { result |= 0x01; }
+        if self.pulse2.length_value > 0   { result |= 0x02; }
+        if self.triangle.length_value > 0 { result |= 0x04; }
+        if self.noise.length_value > 0    { result |= 0x08; }
+        if self.dmc.current_length > 0    { result |= 0x10; }
+        if self.frame_irq                 { result |= 0x40; }
+        if self.dmc.irq_flag              { result |= 0x80; }
+        self.frame_irq = false;
+        result
+    }
+}
+
+impl Default for APU {
+    fn default() -> APU { APU::new() }
+}
+
+#[cfg(test)]
+#[path = "./apu_test.rs"]
+pub mod apu_test;
@@ -0,0 +1,235 @@
+/*  Unit test module of the APU (apu.rs).
+ */
+use crate::core::apu::*;
+
+#[cfg(test)]
+pub mod apu_test {
+    use super::*;
+    use crate::core::memory::MEM;
+
+    #[test]
+    fn test_status_and_length(){
+        let mut apu = APU::new();
+
+        //Length counters only load while the channel is enabled.
+        apu.write_register(0x4003, 0x08);
+        assert_eq!(apu.read_status(), 0);
+
+        apu.write_register(0x4015, 0x0F);
+        apu.write_register(0x4003, 0x08);   //Index 1, 254.
+        apu.write_register(0x400F, 0x00);   //Index 0, 10.
+        assert_eq!(apu.pulse1.length_value, 254);
+        assert_eq!(apu.read_status(), 0x09);
+
+        //Disabling clears the counter.
+        apu.write_register(0x4015, 0x08);
+        assert_eq!(apu.read_status(), 0x08);
+
+        //Halted length counters don't count down.
+        apu.write_register(0x400C, 0x20);
+        apu.noise.step_length();
+        assert_eq!(apu.noise.length_value, 10);
+        apu.write_register(0x400C, 0x00);
+        apu.noise.step_length();
+        assert_eq!(apu.noise.length_value, 9);
+    }
+
+    #[test]
+    fn test_pulse_output(){
+        let mut apu = APU::new();
+        apu.write_register(0x4015, 0x01);
+        apu.write_register(0x4000, 0xBF);   //Duty 2, halt, constant volume 15.
+        apu.write_register(0x4002, 0x10);
+        apu.write_register(0x4003, 0x00);
+
+        //Duty 2 is high for half of the 8 steps.
+        let mut high = 0;
+        for _ in 0..8 {
+            if apu.pulse1.output() == 15 { high += 1; }
+            for _ in 0..(0x11 * 2) { apu.step(); }
+        }
+        assert_eq!(high, 4);
+
+        //Periods under 8 are muted.
+        apu.write_register(0x4002, 0x07);
+        assert_eq!(apu.pulse1.output(), 0);
+    }
+
+    #[test]
+    fn test_envelope_and_sweep(){
+        let mut pulse = Pulse::new(1);
+        pulse.write_control(0x01);  //Envelope period 1, decaying.
+        pulse.step_envelope();
+        assert_eq!(pulse.envelope.volume, 15);
+        pulse.step_envelope();
+        pulse.step_envelope();
+        assert_eq!(pulse.envelope.volume, 14);
+
+        //Pulse 1 negates with one's complement, pulse 2 with two's.
+        pulse.timer_period = 0x100;
+        pulse.write_sweep(0x89);    //Enabled, period 1, negate, shift 1.
+        pulse.step_sweep();
+        assert_eq!(pulse.timer_period, 0x100 - 0x80 - 1);
+
+        let mut pulse = Pulse::new(2);
+        pulse.timer_period = 0x100;
+        pulse.write_sweep(0x89);
+        pulse.step_sweep();
+        assert_eq!(pulse.timer_period, 0x100 - 0x80);
+    }
+
+    #[test]
+    fn test_triangle_linear_counter(){
+        let mut tri = Triangle::default();
+        tri.set_enabled(true);
+        tri.write_control(0x02);
+        tri.write_timer_high(0x08);
+        tri.step_counter();
+        assert_eq!(tri.counter_value, 2);
+        tri.step_counter();
+        tri.step_counter();
+        assert_eq!(tri.counter_value, 0);
+
+        //No linear counter, no stepping.
+        let before = tri.duty_value;
+        for _ in 0..100 { tri.step_timer(); }
+        assert_eq!(tri.duty_value, before);
+    }
+
+    #[test]
+    fn test_noise_lfsr(){
+        //Long mode repeats every 32767 steps, short mode every 93 (or 31).
+        let mut noise = Noise::default();
+        let mut steps = 0;
+        loop {
+            noise.step_timer();
+            steps += 1;
+            if noise.shift_register == 1 { break; }
+        }
+        assert_eq!(steps, 32767);
+
+        noise.write_period(0x80);
+        noise.timer_period = 0;
+        let mut steps = 0;
+        loop {
+            noise.step_timer();
+            steps += 1;
+            if noise.shift_register == 1 { break; }
+        }
+        assert!(steps == 93 || steps == 31, "Short mode period was {}", steps);
+    }
+
+    #[test]
+    fn test_dmc_fetch(){
+        let mut mem = MEM::new_empty();
+        mem.set(0x4010, 0x8F);  //IRQ on, fastest rate.
+        mem.set(0x4012, 0x00);  //$C000
+        mem.set(0x4013, 0x00);  //1 byte.
+        mem.set(0x4015, 0x10);
+        assert_eq!(mem.get(0x4015) & 0x10, 0x10);
+
+        //The fetch happens right away, and stalls the CPU.
+        mem.step_apu();
+        assert_eq!(mem.stall, DMC_STALL_CYCLES);
+        assert_eq!(mem.APU.dmc.current_address, 0xC001);
+        assert_eq!(mem.get(0x4015), 0x80);
+
+        //Writing $4015 acknowledges the IRQ.
+        mem.set(0x4015, 0x00);
+        assert_eq!(mem.get(0x4015), 0x00);
+    }
+
+    #[test]
+    fn test_channel_controls(){
+        let mut controls = ChannelControls::new();
+        assert!(controls.is_default());
+        assert_eq!(Channel::from_name("DMC"), Some(Channel::Dmc));
+        assert_eq!(Channel::from_name("pulse3"), None);
+
+        controls.volume[Channel::Noise as usize] = 0.5;
+        controls.muted[Channel::Pulse1 as usize] = true;
+        assert_eq!(controls.gain(Channel::Noise), 0.5);
+        assert_eq!(controls.gain(Channel::Pulse1), 0.0);
+        assert_eq!(controls.gain(Channel::Expansion), 1.0);
+
+        //Solo wins over mute, and silences everything else.
+        controls.solo[Channel::Pulse1 as usize] = true;
+        assert_eq!(controls.gain(Channel::Pulse1), 1.0);
+        assert_eq!(controls.gain(Channel::Noise), 0.0);
+
+        //Through the APU.
+        let mut apu = APU::new();
+        apu.write_register(0x4015, 0x01);
+        apu.write_register(0x4000, 0xBF);
+        apu.write_register(0x4002, 0x10);
+        apu.write_register(0x4003, 0x00);
+        for _ in 0..(0x11 * 2 * 2) { apu.step(); }    //Into the high part of duty 2.
+        let full = apu.output();
+        assert!(full > 0.0);
+        apu.controls.volume[0] = 0.5;
+        assert!(apu.output() > 0.0 && apu.output() < full);
+        apu.controls.solo[Channel::Noise as usize] = true;
+        assert_eq!(apu.output(), 0.0);
+    }
+
+    #[test]
+    fn test_frame_irq(){
+        let mut mem = MEM::new_empty();
+        mem.set(0x4017, 0x00);      //4-step, IRQ allowed.
+        for _ in 0..FRAME_4_STEP_IRQ - 1 { mem.step_apu(); }
+        assert!(!mem.APU.irq_pending());
+        mem.step_apu();
+        assert!(mem.APU.irq_pending());
+
+        //Reading $4015 returns the flag, then acknowledges it.
+        assert_eq!(mem.get(0x4015) & 0x40, 0x40);
+        assert_eq!(mem.get(0x4015) & 0x40, 0x00);
+
+        //It's set again on the last two cycles of the sequence.
+        mem.step_apu();
+        assert!(mem.APU.frame_irq);
+        mem.get(0x4015);
+        mem.step_apu();
+        assert!(mem.APU.frame_irq);
+        assert_eq!(mem.APU.frame_cycle, 0);
+
+        //Setting the inhibit flag clears it, and no more are raised.
+        mem.set(0x4017, 0x40);
+        assert!(!mem.APU.frame_irq);
+        for _ in 0..FRAME_4_STEP_END * 2 { mem.step_apu(); }
+        assert!(!mem.APU.frame_irq);
+
+        //Neither does the 5-step sequence.
+        mem.set(0x4017, 0x80);
+        for _ in 0..FRAME_5_STEP_END * 2 { mem.step_apu(); }
+        assert!(!mem.APU.frame_irq);
+    }
+
+    #[test]
+    fn test_frame_sequencer(){
+        let mut apu = APU::new();
+        apu.write_register(0x4015, 0x01);
+        apu.write_register(0x4000, 0x00);   //Length counter running.
+        apu.write_register(0x4003, 0x18);   //Index 3, 2.
+        apu.write_register(0x4017, 0x00);
+
+        //4-step: half frames at the second and fourth steps.
+        for _ in 0..FRAME_HALF_1 - 1 { apu.step(); }
+        assert_eq!(apu.pulse1.length_value, 2);
+        apu.step();
+        assert_eq!(apu.pulse1.length_value, 1);
+        for _ in FRAME_HALF_1..FRAME_4_STEP_HALF { apu.step(); }
+        assert_eq!(apu.pulse1.length_value, 0);
+        assert_eq!(apu.read_status() & 0x01, 0);
+
+        //5-step: writing $4017 clocks a half frame right away.
+        apu.write_register(0x4003, 0x18);
+        apu.write_register(0x4017, 0x80);
+        assert_eq!(apu.pulse1.length_value, 1);
+        for _ in 0..FRAME_4_STEP_HALF { apu.step(); }
+        assert_eq!(apu.pulse1.length_value, 0);
+        assert_eq!(apu.frame_cycle, FRAME_4_STEP_HALF);
+        for _ in FRAME_4_STEP_HALF..FRAME_5_STEP_END { apu.step(); }
+        assert_eq!(apu.frame_cycle, 0);
+    }
+}
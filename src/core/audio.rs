@@ -0,0 +1,338 @@
+/* Audio output pipeline.
+ * Turns the APU channel levels into host audio:
+ *  - The nonlinear mixer (nesdev "APU Mixer", lookup table version).
+ *  - A band-limited resampler from the CPU clock (~1.79 MHz) down to the
+ *    host rate. Like blargg's blip_buf, only changes in level are recorded,
+ *    each as a windowed sinc step, so the cost is per change, not per cycle.
+ *  - The output stage of the NES: two high-pass filters (90 Hz, 440 Hz)
+ *    that also block DC, and a 14 kHz low-pass.
+ *  - A lock-free single producer, single consumer ring buffer that a
+ *    frontend (or a test) drains sample blocks from.
+ */
+
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// NTSC CPU clock, which the APU runs at.
+pub const CPU_CLOCK_NTSC: f64 = 1_789_773.0;
+/// Default host sample rate.
+pub const DEFAULT_SAMPLE_RATE: u32 = 44_100;
+/// Default ring buffer size, about 370ms at 44.1 kHz.
+pub const DEFAULT_RING_CAPACITY: usize = 16_384;
+
+//~MIXER~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+/// pulse_out = 95.52 / (8128 / (pulse1 + pulse2) + 100)
+pub static PULSE_TABLE: [f32; 31] = pulse_table();
+/// tnd_out = 163.67 / (24329 / (3 * triangle + 2 * noise + dmc) + 100)
+pub static TND_TABLE: [f32; 203] = tnd_table();
+
+const fn pulse_table() -> [f32; 31] {
+    let mut table = [0f32; 31];
+    let mut n = 1;
+    while n < 31 {
+        table[n] = 95.52 / (8128.0 / n as f32 + 100.0);
+        n += 1;
+    }
+    table
+}
+
+const fn tnd_table() -> [f32; 203] {
+    let mut table = [0f32; 203];
+    let mut n = 1;
+    while n < 203 {
+        table[n] = 163.67 / (24329.0 / n as f32 + 100.0);
+        n += 1;
+    }
+    table
+}
+
+/// Mixes raw channel outputs (pulses and noise 0-15, triangle 0-15,
+///  DMC 0-127) into a level from 0.0 to about 1.0.
+pub fn mix(pulse1: u8, pulse2: u8, triangle: u8, noise: u8, dmc: u8) -> f32 {
+    let pulse = PULSE_TABLE[(pulse1 + pulse2) as usize];
+    let tnd = TND_TABLE[3 * triangle as usize + 2 * noise as usize + dmc as usize];
+    pulse + tnd
+}
+
+/// mix() with each channel scaled by a gain, in the order pulse1, pulse2,
+///  triangle, noise, DMC. Uses the same formulas the tables are built
+///  from, so a gain of 1.0 everywhere matches mix() exactly.
+pub fn mix_scaled(outputs: [u8; 5], gains: [f32; 5]) -> f32 {
+    let mut scaled = [0f32; 5];
+    for (s, (&o, &g)) in scaled.iter_mut().zip(outputs.iter().zip(gains.iter())) {
+        *s = o as f32 * g;
+    }
+    let pulse = scaled[0] + scaled[1];
+    let tnd = 3.0 * scaled[2] + 2.0 * scaled[3] + scaled[4];
+    let pulse_out = if pulse <= 0.0 { 0.0 } else { 95.52 / (8128.0 / pulse + 100.0) };
+    let tnd_out = if tnd <= 0.0 { 0.0 } else { 163.67 / (24329.0 / tnd + 100.0) };
+    pulse_out + tnd_out
+}
+
+//~RESAMPLER~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+/// Kernel length in output samples, and how many sub-sample positions a
+///  step can start at.
+const KERNEL_WIDTH: usize = 16;
+const KERNEL_PHASES: usize = 64;
+/// Cutoff as a fraction of the output rate, a little under Nyquist.
+const KERNEL_CUTOFF: f64 = 0.45;
+
+/// Band-limited resampler. Feed it one level per input clock with push(),
+///  finished output samples come back out of it, delayed by half a kernel.
+pub struct Resampler {
+    /// Output samples per input clock.
+    ratio:      f64,
+    /// Position of the next input clock, in output samples past buf[0].
+    ///  Always in [0, 1).
+    time:       f64,
+    last:       f32,
+    /// Running sum of the deltas, i.e. the current output level.
+    level:      f32,
+    /// Pending deltas, buf[0] is the next output sample.
+    buf:        VecDeque<f32>,
+    kernel:     Vec<[f32; KERNEL_WIDTH]>,
+}
+
+impl Resampler {
+    pub fn new(input_rate: f64, output_rate: u32) -> Resampler {
+        Resampler {
+            ratio:  output_rate as f64 / input_rate,
+            time:   0.0,
+            last:   0.0,
+            level:  0.0,
+            buf:    std::iter::repeat_n(0.0, KERNEL_WIDTH + 1).collect(),
+            kernel: build_kernel(),
+        }
+    }
+
+    /// Adds one input clock at the given level. Returns a finished output
+    ///  sample when one is ready (at most one, the ratio is always < 1).
+    pub fn push(&mut self, level: f32) -> Option<f32> {
+        let delta = level - self.last;
+        if delta != 0.0 {
+            self.last = level;
+            let phase = (self.time * KERNEL_PHASES as f64) as usize;
+            for (slot, k) in self.buf.iter_mut().zip(self.kernel[phase].iter()) {
+                *slot += delta * k;
+            }
+        }
+
+        self.time += self.ratio;
+        if self.time < 1.0 {
+            return None;
+        }
+        self.time -= 1.0;
+        self.level += self.buf.pop_front().unwrap_or(0.0);
+        self.buf.push_back(0.0);
+        Some(self.level)
+    }
+}
+
+/// One impulse per phase, each a Blackman windowed sinc shifted by
+///  phase / KERNEL_PHASES of a sample, and normalized to sum to 1 so that
+///  steps land at exactly the right level.
+fn build_kernel() -> Vec<[f32; KERNEL_WIDTH]> {
+    let half = (KERNEL_WIDTH / 2) as f64;
+    (0..KERNEL_PHASES).map(|phase| {
+        let offset = phase as f64 / KERNEL_PHASES as f64;
+        let mut taps = [0f64; KERNEL_WIDTH];
+        for (k, tap) in taps.iter_mut().enumerate() {
+            //x runs from -half to +half across the kernel.
+            let x = k as f64 - offset - (half - 1.0);
+            let sinc = if x == 0.0 { 1.0 }
+                       else { (2.0 * PI * KERNEL_CUTOFF * x).sin() / (2.0 * PI * KERNEL_CUTOFF * x) };
+            let w = (x + half) / KERNEL_WIDTH as f64;
+            let window = 0.42 - 0.5 * (2.0 * PI * w).cos() + 0.08 * (4.0 * PI * w).cos();
+            *tap = sinc * window;
+        }
+        let sum: f64 = taps.iter().sum();
+        let mut out = [0f32; KERNEL_WIDTH];
+        for (o, t) in out.iter_mut().zip(taps.iter()) {
+            *o = (t / sum) as f32;
+        }
+        out
+    }).collect()
+}
+
+//~FILTERS~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilterKind {
+    HighPass,
+    LowPass,
+}
+
+/// First order RC filter.
+#[derive(Debug, Clone)]
+pub struct Filter {
+    pub kind:   FilterKind,
+    alpha:      f32,
+    prev_in:    f32,
+    prev_out:   f32,
+}
+
+impl Filter {
+    pub fn new(kind: FilterKind, sample_rate: u32, cutoff: f32) -> Filter {
+        let rc = 1.0 / (2.0 * std::f32::consts::PI * cutoff);
+        let dt = 1.0 / sample_rate as f32;
+        let alpha = match kind {
+            FilterKind::HighPass => rc / (rc + dt),
+            FilterKind::LowPass  => dt / (rc + dt),
+        };
+        Filter { kind, alpha, prev_in: 0.0, prev_out: 0.0 }
+    }
+
+    pub fn high_pass(sample_rate: u32, cutoff: f32) -> Filter {
+        Filter::new(FilterKind::HighPass, sample_rate, cutoff)
+    }
+
+    pub fn low_pass(sample_rate: u32, cutoff: f32) -> Filter {
+        Filter::new(FilterKind::LowPass, sample_rate, cutoff)
+    }
+
+    pub fn step(&mut self, x: f32) -> f32 {
+        let y = match self.kind {
+            FilterKind::HighPass => self.alpha * (self.prev_out + x - self.prev_in),
+            FilterKind::LowPass  => self.prev_out + self.alpha * (x - self.prev_out),
+        };
+        self.prev_in = x;
+        self.prev_out = y;
+        y
+    }
+}
+
+/// The filters of the NES (front loader) output stage.
+pub fn nes_filters(sample_rate: u32) -> Vec<Filter> {
+    vec![
+        Filter::high_pass(sample_rate, 90.0),
+        Filter::high_pass(sample_rate, 440.0),
+        Filter::low_pass(sample_rate, 14_000.0),
+    ]
+}
+
+//~RING BUFFER~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+/// Lock-free ring buffer of samples, for one producer (the emulator) and
+///  one consumer (the audio callback). Samples are stored as f32 bits in
+///  atomics, so it needs no unsafe code. When full, new samples are dropped.
+pub struct RingBuffer {
+    buf:    Box<[AtomicU32]>,
+    /// Next slot to read, only moved by the consumer.
+    head:   AtomicUsize,
+    /// Next slot to write, only moved by the producer.
+    tail:   AtomicUsize,
+}
+
+impl RingBuffer {
+    pub fn new(capacity: usize) -> RingBuffer {
+        //One slot stays empty, to tell full from empty.
+        RingBuffer {
+            buf:    (0..capacity + 1).map(|_| AtomicU32::new(0)).collect(),
+            head:   AtomicUsize::new(0),
+            tail:   AtomicUsize::new(0),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buf.len() - 1
+    }
+
+    /// Samples waiting to be read.
+    pub fn len(&self) -> usize {
+        let head = self.head.load(Ordering::Acquire);
+        let tail = self.tail.load(Ordering::Acquire);
+        (tail + self.buf.len() - head) % self.buf.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Producer side. Returns false (and drops the sample) when full.
+    pub fn push(&self, sample: f32) -> bool {
+        let tail = self.tail.load(Ordering::Relaxed);
+        let next = (tail + 1) % self.buf.len();
+        if next == self.head.load(Ordering::Acquire) {
+            return false;
+        }
+        self.buf[tail].store(sample.to_bits(), Ordering::Relaxed);
+        self.tail.store(next, Ordering::Release);
+        true
+    }
+
+    /// Consumer side. Fills as much of out as it can, returns the count.
+    pub fn pop_into(&self, out: &mut [f32]) -> usize {
+        let mut head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Acquire);
+        let mut count = 0;
+        while head != tail && count < out.len() {
+            out[count] = f32::from_bits(self.buf[head].load(Ordering::Relaxed));
+            head = (head + 1) % self.buf.len();
+            count += 1;
+        }
+        self.head.store(head, Ordering::Release);
+        count
+    }
+
+    /// Consumer side. Takes everything that's waiting.
+    pub fn drain(&self) -> Vec<f32> {
+        let mut out = vec![0.0; self.len()];
+        let count = self.pop_into(&mut out);
+        out.truncate(count);
+        out
+    }
+}
+
+//~AUDIO~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+/// The whole pipeline: push mixer levels in at the CPU clock, read
+///  filtered samples at the host rate out of ring().
+pub struct Audio {
+    sample_rate:    u32,
+    resampler:      Resampler,
+    filters:        Vec<Filter>,
+    ring:           Arc<RingBuffer>,
+}
+
+impl Audio {
+    pub fn new(sample_rate: u32) -> Audio {
+        Audio::with_capacity(sample_rate, DEFAULT_RING_CAPACITY)
+    }
+
+    pub fn with_capacity(sample_rate: u32, capacity: usize) -> Audio {
+        Audio {
+            sample_rate,
+            resampler:  Resampler::new(CPU_CLOCK_NTSC, sample_rate),
+            filters:    nes_filters(sample_rate),
+            ring:       Arc::new(RingBuffer::new(capacity)),
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// The ring buffer output goes to. Clone the Arc to hand it to an
+    ///  audio thread.
+    pub fn ring(&self) -> Arc<RingBuffer> {
+        Arc::clone(&self.ring)
+    }
+
+    /// Adds one CPU cycle at the given mixer level. Returns the filtered
+    ///  sample when one is finished (it's also queued in the ring).
+    pub fn push(&mut self, level: f32) -> Option<f32> {
+        let mut sample = self.resampler.push(level)?;
+        for filter in &mut self.filters {
+            sample = filter.step(sample);
+        }
+        self.ring.push(sample);
+        Some(sample)
+    }
+}
+
+impl Default for Audio {
+    fn default() -> Audio { Audio::new(DEFAULT_SAMPLE_RATE) }
+}
+
+#[cfg(test)]
+#[path = "./audio_test.rs"]
+pub mod audio_test;
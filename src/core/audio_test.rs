@@ -0,0 +1,114 @@
+/*  Unit test module of the audio pipeline (audio.rs).
+ */
+use crate::core::audio::*;
+
+#[cfg(test)]
+pub mod audio_test {
+    use super::*;
+
+    #[test]
+    fn test_mixer_tables(){
+        assert_eq!(mix(0, 0, 0, 0, 0), 0.0);
+        //Values from nesdev's linear approximation check.
+        assert!((PULSE_TABLE[30] - 0.2585).abs() < 0.001);
+        assert!((TND_TABLE[202] - 0.7418).abs() < 0.001);
+        //It's nonlinear: two pulses at 15 are less than twice one.
+        assert!(PULSE_TABLE[30] < 2.0 * PULSE_TABLE[15]);
+        assert!(mix(15, 15, 15, 15, 127) < 1.0);
+
+        //Unit gains match the tables, zero gains are silent.
+        let outputs = [7, 12, 15, 3, 90];
+        let plain = mix(7, 12, 15, 3, 90);
+        assert!((mix_scaled(outputs, [1.0; 5]) - plain).abs() < 1e-6);
+        assert_eq!(mix_scaled(outputs, [0.0; 5]), 0.0);
+        assert!(mix_scaled(outputs, [0.5; 5]) < plain);
+    }
+
+    #[test]
+    fn test_resampler_rate_and_step(){
+        let mut resampler = Resampler::new(CPU_CLOCK_NTSC, 44_100);
+        let mut out = Vec::new();
+        for _ in 0..CPU_CLOCK_NTSC as usize {
+            if let Some(s) = resampler.push(0.5) { out.push(s); }
+        }
+        //One second in, one second out (give or take the last sample).
+        assert!((out.len() as i64 - 44_100).abs() <= 1, "Got {} samples", out.len());
+        //A step settles at exactly the new level.
+        assert!((out[out.len() - 1] - 0.5).abs() < 1e-5);
+        //The start of the step only rings a little before it.
+        assert!(out[0].abs() < 0.01);
+    }
+
+    #[test]
+    fn test_resampler_band_limit(){
+        //A square wave far above the output Nyquist should come out quiet.
+        let mut resampler = Resampler::new(CPU_CLOCK_NTSC, 44_100);
+        let mut peak: f32 = 0.0;
+        for cycle in 0..200_000 {
+            let level = if (cycle / 20) % 2 == 0 { 1.0 } else { 0.0 };
+            if let Some(s) = resampler.push(level) {
+                if cycle > 10_000 { peak = peak.max((s - 0.5).abs()); }
+            }
+        }
+        assert!(peak < 0.1, "Peak was {}", peak);
+    }
+
+    #[test]
+    fn test_filters_block_dc(){
+        let mut filters = nes_filters(44_100);
+        let mut y = 0.0;
+        for _ in 0..44_100 {
+            y = filters.iter_mut().fold(1.0, |s, f| f.step(s));
+        }
+        assert!(y.abs() < 1e-3);
+
+        let mut low = Filter::low_pass(44_100, 14_000.0);
+        for _ in 0..100 { y = low.step(1.0); }
+        assert!((y - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_ring_buffer(){
+        let ring = RingBuffer::new(4);
+        assert!(ring.is_empty());
+        for i in 0..4 { assert!(ring.push(i as f32)); }
+        assert!(!ring.push(4.0));
+        assert_eq!(ring.len(), 4);
+
+        let mut block = [0.0; 3];
+        assert_eq!(ring.pop_into(&mut block), 3);
+        assert_eq!(block, [0.0, 1.0, 2.0]);
+        assert!(ring.push(5.0));
+        assert_eq!(ring.drain(), vec![3.0, 5.0]);
+
+        //Across threads.
+        let ring = std::sync::Arc::new(RingBuffer::new(64));
+        let producer = std::sync::Arc::clone(&ring);
+        let handle = std::thread::spawn(move || {
+            let mut i = 0;
+            while i < 1000 {
+                if producer.push(i as f32) { i += 1; }
+            }
+        });
+        let mut next = 0;
+        while next < 1000 {
+            for s in ring.drain() {
+                assert_eq!(s, next as f32);
+                next += 1;
+            }
+        }
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_audio_pipeline(){
+        let mut audio = Audio::with_capacity(48_000, 1024);
+        let ring = audio.ring();
+        for cycle in 0..100_000 {
+            audio.push(if (cycle / 2000) % 2 == 0 { 0.3 } else { 0.0 });
+        }
+        assert_eq!(audio.sample_rate(), 48_000);
+        assert_eq!(ring.len(), 1024);
+        assert!(ring.drain().iter().any(|s| s.abs() > 0.01));
+    }
+}
@@ -1,18 +1,69 @@
 //extern crate memmap;
 //use self::memmap::Mmap;
 
-use std::fs::File;
+use std::fs;
 use std::io;
-use std::io::BufReader;
 use std::io::prelude::*;
 use std::fmt;               //Implementing fmt::Debug.
+use std::error::Error;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::core::header::{ConsoleType, Header, HeaderFormat, HEADER_SIZE, TRAINER_SIZE};
+use crate::core::romdb::RomDb;
 
 pub use ::log::*;
 
+/// Why a ROM couldn't be loaded.
+#[derive(Debug)]
+pub enum LoadError {
+    /// No "NES" $1A at the start of the file.
+    NotINes,
+    /// The header is there, but makes no sense.
+    BadHeader(String),
+    /// The file ends before all of the PRG-ROM (or trainer) the header
+    ///  asks for. Sizes are in bytes.
+    TruncatedPrg { expected: usize, found: usize },
+    TruncatedChr { expected: usize, found: usize },
+    UnsupportedMapper(u16),
+    Io(io::Error),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::NotINes => write!(f, "not an iNES ROM"),
+            LoadError::BadHeader(why) => write!(f, "bad header: {}", why),
+            LoadError::TruncatedPrg { expected, found } =>
+                write!(f, "PRG-ROM is truncated, {} of {} bytes", found, expected),
+            LoadError::TruncatedChr { expected, found } =>
+                write!(f, "CHR-ROM is truncated, {} of {} bytes", found, expected),
+            LoadError::UnsupportedMapper(n) => write!(f, "mapper {} isn't supported", n),
+            LoadError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for LoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LoadError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(e: io::Error) -> LoadError {
+        LoadError::Io(e)
+    }
+}
+
 #[allow(non_snake_case)]
 
 pub struct CART {
-    pub filename: &'static str, 
+    pub filename: String,   //Empty for carts that didn't come from a file.
     pub ines_fmt: bool, //True if ROM is in iNES format.
     pub nes2_fmt: bool, //True if ROM is NES 2.0 format.
     pub section_sizes: [u32;5], // Differing sizes of certain registers:
@@ -22,90 +73,292 @@ pub struct CART {
                                 // [3] : INST_ROM, either 0 or 8192.
                                 // [4] : PROM, either 0 or 32.
     pub HEAD: [u8;16],
+    /// HEAD, parsed.
+    pub header: Header,
     pub TRAIN: [u8;512],
     pub PRG: Vec<u8>,
+    /// CHR-ROM, or CHR-RAM on boards without any (see chr_ram).
     pub CHR: Vec<u8>,
+    pub chr_ram: bool,
+    /// PRG-RAM and PRG-NVRAM, as much as the header asks for, in that order.
+    pub PRG_RAM: Vec<u8>,
     pub INST_ROM: [u8;8192],
     pub PROM: [u8;32],
+    /// Where bad headers get corrected from. None leaves headers alone.
+    pub database: Option<Arc<RomDb>>,
+    /// True if the database changed the header.
+    pub db_corrected: bool,
 }
 
 impl CART {
-    pub fn new(file_n: &'static str) -> CART {
+    pub fn new(file_n: &str) -> CART {
     	CART {
-            filename:   file_n,
+            filename:   file_n.to_string(),
             ines_fmt: false,
             nes2_fmt: false,
             section_sizes: [0;5], 
             HEAD: [0;16],
+            header: Header::default(),
             TRAIN: [0;512],
             PRG:  Vec::new(), //Possibly make a Box<[T]>
             CHR: Vec::new(),
+            chr_ram: false,
+            PRG_RAM: Vec::new(),
             INST_ROM: [0;8192],
             PROM: [0;32],
+            database: Some(RomDb::builtin()),
+            db_corrected: false,
         }
     }
-    ///TODO: Differentiate between iNES and NES2.0
-    pub fn read_cart(&mut self) -> io::Result<()> { 
-        let mut f = File::open(self.filename.to_string())?;
-        let mut reader = BufReader::new(f);
+    /// Opens and reads a ROM file.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<CART, LoadError> {
+        let mut cart = CART::new(&path.as_ref().to_string_lossy());
+        cart.read_cart()?;
+        Ok(cart)
+    }
 
-        //Store and read header.
-        reader.read(&mut self.HEAD)?; // I think.. this works... maybe?
-        self.section_sizes[1] = self.HEAD[4] as u32; //This is in 16kb units!
-        self.section_sizes[2] = self.HEAD[5] as u32; //This is in 8kb units!
-        self.section_sizes[0] = if self.HEAD[6] & 0b100 == 0b100 {512} else {0};
+    /// Reads a ROM image that's already in memory: a &[u8], a Vec<u8>, or
+    ///  include_bytes!("game.nes").
+    pub fn from_bytes<B: AsRef<[u8]>>(bytes: B) -> Result<CART, LoadError> {
+        let mut cart = CART::new("");
+        cart.load_bytes(bytes.as_ref())?;
+        Ok(cart)
+    }
 
-        //Check for iNES format identifier. 
-        if self.HEAD[0]==0x4E && self.HEAD[1]==0x45 
-            && self.HEAD[2]==0x53 && self.HEAD[3]==0x1A {
-                self.ines_fmt = true;
-        } 
-        //Check for NES 2.0 format identifier.
-        if self.ines_fmt && ((self.HEAD[7] & 0x0C) == 0x08) {
-            self.nes2_fmt = true;
-        }
-        //Snippet from nesdev -> NES_2.0#Identification
+    /// Reads a ROM image from anything readable, e.g. a file in an archive.
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<CART, LoadError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        CART::from_bytes(bytes)
+    }
+
+    pub fn read_cart(&mut self) -> Result<(), LoadError> { 
+        let bytes = fs::read(&self.filename)?;
+        self.load_bytes(&bytes)
+    }
 
-        //Handle NES 2.0 format.
-        if self.ines_fmt && self.nes2_fmt { 
-            panic!("NES 2.0 not currently supported.");
+    /// Fills the cart from a whole ROM image. from_bytes() does this on a
+    ///  new CART, call it directly to change the database first.
+    pub fn load_bytes(&mut self, bytes: &[u8]) -> Result<(), LoadError> {
+        //Store and read header.
+        if bytes.len() < 4 || &bytes[0..4] != b"NES\x1A" {
+            return Err(LoadError::NotINes);
+        }
+        if bytes.len() < HEADER_SIZE {
+            return Err(LoadError::BadHeader(format!("the file ends {} bytes into the header", bytes.len())));
+        }
+        self.HEAD.copy_from_slice(&bytes[..HEADER_SIZE]);
+        self.header = Header::parse(&self.HEAD).map_err(LoadError::BadHeader)?;
+        if self.header.format == HeaderFormat::Archaic {
+            warn!("CART     -> Old or dirty iNES header ({:?}), using mapper {}.",
+                  String::from_utf8_lossy(&self.HEAD[7..]), self.header.mapper);
         }
+        self.correct_header(bytes);
+        self.ines_fmt = true;
+        self.nes2_fmt = self.header.is_nes2();
+        //NES 2.0 sizes don't have to be whole units, so round up.
+        self.section_sizes[1] = self.header.prg_rom_size.div_ceil(0x4000) as u32; //This is in 16kb units!
+        self.section_sizes[2] = self.header.chr_rom_size.div_ceil(0x2000) as u32; //This is in 8kb units!
+        self.section_sizes[0] = if self.header.trainer {512} else {0};
 
-        //Fill trainer if it exists.
-        if self.section_sizes[0] > 0 {
-            reader.read(&mut self.TRAIN)?;
+        //Fill trainer if it exists. A file that stops in it has no PRG.
+        let mut offset = HEADER_SIZE;
+        let expected = self.header.prg_rom_size;
+        if self.header.trainer {
+            let train = section(bytes, offset, TRAINER_SIZE);
+            if train.len() < TRAINER_SIZE {
+                return Err(LoadError::TruncatedPrg { expected, found: 0 });
+            }
+            self.TRAIN.copy_from_slice(train);
+            offset += TRAINER_SIZE;
         }
         
         //Filling variable length PRG
-        let mut i = 0;
-        let s = self.section_sizes[1]*16384; 
-        for byte in reader.by_ref().bytes() {
-            self.PRG.push(byte.unwrap());
-
-            if i >= s { break;}
-            i += 1;
+        if expected == 0 {
+            return Err(LoadError::BadHeader("there's no PRG-ROM".to_string()));
+        }
+        self.PRG = section(bytes, offset, expected).to_vec();
+        if self.PRG.len() < expected {
+            return Err(LoadError::TruncatedPrg { expected, found: self.PRG.len() });
         }
+        offset += expected;
 
         //Filling variable length CHR 
-        let mut i = 0;
-        let s = self.section_sizes[2]*8192; 
-        for byte in reader.by_ref().bytes() {
-            self.CHR.push(byte.unwrap());
+        let expected = self.header.chr_rom_size;
+        self.CHR = section(bytes, offset, expected).to_vec();
+        if self.CHR.len() < expected {
+            return Err(LoadError::TruncatedChr { expected, found: self.CHR.len() });
+        }
+        offset += expected;
+
+        //Boards without CHR-ROM have CHR-RAM instead, 8kb unless NES 2.0
+        // says otherwise.
+        self.chr_ram = expected == 0;
+        if self.chr_ram {
+            let size = self.header.chr_ram_size + self.header.chr_nvram_size;
+            self.CHR = vec![0; if size == 0 { 0x2000 } else { size }];
+        }
+        self.PRG_RAM = vec![0; self.header.prg_ram_size + self.header.prg_nvram_size];
+        //Trainers run from $7000, so there has to be RAM there.
+        if self.header.trainer && self.PRG_RAM.len() < 0x2000 {
+            self.PRG_RAM.resize(0x2000, 0);
+        }
+        self.copy_trainer();
 
-            if i >= s { break;}
-            i += 1;
+        //PlayChoice-10 dumps have the instruction screen's INST-ROM after
+        // CHR, then the PROM: 16 bytes of security data and 16 of CounterOut.
+        if self.header.console == ConsoleType::Playchoice10 {
+            let inst = section(bytes, offset, self.INST_ROM.len());
+            if inst.len() == self.INST_ROM.len() {
+                self.INST_ROM.copy_from_slice(inst);
+                self.section_sizes[3] = inst.len() as u32;
+                offset += inst.len();
+                //Some dumps leave out CounterOut.
+                let prom = section(bytes, offset, self.PROM.len());
+                if prom.len() >= 16 {
+                    self.PROM[..prom.len()].copy_from_slice(prom);
+                    self.section_sizes[4] = prom.len() as u32;
+                    offset += prom.len();
+                }
+            }
+            else {
+                warn!("CART     -> PlayChoice-10 ROM without an INST-ROM.");
+            }
         }
 
-        //INST-ROM, when I need it
-        //PROM, when I need it
         //Name bytes if I need it.
+        if offset < bytes.len() {
+            debug!("CART     -> {} bytes after CHR-ROM.", bytes.len() - offset);
+        }
         Ok(())
     }
 
-    pub fn fill_banks(){
+    /// Looks the ROM up in the database, and fixes the header if it's
+    ///  there. Tries PRG and CHR as sized by the header first, then
+    ///  everything after it, in case the header has the sizes wrong.
+    fn correct_header(&mut self, bytes: &[u8]) {
+        let db = match &self.database {
+            Some(db) if !db.is_empty() => db.clone(),
+            _ => return,
+        };
+        let offset = self.header.prg_offset();
+        let sized = section(bytes, offset, self.header.prg_rom_size + self.header.chr_rom_size);
+        let rest = section(bytes, offset, bytes.len());
+        if let Some(entry) = db.find(sized).or_else(|| db.find(rest)) {
+            let before = self.header.clone();
+            entry.apply(&mut self.header);
+            self.db_corrected = self.header != before;
+            if self.db_corrected {
+                info!("CART     -> Header corrected from the ROM database: mapper {}.{}, {:?}.",
+                      self.header.mapper, self.header.submapper, self.header.mirroring);
+            }
+        }
+    }
+
+    /// Reads PRG-RAM at $6000-$7FFF, mirrored when there's less than 8kb.
+    ///  Boards without any read as 0.
+    pub fn read_prg_ram(&self, address: u16) -> u8 {
+        if self.PRG_RAM.is_empty() {
+            return 0;
+        }
+        self.PRG_RAM[(address as usize - 0x6000) % self.PRG_RAM.len()]
+    }
+
+    pub fn write_prg_ram(&mut self, address: u16, val: u8) {
+        let len = self.PRG_RAM.len();
+        if len > 0 {
+            self.PRG_RAM[(address as usize - 0x6000) % len] = val;
+        }
+    }
+
+    /// Reads $6000-$FFFF the way a board without bank switching wires it:
+    ///  PRG-RAM, then the first 32kb of PRG (16kb is mirrored at $C000).
+    ///  Nothing answers below $6000.
+    pub fn read_unbanked(&self, address: u16) -> u8 {
+        // -- Check flags 6, bit 1 if PRG RAM Exists
+        // ---- If so, check size with flags 8.
+        // ---- Load $6000-7FFF with RAM data.
+        // -- Check flags 4, for PRG ROM size (in 16 kb units)
+        // ---- Load $8000-$BFFF with ROM.
+        // ---- If ROM size is 256, load $C000-$FFFF with last half of ROM
+        // ---- If ROM size is 128, load $C000-$FFFF with ROM.
+        if address < 0x6000 {
+            0
+        }
+        else if address < 0x8000 {
+            self.read_prg_ram(address)
+        }
+        else if self.PRG.len() > 16384 {
+            self.PRG[(address & 0x7FFF) as usize % self.PRG.len()]
+        }
+        else {
+            *self.PRG.get((address & 0x3FFF) as usize).unwrap_or(&0)
+        }
+    }
+
+    /// Writes CHR-RAM. CHR-ROM ignores writes.
+    pub fn write_chr_ram(&mut self, address: u16, val: u8) {
+        if self.chr_ram {
+            let len = self.CHR.len();
+            self.CHR[address as usize % len] = val;
+        }
+    }
 
+    /// Power on state: RAM without a battery comes up cleared (CHR-RAM
+    ///  too), and the trainer is copied in.
+    pub fn power_on(&mut self) {
+        let battery = self.battery_range();
+        for (i, b) in self.PRG_RAM.iter_mut().enumerate() {
+            if !battery.contains(&i) {
+                *b = 0;
+            }
+        }
+        if self.chr_ram {
+            self.CHR.iter_mut().for_each(|b| *b = 0);
+        }
+        self.copy_trainer();
+    }
+
+    /// Copies the trainer into PRG-RAM at $7000-$71FF, as the copiers these
+    ///  dumps came from did at power on. Does nothing without one.
+    pub fn copy_trainer(&mut self) {
+        if self.header.trainer {
+            let train = self.TRAIN;
+            for (i, &b) in train.iter().enumerate() {
+                self.write_prg_ram(0x7000 + i as u16, b);
+            }
+        }
+    }
+
+    /// The PRG-RAM that keeps its contents with the power off, for .sav
+    ///  files. None unless the header says there's a battery.
+    pub fn battery_ram(&self) -> Option<&[u8]> {
+        let range = self.battery_range();
+        if range.is_empty() { None } else { Some(&self.PRG_RAM[range]) }
+    }
+
+    pub fn battery_ram_mut(&mut self) -> Option<&mut [u8]> {
+        let range = self.battery_range();
+        if range.is_empty() { None } else { Some(&mut self.PRG_RAM[range]) }
+    }
+
+    /// Where battery_ram() is in PRG_RAM: the PRG-NVRAM, after the
+    ///  volatile PRG-RAM.
+    fn battery_range(&self) -> Range<usize> {
+        if !self.header.battery {
+            return 0..0;
+        }
+        let len = self.PRG_RAM.len();
+        let start = self.header.prg_ram_size.min(len);
+        start..(start + self.header.prg_nvram_size).min(len)
     }
 }
+
+/// Up to len bytes from offset, less if the image ends first.
+fn section(bytes: &[u8], offset: usize, len: usize) -> &[u8] {
+    let start = offset.min(bytes.len());
+    &bytes[start..start + len.min(bytes.len() - start)]
+}
 impl fmt::Debug for CART {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f,   "Filename: {} \n
@@ -114,4 +367,8 @@ impl fmt::Debug for CART {
                      HEAD: {:?}\n",
                      self.filename, self.ines_fmt, self.nes2_fmt, self.section_sizes, self.HEAD)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+#[path = "./cartridge_test.rs"]
+pub mod cartridge_test;
@@ -0,0 +1,255 @@
+/*  Unit test module of ROM loading (cartridge.rs).
+ */
+use crate::core::cartridge::*;
+
+#[cfg(test)]
+pub mod cartridge_test {
+    use super::*;
+    use crate::core::nes::NES;
+
+    /// Writes some bytes to a temp file, and returns the path.
+    fn temp_file(name: &str, bytes: &[u8]) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("soliloquy_{}_{}.nes", name, std::process::id()));
+        std::fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    /// A header, followed by PRG and CHR of the given lengths.
+    fn rom(head: &[u8], prg: usize, chr: usize) -> Vec<u8> {
+        let mut bytes = b"NES\x1A".to_vec();
+        bytes.extend_from_slice(head);
+        bytes.resize(16, 0);
+        bytes.extend((0..prg).map(|i| i as u8));
+        bytes.extend(vec![0xCC; chr]);
+        bytes
+    }
+
+    #[test]
+    fn test_load(){
+        let cart = CART::open(temp_file("load", &rom(&[1, 1], 0x4000, 0x2000))).unwrap();
+        assert_eq!(cart.PRG.len(), 0x4000);
+        assert_eq!(cart.PRG[0x3FFF], 0xFF);
+        assert_eq!(cart.CHR.len(), 0x2000);
+        assert!(cart.CHR.iter().all(|&b| b == 0xCC));
+    }
+
+    #[test]
+    fn test_load_errors(){
+        match CART::open("/nonexistent/soliloquy.nes") {
+            Err(LoadError::Io(_)) => {}
+            other => panic!("{:?}", other.map(|_| ())),
+        }
+        match CART::open(temp_file("not_ines", b"PK\x03\x04 not a rom at all")) {
+            Err(LoadError::NotINes) => {}
+            other => panic!("{:?}", other.map(|_| ())),
+        }
+        match CART::open(temp_file("short_header", b"NES\x1A\x01\x01")) {
+            Err(LoadError::BadHeader(_)) => {}
+            other => panic!("{:?}", other.map(|_| ())),
+        }
+        match CART::open(temp_file("short_prg", &rom(&[2, 1], 0x5000, 0))) {
+            Err(LoadError::TruncatedPrg { expected: 0x8000, found: 0x5000 }) => {}
+            other => panic!("{:?}", other.map(|_| ())),
+        }
+        match CART::open(temp_file("short_chr", &rom(&[1, 1], 0x4000, 0x100))) {
+            Err(LoadError::TruncatedChr { expected: 0x2000, found: 0x100 }) => {}
+            other => panic!("{:?}", other.map(|_| ())),
+        }
+        //Mapper 255.
+        match NES::new(temp_file("mapper", &rom(&[1, 1, 0xF0, 0xF0], 0x4000, 0x2000))) {
+            Err(LoadError::UnsupportedMapper(255)) => {}
+            Err(e) => panic!("{}", e),
+            Ok(_) => panic!("Loaded an unsupported mapper."),
+        }
+        assert_eq!(LoadError::UnsupportedMapper(5).to_string(), "mapper 5 isn't supported");
+    }
+
+    #[test]
+    fn test_from_memory(){
+        let bytes = rom(&[1, 1], 0x4000, 0x2000);
+        let from_slice = CART::from_bytes(&bytes[..]).unwrap();
+        let from_vec = CART::from_bytes(bytes.clone()).unwrap();
+        let from_reader = CART::from_reader(std::io::Cursor::new(&bytes)).unwrap();
+        for cart in [&from_slice, &from_vec, &from_reader].iter() {
+            assert_eq!(cart.filename, "");
+            assert_eq!(cart.PRG, from_slice.PRG);
+            assert_eq!(cart.CHR.len(), 0x2000);
+        }
+        assert!(matches!(CART::from_bytes(b"NES"), Err(LoadError::NotINes)));
+
+        //A trainer is skipped over, not read as PRG.
+        let mut trained = rom(&[1, 0, 0x04], 0, 0);
+        trained.extend(vec![0x77; 512]);
+        trained.extend(vec![0x11; 0x4000]);
+        let cart = CART::from_bytes(&trained).unwrap();
+        assert_eq!(cart.TRAIN[0], 0x77);
+        assert!(cart.PRG.iter().all(|&b| b == 0x11));
+        assert!(matches!(CART::from_bytes(&trained[..100]),
+                         Err(LoadError::TruncatedPrg { expected: 0x4000, found: 0 })));
+
+        //And it boots, straight from memory.
+        let mut prg = vec![0xEA; 0x4000];
+        prg[..3].copy_from_slice(&[0x4C, 0x00, 0x80]);     //JMP $8000
+        prg[0x3FFC..0x3FFE].copy_from_slice(&[0x00, 0x80]);
+        let mut bytes = rom(&[1, 1], 0, 0);
+        bytes.extend(prg);
+        bytes.extend(vec![0; 0x2000]);
+        let mut nes = NES::from_cart(CART::from_bytes(bytes).unwrap()).unwrap();
+        assert_eq!(nes.cpu.pc, 0x8000);
+        nes.step_frame();
+        assert_eq!(nes.frame_count(), 1);
+    }
+
+    #[test]
+    fn test_rom_and_ram_sizes(){
+        use crate::core::mapper::*;
+
+        //Exactly the sizes in the header, even with more data after.
+        let mut bytes = rom(&[2, 1], 0x8000, 0x2000);
+        bytes.extend(vec![0xEE; 100]);
+        let cart = CART::from_bytes(&bytes).unwrap();
+        assert_eq!(cart.PRG.len(), 0x8000);
+        assert_eq!(cart.CHR.len(), 0x2000);
+        assert!(!cart.chr_ram);
+        assert_eq!(cart.PRG_RAM.len(), 0x2000);
+
+        //CHR-ROM can't be written, CHR-RAM can.
+        let mut map = new_map(0, Box::new(cart)).unwrap();
+        map.set_chr(0x0010, 0x42);
+        assert_eq!(map.get_chr(0x0010), 0xCC);
+        let cart = CART::from_bytes(rom(&[1, 0], 0x4000, 0)).unwrap();
+        assert!(cart.chr_ram);
+        assert_eq!(cart.CHR.len(), 0x2000);
+        let mut map = new_map(0, Box::new(cart)).unwrap();
+        map.set_chr(0x1FFF, 0x42);
+        assert_eq!(map.get_chr(0x1FFF), 0x42);
+
+        //NES 2.0: 32kb CHR-RAM, 8kb battery backed PRG-RAM, no plain PRG-RAM.
+        let cart = CART::from_bytes(rom(&[1, 0, 0x02, 0x08, 0, 0, 0x70, 0x09], 0x4000, 0)).unwrap();
+        assert_eq!(cart.CHR.len(), 0x8000);
+        assert_eq!(cart.PRG_RAM.len(), 0x2000);
+
+        assert!(matches!(CART::from_bytes(rom(&[0, 1], 0, 0x2000)), Err(LoadError::BadHeader(_))));
+    }
+
+    #[test]
+    fn test_map_defaults(){
+        use crate::core::mapper::*;
+
+        /// A board that only hands out its cartridge.
+        struct Plain { cart: Box<CART> }
+        impl MAP for Plain {
+            fn cart(&self) -> Option<&CART> { Some(&self.cart) }
+            fn cart_mut(&mut self) -> Option<&mut CART> { Some(&mut self.cart) }
+        }
+
+        //16kb of PRG, mirrored at $C000, 8kb of CHR-RAM, vertical mirroring.
+        let cart = CART::from_bytes(rom(&[1, 0, 0x01], 0x4000, 0)).unwrap();
+        let mut map = Plain { cart: Box::new(cart) };
+        assert_eq!(map.get(0x8005), 0x05);
+        assert_eq!(map.get(0xC005), 0x05);
+        assert_eq!(map.get(0x5000), 0);
+        map.set(0x6010, 0x42);
+        assert_eq!(map.get(0x6010), 0x42);
+        map.set_chr(0x0100, 0x24);
+        assert_eq!(map.get_chr(0x0100), 0x24);
+        assert_eq!(map.mirroring(), Mirroring::Vertical);
+        assert!(map.battery_ram().is_none());
+        map.power_on();
+        assert_eq!(map.get(0x6010), 0);
+        assert_eq!(map.get_chr(0x0100), 0);
+
+        //Without a cartridge, nothing answers.
+        let mut empty = EMPTY_MAP;
+        empty.set(0x6000, 0x42);
+        assert_eq!(empty.get(0x6000), 0);
+        assert_eq!(empty.get_chr(0), 0);
+        assert_eq!(empty.mirroring(), Mirroring::Horizontal);
+    }
+
+    #[test]
+    fn test_battery_save(){
+        //NROM with 2kb of battery backed PRG-RAM (NES 2.0), mirrored to 8kb.
+        let path = temp_file("battery", &rom(&[1, 1, 0x02, 0x08, 0, 0, 0x50], 0x4000, 0x2000));
+        let sav = path.with_extension("sav");
+        let _ = std::fs::remove_file(&sav);
+
+        let mut nes = NES::new(&path).unwrap();
+        assert_eq!(nes.save_path(), Some(sav.as_path()));
+        nes.cpu.memory.set(0x6000, 0x42);
+        nes.cpu.memory.set(0x7FFF, 0x24);
+        assert_eq!(nes.cpu.memory.get(0x6800), 0x42);
+        nes.shutdown();
+        let data = std::fs::read(&sav).unwrap();
+        assert_eq!(data.len(), 0x800);
+        assert_eq!((data[0], data[0x7FF]), (0x42, 0x24));
+        //Nothing changed, so nothing to write.
+        assert!(!nes.flush_battery().unwrap());
+
+        //It's back on the next boot.
+        let mut nes = NES::new(&path).unwrap();
+        assert_eq!(nes.cpu.memory.get(0x6000), 0x42);
+        //But not for movies, and they leave it alone.
+        nes.record_movie();
+        assert_eq!(nes.cpu.memory.get(0x6000), 0);
+        nes.cpu.memory.set(0x6000, 0x99);
+        nes.shutdown();
+        assert_eq!(std::fs::read(&sav).unwrap()[0], 0x42);
+        let _ = std::fs::remove_file(&sav);
+
+        //Without a battery, PRG-RAM works but isn't saved.
+        let path = temp_file("no_battery", &rom(&[1, 1], 0x4000, 0x2000));
+        let mut nes = NES::new(&path).unwrap();
+        assert_eq!(nes.save_path(), None);
+        nes.cpu.memory.set(0x6123, 0x55);
+        assert_eq!(nes.cpu.memory.get(0x6123), 0x55);
+        assert!(!nes.flush_battery().unwrap());
+    }
+
+    #[test]
+    fn test_battery_ram_is_nvram_only(){
+        //NES 2.0 with 2kb of PRG-RAM and 2kb of PRG-NVRAM: $6000-$67FF is
+        //  volatile, $6800-$6FFF is battery backed.
+        let path = temp_file("nvram", &rom(&[1, 1, 0x02, 0x08, 0, 0, 0x55], 0x4000, 0x2000));
+        let sav = path.with_extension("sav");
+        let _ = std::fs::remove_file(&sav);
+
+        let mut nes = NES::new(&path).unwrap();
+        assert_eq!(nes.cpu.memory.CART.battery_ram().map(|ram| ram.len()), Some(0x800));
+        nes.cpu.memory.set(0x6000, 0x11);
+        nes.cpu.memory.set(0x6800, 0x22);
+        nes.shutdown();
+        let data = std::fs::read(&sav).unwrap();
+        assert_eq!((data.len(), data[0]), (0x800, 0x22));
+
+        //Only the NVRAM comes back.
+        let mut nes = NES::new(&path).unwrap();
+        assert_eq!(nes.cpu.memory.get(0x6000), 0);
+        assert_eq!(nes.cpu.memory.get(0x6800), 0x22);
+        let _ = std::fs::remove_file(&sav);
+    }
+
+    #[test]
+    fn test_trainer(){
+        //NES 2.0 with a trainer and no PRG-RAM still gets 8kb for it.
+        let mut bytes = rom(&[1, 1, 0x04, 0x08], 0, 0);
+        bytes.extend((0..512).map(|i| (i / 2) as u8));
+        bytes.extend(vec![0xEA; 0x4000]);
+        bytes.extend(vec![0; 0x2000]);
+        let mut nes = NES::from_cart(CART::from_bytes(&bytes).unwrap()).unwrap();
+        let memory = &mut nes.cpu.memory;
+        assert_eq!(memory.get(0x7000), 0);
+        assert_eq!(memory.get(0x7011), 8);
+        assert_eq!(memory.get(0x71FF), 0xFF);
+        assert_eq!(memory.get(0x7200), 0);
+        assert_eq!(memory.get(0x6000), 0);
+
+        //The game can write over it, but it's back after a power cycle,
+        //  which clears the rest of the (battery-less) RAM.
+        memory.set(0x7011, 0x99);
+        memory.set(0x7200, 0x99);
+        nes.power_cycle();
+        assert_eq!(nes.cpu.memory.get(0x7011), 8);
+        assert_eq!(nes.cpu.memory.get(0x7200), 0);
+    }
+}
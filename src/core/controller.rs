@@ -0,0 +1,95 @@
+/* Emulates the standard NES controller. The ports are in input.rs.
+ * $4016 (write)   ---- ---S   Strobe. While high, the shift registers keep
+ *                             reloading from the buttons.
+ * $4016 (read)    ---- ---D   Port 1 serial data.
+ * $4017 (read)    ---- ---D   Port 2 serial data.
+ * Buttons shift out in the order A, B, Select, Start, Up, Down, Left, Right,
+ *  and official controllers return 1 after all 8 are read.
+ * The top bits of a read are open bus, which is almost always $40 (the high
+ *  byte of the address), so that's what we return. Some games check it.
+ *
+ * http://wiki.nesdev.com/w/index.php/Standard_controller
+ */
+
+use std::any::Any;
+
+use crate::core::input::InputDevice;
+use crate::core::ppu::PPU;
+
+pub const BUTTON_A:      u8 = 1 << 0;
+pub const BUTTON_B:      u8 = 1 << 1;
+pub const BUTTON_SELECT: u8 = 1 << 2;
+pub const BUTTON_START:  u8 = 1 << 3;
+pub const BUTTON_UP:     u8 = 1 << 4;
+pub const BUTTON_DOWN:   u8 = 1 << 5;
+pub const BUTTON_LEFT:   u8 = 1 << 6;
+pub const BUTTON_RIGHT:  u8 = 1 << 7;
+
+/// Open bus bits of a controller read.
+pub const OPEN_BUS: u8 = 0x40;
+
+/// A standard joypad.
+#[derive(Debug, Clone, Default)]
+pub struct Controller {
+    /// Held buttons, one bit each (BUTTON_*).
+    pub buttons:    u8,
+    /// Buttons still to be shifted out, since the last strobe.
+    shift:          u8,
+    /// How many bits have been read since the last strobe.
+    reads:          u8,
+    strobe:         bool,
+}
+
+impl Controller {
+    pub fn new() -> Controller {
+        Controller::default()
+    }
+
+    pub fn set_button(&mut self, button: u8, pressed: bool) {
+        if pressed { self.buttons |= button; } else { self.buttons &= !button; }
+    }
+
+    /// Bit 0 of a $4016 write.
+    pub fn write_strobe(&mut self, strobe: bool) {
+        self.strobe = strobe;
+        if strobe {
+            self.reload();
+        }
+    }
+
+    fn reload(&mut self) {
+        self.shift = self.buttons;
+        self.reads = 0;
+    }
+
+    /// Reads the next bit (bit 0 of the result).
+    pub fn read(&mut self) -> u8 {
+        if self.strobe {
+            self.reload();
+            return self.buttons & 1;
+        }
+        if self.reads >= 8 {
+            return 1;
+        }
+        let bit = self.shift & 1;
+        self.shift >>= 1;
+        self.reads += 1;
+        bit
+    }
+}
+
+impl InputDevice for Controller {
+    fn name(&self) -> &'static str { "Controller" }
+    fn write(&mut self, val: u8) {
+        self.write_strobe(val & 1 != 0);
+    }
+    fn read(&mut self, _register: usize, _ppu: &PPU, _palette: &[u32; 64]) -> u8 {
+        Controller::read(self)
+    }
+    fn as_any(&self) -> &dyn Any { self }
+    fn as_any_mut(&mut self) -> &mut dyn Any { self }
+}
+
+#[cfg(test)]
+#[path = "./controller_test.rs"]
+pub mod controller_test;
@@ -0,0 +1,66 @@
+/*  Unit test module of the controllers (controller.rs).
+ */
+use crate::core::controller::*;
+
+#[cfg(test)]
+pub mod controller_test {
+    use super::*;
+    use crate::core::memory::MEM;
+
+    #[test]
+    fn test_shift_register(){
+        let mut pad = Controller::new();
+        pad.set_button(BUTTON_A, true);
+        pad.set_button(BUTTON_START, true);
+        pad.set_button(BUTTON_RIGHT, true);
+        pad.set_button(BUTTON_A, false);
+        pad.set_button(BUTTON_A, true);
+
+        pad.write_strobe(true);
+        pad.write_strobe(false);
+        let bits: Vec<u8> = (0..8).map(|_| pad.read()).collect();
+        assert_eq!(bits, vec![1, 0, 0, 1, 0, 0, 0, 1]);
+
+        //Official pads return 1s after the 8th read.
+        assert_eq!(pad.read(), 1);
+        assert_eq!(pad.read(), 1);
+
+        //Button changes after the strobe don't show until the next one.
+        pad.buttons = BUTTON_B;
+        pad.write_strobe(true);
+        pad.write_strobe(false);
+        assert_eq!(pad.read(), 0);
+        assert_eq!(pad.read(), 1);
+    }
+
+    #[test]
+    fn test_strobe_held(){
+        //While the strobe is high, every read is the A button.
+        let mut pad = Controller::new();
+        pad.buttons = BUTTON_A | BUTTON_B;
+        pad.write_strobe(true);
+        assert_eq!(pad.read(), 1);
+        assert_eq!(pad.read(), 1);
+        pad.buttons = BUTTON_B;
+        assert_eq!(pad.read(), 0);
+    }
+
+    #[test]
+    fn test_ports(){
+        let mut mem = MEM::new_empty();
+        mem.INPUT.set_buttons(0, BUTTON_SELECT);
+        mem.INPUT.set_buttons(1, BUTTON_B);
+        mem.set(0x4016, 1);
+        mem.set(0x4016, 0);
+
+        let port1: Vec<u8> = (0..3).map(|_| mem.get(0x4016)).collect();
+        let port2: Vec<u8> = (0..3).map(|_| mem.get(0x4017)).collect();
+        assert_eq!(port1, vec![0x40, 0x40, 0x41]);
+        assert_eq!(port2, vec![0x40, 0x41, 0x40]);
+
+        //$4017 writes go to the APU frame counter, not the pads.
+        mem.set(0x4017, 0x40);
+        assert!(mem.APU.irq_inhibit);
+        assert_eq!(mem.get(0x4017), 0x40);
+    }
+}
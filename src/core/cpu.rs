@@ -30,6 +30,13 @@
         ($w:expr) => ($w & 0xff);
     }
 
+/// No interrupt pending.
+pub const INTERRUPT_NONE: u8 = 0;
+/// Non-maskable interrupt pending.
+pub const INTERRUPT_NMI:  u8 = 1;
+/// Maskable interrupt pending.
+pub const INTERRUPT_IRQ:  u8 = 2;
+
 //CPU=DEFINITION================================================================
 //==============================================================================
 
@@ -67,7 +74,7 @@ pub struct CPU {
     /// Interrupt type to perform.
     pub interrupt:      u8,         
     /// Number of cycles to stall.
-    pub stall:          u8, 
+    pub stall:          u16, 
 
 }
 
@@ -149,6 +156,22 @@ impl CPU {
     /// I understand that rust doesn't allow this for many memory/concurrency
     ///  safety reasons, but much to my sadness.
     pub fn step(&mut self){
+        //DMA (and friends) halt the CPU, so the whole stall is one "step".
+        self.stall += self.memory.stall;
+        self.memory.stall = 0;
+        if self.stall > 0 {
+            self.cycles += self.stall as u64;
+            self.stall = 0;
+            return;
+        }
+
+        match self.interrupt {
+            INTERRUPT_NMI => self.nmi(),
+            INTERRUPT_IRQ => self.irq(),
+            _             => {}
+        }
+        self.interrupt = INTERRUPT_NONE;
+
         let opnum = self.memory.get(self.pc);
 
         info!("ATTEMPT  -> OP: #[{:X}] \t\t CPU:[PC:{:4X} || A:{:2X}, X:{:2X}, Y:{:2X}, P:{:2X}, SP:{:2X}, CYC:{}, SL:?]",
@@ -159,14 +182,68 @@ impl CPU {
             panic!("OP: [{:x}] NOT FOUND!", opnum);
         }
 
+        //Page crossing penalties aren't counted yet.
+        self.cycles += OP_SPEEDS[opnum as usize] as u64;
     }
 
 
 
-    fn reset(&mut self){
+    /// Puts the CPU into its reset state, and jumps to the reset vector.
+    pub fn reset(&mut self){
         self.sp = 0xFD;
         self.set_status(2, true);
         self.memory.set(0x4015, 0);
+        self.pc = self.read_vector(0xFFFC);
+    }
+
+    /// Calls a subroutine from outside the CPU (the NSF player does this
+    ///  for INIT and PLAY). Pushes return_to the way JSR would, so the
+    ///  routine's RTS lands there.
+    pub fn call(&mut self, address: u16, return_to: u16){
+        self.stack_push(word_to_h_byte!(return_to) as u8);
+        self.stack_push(word_to_l_byte!(return_to) as u8);
+        self.pc = address;
+    }
+
+    /// Requests an NMI, which is serviced before the next instruction.
+    pub fn trigger_nmi(&mut self){
+        self.interrupt = INTERRUPT_NMI;
+    }
+
+    /// Requests an IRQ, which is serviced before the next instruction if
+    ///  the interrupt disable flag is clear. NMI takes priority.
+    pub fn trigger_irq(&mut self){
+        if self.interrupt != INTERRUPT_NMI && self.status & (1 << 2) == 0 {
+            self.interrupt = INTERRUPT_IRQ;
+        }
+    }
+
+    /// Reads a little endian 16-bit vector.
+    fn read_vector(&mut self, address: u16) -> u16 {
+        let low  = self.memory.get(address) as u16;
+        let high = self.memory.get(address + 1) as u16;
+        bytes_to_word!(high, low)
+    }
+
+    /// Pushes PC and P (with 'B' clear), and jumps through the vector.
+    fn interrupt_to(&mut self, vector: u16){
+        self.stack_push(word_to_h_byte!(self.pc) as u8);
+        self.stack_push(word_to_l_byte!(self.pc) as u8);
+        let P: u8 = (self.status | (1 << 5)) & !(1 << 4);
+        self.stack_push(P);
+        self.SEI();
+        self.pc = self.read_vector(vector);
+        self.cycles += 7;
+    }
+
+    /// Non-Maskable Interrupt, the PPU throws this at vblank.
+    fn nmi(&mut self){
+        self.interrupt_to(0xFFFA);
+    }
+
+    /// Interrupt ReQuest, thrown by the APU and some mappers.
+    fn irq(&mut self){
+        self.interrupt_to(0xFFFE);
     }
 
     /// Receives a string as a param, and throws one of the 3 (?) cpu
@@ -1305,28 +1382,28 @@ impl AddressingMode for ZeroPageAM {
 }
 impl AddressingMode for ZeroPageXAM  {
     fn load (&self, cpu: &mut CPU) -> u8
-    {	cpu.memory.get_zp( self.address+cpu.x ) }
+    {	cpu.memory.get_zp( self.address.wrapping_add(cpu.x) ) }
     fn save (&self, cpu: &mut CPU, storeval: u8)
-    {	cpu.memory.set_zp( self.address + cpu.x , storeval); }
+    {	cpu.memory.set_zp( self.address.wrapping_add(cpu.x), storeval); }
     fn address (&self) -> u16 { self.address as u16 } 
 }
 impl AddressingMode for ZeroPageYAM  {
     fn load (&self, cpu: &mut CPU) -> u8
-    {	cpu.memory.get_zp( self.address + cpu.y ) }
+    {	cpu.memory.get_zp( self.address.wrapping_add(cpu.y) ) }
     fn save (&self, cpu: &mut CPU, storeval: u8)
-    {	cpu.memory.set_zp( self.address + cpu.y , storeval); }
+    {	cpu.memory.set_zp( self.address.wrapping_add(cpu.y), storeval); }
     fn address (&self) -> u16 { self.address as u16 } 
 }
 impl AddressingMode for IndexedIndirectAM {
     fn load (&self, cpu: &mut CPU) -> u8 {
-	let low  = cpu.memory.get_zp( self.address + cpu.x );
-        let high = cpu.memory.get_zp( self.address + cpu.x + 1);
+	let low  = cpu.memory.get_zp( self.address.wrapping_add(cpu.x) );
+        let high = cpu.memory.get_zp( self.address.wrapping_add(cpu.x).wrapping_add(1) );
 
         cpu.memory.get( bytes_to_word!(high as u16,low as u16) )
     }
     fn save (&self, cpu: &mut CPU, storeval: u8){
-	let low  = cpu.memory.get_zp( self.address + cpu.x );
-        let high = cpu.memory.get_zp( self.address + cpu.x + 1);
+	let low  = cpu.memory.get_zp( self.address.wrapping_add(cpu.x) );
+        let high = cpu.memory.get_zp( self.address.wrapping_add(cpu.x).wrapping_add(1) );
 
         cpu.memory.set( bytes_to_word!(high as u16,low as u16), storeval );
     }
@@ -1335,13 +1412,13 @@ impl AddressingMode for IndexedIndirectAM {
 impl AddressingMode for IndirectIndexedAM {
     fn load (&self, cpu: &mut CPU) -> u8 {
 	let low  = cpu.memory.get_zp( self.address );
-        let high = cpu.memory.get_zp( self.address + 1);
+        let high = cpu.memory.get_zp( self.address.wrapping_add(1) );
 
         cpu.memory.get( bytes_to_word!(high as u16,low as u16) + cpu.y as u16 )
     }
     fn save (&self, cpu: &mut CPU, storeval: u8){
 	let low  = cpu.memory.get_zp( self.address );
-        let high = cpu.memory.get_zp( self.address + 1);
+        let high = cpu.memory.get_zp( self.address.wrapping_add(1) );
 
         cpu.memory.set( bytes_to_word!(high as u16,low as u16) + cpu.y as u16,
                         storeval );
@@ -0,0 +1,174 @@
+/* Cartridge expansion sound.
+ * Some cartridges (and NSF files) add sound channels of their own, which
+ *  are mixed in with the APU through MAP::expansion_audio.
+ * So far only the Konami VRC6 is emulated: two pulse channels with 8 duty
+ *  settings, and a sawtooth. See nesdev "VRC6 audio".
+ *
+ * $9000/$A000   MDDD VVVV   Pulse mode (always on), duty, volume
+ * $9001/$A001   PPPP PPPP   Pulse period low
+ * $9002/$A002   E--- PPPP   Pulse enable, period high
+ * $9003         ---- -XXH   Halt all channels (the frequency shifts aren't done)
+ * $B000         --AA AAAA   Saw accumulator rate
+ * $B001         PPPP PPPP   Saw period low
+ * $B002         E--- PPPP   Saw enable, period high
+ */
+
+use crate::core::audio::PULSE_TABLE;
+
+/// VRC6 pulse channel.
+#[derive(Debug, Clone, Default)]
+pub struct Vrc6Pulse {
+    pub enabled:    bool,
+    pub mode:       bool,
+    pub duty:       u8,
+    pub volume:     u8,
+    pub period:     u16,
+    pub timer:      u16,
+    /// Duty step, counts down from 15.
+    pub step:       u8,
+}
+
+impl Vrc6Pulse {
+    fn write(&mut self, register: u16, val: u8) {
+        match register {
+            0 => {
+                self.mode = val & 0x80 != 0;
+                self.duty = (val >> 4) & 7;
+                self.volume = val & 0x0F;
+            }
+            1 => self.period = (self.period & 0x0F00) | val as u16,
+            2 => {
+                self.period = (self.period & 0x00FF) | (((val & 0x0F) as u16) << 8);
+                self.enabled = val & 0x80 != 0;
+                //Disabling resets the duty cycle.
+                if !self.enabled {
+                    self.step = 15;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn step_timer(&mut self) {
+        if !self.enabled {
+            return;
+        }
+        if self.timer == 0 {
+            self.timer = self.period;
+            self.step = self.step.wrapping_sub(1) & 0x0F;
+        }
+        else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        if self.enabled && (self.mode || self.step <= self.duty) { self.volume } else { 0 }
+    }
+}
+
+/// VRC6 sawtooth channel.
+#[derive(Debug, Clone, Default)]
+pub struct Vrc6Saw {
+    pub enabled:        bool,
+    pub rate:           u8,
+    pub period:         u16,
+    pub timer:          u16,
+    /// 0-13, the accumulator grows on every other step and resets at 14.
+    pub step:           u8,
+    pub accumulator:    u8,
+}
+
+impl Vrc6Saw {
+    fn write(&mut self, register: u16, val: u8) {
+        match register {
+            0 => self.rate = val & 0x3F,
+            1 => self.period = (self.period & 0x0F00) | val as u16,
+            2 => {
+                self.period = (self.period & 0x00FF) | (((val & 0x0F) as u16) << 8);
+                self.enabled = val & 0x80 != 0;
+                if !self.enabled {
+                    self.step = 0;
+                    self.accumulator = 0;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn step_timer(&mut self) {
+        if !self.enabled {
+            return;
+        }
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.period;
+        self.step += 1;
+        if self.step == 14 {
+            self.step = 0;
+            self.accumulator = 0;
+        }
+        else if self.step.is_multiple_of(2) {
+            self.accumulator = self.accumulator.wrapping_add(self.rate);
+        }
+    }
+
+    /// The top 5 bits of the accumulator.
+    pub fn output(&self) -> u8 {
+        if self.enabled { self.accumulator >> 3 } else { 0 }
+    }
+}
+
+/// The VRC6 sound hardware.
+#[derive(Debug, Clone, Default)]
+pub struct Vrc6Audio {
+    pub pulse1: Vrc6Pulse,
+    pub pulse2: Vrc6Pulse,
+    pub saw:    Vrc6Saw,
+    pub halt:   bool,
+}
+
+impl Vrc6Audio {
+    pub fn new() -> Vrc6Audio {
+        let mut vrc6 = Vrc6Audio::default();
+        vrc6.pulse1.step = 15;
+        vrc6.pulse2.step = 15;
+        vrc6
+    }
+
+    /// True for the addresses the VRC6 sound registers decode.
+    pub fn handles(address: u16) -> bool {
+        matches!(address & 0xF003, 0x9000..=0x9003 | 0xA000..=0xA002 | 0xB000..=0xB002)
+    }
+
+    /// Handles a CPU write, if it's to one of the sound registers.
+    pub fn write(&mut self, address: u16, val: u8) {
+        let register = address & 3;
+        match address & 0xF003 {
+            0x9003          => self.halt = val & 1 != 0,
+            0x9000..=0x9002 => self.pulse1.write(register, val),
+            0xA000..=0xA002 => self.pulse2.write(register, val),
+            0xB000..=0xB002 => self.saw.write(register, val),
+            _               => {}
+        }
+    }
+
+    /// Clocked every CPU cycle.
+    pub fn step(&mut self) {
+        if self.halt {
+            return;
+        }
+        self.pulse1.step_timer();
+        self.pulse2.step_timer();
+        self.saw.step_timer();
+    }
+
+    /// Output on the APU mixer's scale. The VRC6 is mixed linearly, one step
+    ///  of volume is about as loud as one step of an APU pulse on its own.
+    pub fn output(&self) -> f32 {
+        let sum = self.pulse1.output() as f32 + self.pulse2.output() as f32 + self.saw.output() as f32;
+        sum * PULSE_TABLE[15] / 15.0
+    }
+}
@@ -0,0 +1,415 @@
+/* Video post-processing filters for the PPU output.
+ *
+ * Everything here works on the *raw* PPU output rather than on RGB, so it can
+ *  run headless (screenshots, tests) just as well as behind a window.
+ * A raw pixel is a u16 laid out the same way blargg's nes_ntsc expects:
+ *   bits 0-5 : palette color (what the PPU read out of palette RAM)
+ *   bits 6-8 : color emphasis bits (PPUMASK bits 5-7, R G B)
+ *
+ * Filters:
+ *  - rgb_frame     : plain palette lookup, one RGB pixel per NES pixel.
+ *  - NtscFilter    : signal-level composite simulation (artifacts, dot crawl).
+ *  - scale_nearest : integer nearest-neighbor scaling.
+ *  - scanlines     : doubles the height and darkens every other line.
+ *
+ * RGB output pixels are packed as 0x00RRGGBB.
+ */
+
+use std::f32::consts::PI;
+
+/// Width of a full PPU frame in pixels.
+pub const NES_WIDTH: usize = 256;
+/// Height of a full PPU frame in pixels.
+pub const NES_HEIGHT: usize = 240;
+
+/// The 2C02 palette, as used by most emulators (see fogleman/nes).
+pub static NES_PALETTE: [u32; 64] = [
+    0x666666, 0x002A88, 0x1412A7, 0x3B00A4, 0x5C007E, 0x6E0040, 0x6C0600, 0x561D00,
+    0x333500, 0x0B4800, 0x005200, 0x004F08, 0x00404D, 0x000000, 0x000000, 0x000000,
+    0xADADAD, 0x155FD9, 0x4240FF, 0x7527FE, 0xA01ACC, 0xB71E7B, 0xB53120, 0x994E00,
+    0x6B6D00, 0x388700, 0x0C9300, 0x008F32, 0x007C8D, 0x000000, 0x000000, 0x000000,
+    0xFFFEFF, 0x64B0FF, 0x9290FF, 0xC676FF, 0xF36AFF, 0xFE6ECC, 0xFE8170, 0xEA9E22,
+    0xBCBE00, 0x88D800, 0x5CE430, 0x45E082, 0x48CDDE, 0x4F4F4F, 0x000000, 0x000000,
+    0xFFFEFF, 0xC0DFFF, 0xD3D2FF, 0xE8C8FF, 0xFBC2FF, 0xFEC4EA, 0xFECCC5, 0xF7D8A5,
+    0xE4E594, 0xCFEF96, 0xBDF4AB, 0xB3F3CC, 0xB5EBF2, 0xB8B8B8, 0x000000, 0x000000,
+];
+
+/// The RGB PPU palette (RP2C03 and RP2C05), used by the PlayChoice-10 and
+///  some Vs. System boards. These PPUs output RGB straight from a ROM with
+///  3 bits per channel, rather than an NTSC signal.
+pub static RGB_PPU_PALETTE: [u32; 64] = [
+    0x6D6D6D, 0x002491, 0x0000DA, 0x6D48DA, 0x91006D, 0xB6006D, 0xB62400, 0x914800,
+    0x6D4800, 0x244800, 0x006D24, 0x009100, 0x004848, 0x000000, 0x000000, 0x000000,
+    0xB6B6B6, 0x006DDA, 0x0048FF, 0x9100FF, 0xB600FF, 0xFF0091, 0xFF0000, 0xDA6D00,
+    0x916D00, 0x249100, 0x009100, 0x00B66D, 0x009191, 0x000000, 0x000000, 0x000000,
+    0xFFFFFF, 0x6DB6FF, 0x9191FF, 0xDA6DFF, 0xFF00FF, 0xFF6DFF, 0xFF9100, 0xFFB600,
+    0xDADA00, 0x6DDA00, 0x00FF00, 0x48FFDA, 0x00FFFF, 0x000000, 0x000000, 0x000000,
+    0xFFFFFF, 0xB6DAFF, 0xDAB6FF, 0xFFB6FF, 0xFF91FF, 0xFFB6B6, 0xFFDA91, 0xFFFF48,
+    0xFFFF6D, 0xB6FF48, 0x91FF6D, 0x48FFDA, 0x91DAFF, 0x000000, 0x000000, 0x000000,
+];
+
+/// Where each RP2C04 gets its colors from: entry i of a chip's palette is
+///  entry RP2C04_ORDER[chip][i] of the RGB_PPU_PALETTE. The chips share
+///  the RP2C03's color ROM, but each has it wired in its own order.
+static RP2C04_ORDER: [[u8; 64]; 4] = [
+    //RP2C04-0001
+    [
+        0x35, 0x23, 0x16, 0x22, 0x1C, 0x09, 0x1D, 0x15, 0x20, 0x00, 0x27, 0x05, 0x04, 0x28, 0x08, 0x20,
+        0x21, 0x3E, 0x1F, 0x29, 0x3C, 0x32, 0x36, 0x12, 0x3F, 0x2B, 0x2E, 0x1E, 0x3D, 0x2D, 0x24, 0x01,
+        0x0E, 0x31, 0x33, 0x2A, 0x2C, 0x0C, 0x1B, 0x14, 0x2E, 0x07, 0x34, 0x06, 0x13, 0x02, 0x26, 0x2E,
+        0x2E, 0x19, 0x10, 0x0A, 0x39, 0x03, 0x37, 0x17, 0x0F, 0x11, 0x0B, 0x0D, 0x38, 0x25, 0x18, 0x3A,
+    ],
+    //RP2C04-0002
+    [
+        0x2E, 0x27, 0x18, 0x39, 0x3A, 0x25, 0x1C, 0x31, 0x16, 0x13, 0x38, 0x34, 0x20, 0x23, 0x3C, 0x0B,
+        0x0F, 0x21, 0x06, 0x3D, 0x1B, 0x29, 0x1E, 0x22, 0x1D, 0x24, 0x0E, 0x2B, 0x32, 0x08, 0x2E, 0x03,
+        0x04, 0x36, 0x26, 0x33, 0x11, 0x1F, 0x10, 0x02, 0x14, 0x3F, 0x00, 0x09, 0x12, 0x2E, 0x28, 0x20,
+        0x3E, 0x0D, 0x2A, 0x17, 0x0C, 0x01, 0x15, 0x19, 0x2E, 0x2C, 0x07, 0x37, 0x35, 0x05, 0x0A, 0x2D,
+    ],
+    //RP2C04-0003
+    [
+        0x14, 0x25, 0x3A, 0x10, 0x0B, 0x20, 0x31, 0x09, 0x01, 0x2E, 0x36, 0x08, 0x15, 0x3D, 0x3E, 0x3C,
+        0x22, 0x1C, 0x05, 0x12, 0x19, 0x18, 0x17, 0x1B, 0x00, 0x03, 0x2E, 0x02, 0x16, 0x06, 0x34, 0x35,
+        0x23, 0x0F, 0x0E, 0x37, 0x0D, 0x27, 0x26, 0x20, 0x29, 0x04, 0x21, 0x24, 0x11, 0x2D, 0x2E, 0x1F,
+        0x2C, 0x1E, 0x39, 0x33, 0x07, 0x2A, 0x28, 0x1D, 0x0A, 0x2E, 0x32, 0x38, 0x13, 0x2B, 0x3F, 0x0C,
+    ],
+    //RP2C04-0004
+    [
+        0x18, 0x03, 0x1C, 0x28, 0x2E, 0x35, 0x01, 0x17, 0x10, 0x1F, 0x2A, 0x0E, 0x36, 0x37, 0x0B, 0x39,
+        0x25, 0x1E, 0x12, 0x34, 0x2E, 0x1D, 0x06, 0x26, 0x3E, 0x1B, 0x22, 0x19, 0x04, 0x2E, 0x3A, 0x21,
+        0x05, 0x0A, 0x07, 0x02, 0x13, 0x14, 0x00, 0x15, 0x0C, 0x3D, 0x11, 0x0F, 0x0D, 0x38, 0x2D, 0x24,
+        0x33, 0x20, 0x08, 0x16, 0x32, 0x29, 0x31, 0x27, 0x09, 0x2C, 0x3F, 0x2E, 0x23, 0x20, 0x3C, 0x2B,
+    ],
+];
+
+/// The RP2C04-0001 to -0004 palettes, used by some Vs. System boards.
+pub static RP2C04_PALETTES: [[u32; 64]; 4] = [
+    rp2c04_palette(0), rp2c04_palette(1), rp2c04_palette(2), rp2c04_palette(3),
+];
+
+const fn rp2c04_palette(chip: usize) -> [u32; 64] {
+    let mut palette = [0; 64];
+    let mut i = 0;
+    while i < 64 {
+        palette[i] = RGB_PPU_PALETTE[RP2C04_ORDER[chip][i] as usize];
+        i += 1;
+    }
+    palette
+}
+
+/// How much a channel is dimmed when another channel is emphasized.
+const RGB_EMPHASIS_ATTENUATION: f32 = 0.816;
+
+/// Converts a single raw PPU pixel to RGB using the given 64 color palette.
+/// The emphasis bits dim the channels that are *not* emphasized, which is
+///  roughly what happens on a TV.
+pub fn raw_to_rgb(pixel: u16, palette: &[u32; 64]) -> u32 {
+    let rgb = palette[(pixel & 0x3F) as usize];
+    let emphasis = (pixel >> 6) & 7;
+    if emphasis == 0 {
+        return rgb;
+    }
+
+    let mut channels = [
+        ((rgb >> 16) & 0xFF) as f32,
+        ((rgb >> 8) & 0xFF) as f32,
+        (rgb & 0xFF) as f32,
+    ];
+    // Bit 0 emphasizes red, bit 1 green, bit 2 blue.
+    for (c, value) in channels.iter_mut().enumerate() {
+        if emphasis & !(1 << c) != 0 {
+            *value *= RGB_EMPHASIS_ATTENUATION;
+        }
+    }
+    pack_rgb(channels[0] / 255.0, channels[1] / 255.0, channels[2] / 255.0)
+}
+
+/// Converts a whole raw frame to RGB with the default 2C02 palette.
+pub fn rgb_frame(raw: &[u16]) -> Vec<u32> {
+    raw.iter().map(|&p| raw_to_rgb(p, &NES_PALETTE)).collect()
+}
+
+/// Nearest-neighbor integer scaling.
+/// Returns the scaled buffer, which is (width*factor) x (height*factor).
+pub fn scale_nearest(input: &[u32], width: usize, height: usize, factor: usize) -> Vec<u32> {
+    assert_eq!(input.len(), width * height, "Input buffer does not match dimensions.");
+    let factor = factor.max(1);
+    let out_w = width * factor;
+    let mut out = Vec::with_capacity(out_w * height * factor);
+
+    for row in input.chunks(width) {
+        let start = out.len();
+        for &px in row {
+            out.extend(std::iter::repeat_n(px, factor));
+        }
+        //Copy the freshly scaled line for the remaining vertical repeats.
+        for _ in 1..factor {
+            out.extend_from_within(start..start + out_w);
+        }
+    }
+    out
+}
+
+/// Doubles the height of the image, darkening every second line.
+/// intensity is how dark the gap lines are: 0.0 leaves them untouched,
+///  1.0 makes them black.
+pub fn scanlines(input: &[u32], width: usize, height: usize, intensity: f32) -> Vec<u32> {
+    assert_eq!(input.len(), width * height, "Input buffer does not match dimensions.");
+    let keep = 1.0 - intensity.clamp(0.0, 1.0);
+    let mut out = Vec::with_capacity(width * height * 2);
+
+    for row in input.chunks(width) {
+        out.extend_from_slice(row);
+        out.extend(row.iter().map(|&px| {
+            let r = ((px >> 16) & 0xFF) as f32 * keep;
+            let g = ((px >> 8) & 0xFF) as f32 * keep;
+            let b = (px & 0xFF) as f32 * keep;
+            ((r as u32) << 16) | ((g as u32) << 8) | b as u32
+        }));
+    }
+    out
+}
+
+//~NTSC~FILTER~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+// Signal generation follows the nesdev "NTSC video" article: every PPU dot is
+//  8 master clocks long, and the color subcarrier is 12 master clocks long,
+//  so we generate 8 samples per pixel, each 30 degrees of subcarrier apart.
+
+/// Composite levels relative to sync, signal low then signal high.
+const LEVELS: [f32; 8] = [0.350, 0.518, 0.962, 1.550,
+                          1.094, 1.506, 1.962, 1.962];
+const BLACK: f32 = 0.518;
+const WHITE: f32 = 1.962;
+/// Signal attenuation while an emphasized color phase is being output.
+const NTSC_EMPHASIS_ATTENUATION: f32 = 0.746;
+
+/// Samples generated per PPU pixel.
+const SAMPLES_PER_PIXEL: usize = 8;
+/// Samples per color subcarrier cycle.
+const SAMPLES_PER_CYCLE: usize = 12;
+/// Samples between output pixels, this gives 2 output pixels per NES pixel.
+const SAMPLES_PER_OUTPUT: usize = 4;
+/// Each scanline is 341 dots, 341*8 % 12 = 4 samples of phase shift per line.
+const LINE_PHASE_SHIFT: usize = 4;
+/// Phase tweak so that the default hue matches the usual palettes.
+const HUE_FUDGE: f32 = 3.9;
+
+/// Returns the output width of the NTSC filter for a given input width.
+pub fn ntsc_output_width(in_width: usize) -> usize {
+    in_width * SAMPLES_PER_PIXEL / SAMPLES_PER_OUTPUT
+}
+
+/// Knobs for the NTSC filter, named after the ones in blargg's nes_ntsc.
+/// Every knob is in the range -1.0 to 1.0, 0.0 is "normal".
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NtscSetup {
+    /// Hue rotation, -1.0 = -180 degrees, 1.0 = +180 degrees.
+    pub hue:            f32,
+    /// -1.0 = grayscale, 1.0 = oversaturated.
+    pub saturation:     f32,
+    pub contrast:       f32,
+    pub brightness:     f32,
+    /// Edge contrast enhancement/blurring of the luma.
+    pub sharpness:      f32,
+    /// How much of the color subcarrier bleeds into luma (-1.0 = none).
+    pub artifacts:      f32,
+    /// How much luma detail bleeds into color (-1.0 = none).
+    pub fringing:       f32,
+    /// Averages every burst phase a frame can start on, which hides dot
+    ///  crawl (every frame comes out the same) at the cost of some blur.
+    pub merge_fields:   bool,
+}
+
+impl NtscSetup {
+    /// Regular composite video, the default.
+    pub fn composite() -> NtscSetup {
+        NtscSetup {
+            hue: 0.0, saturation: 0.0, contrast: 0.0, brightness: 0.0,
+            sharpness: 0.0, artifacts: 0.0, fringing: 0.0, merge_fields: false,
+        }
+    }
+    /// S-Video: color and luma are separate, so no artifacts or fringing.
+    pub fn svideo() -> NtscSetup {
+        NtscSetup { sharpness: 0.2, artifacts: -1.0, fringing: -1.0, ..NtscSetup::composite() }
+    }
+    /// RF: blurrier and noisier than composite.
+    pub fn rf() -> NtscSetup {
+        NtscSetup { sharpness: -0.2, artifacts: 0.5, fringing: 0.5, ..NtscSetup::composite() }
+    }
+}
+
+impl Default for NtscSetup {
+    fn default() -> NtscSetup { NtscSetup::composite() }
+}
+
+/// Signal-level NTSC composite filter.
+/// Produces ntsc_output_width(width) pixels per line and the same number of
+///  lines as the input.
+pub struct NtscFilter {
+    pub setup:          NtscSetup,
+    /// Current burst phase, 0..3. Changes every frame, which causes dot crawl.
+    pub burst_phase:    usize,
+    //Per-line scratch buffers, kept around to avoid reallocating every frame.
+    signal:             Vec<f32>,
+    luma_sum:           Vec<f32>,
+}
+
+impl NtscFilter {
+    pub fn new(setup: NtscSetup) -> NtscFilter {
+        NtscFilter {
+            setup,
+            burst_phase:    0,
+            signal:         Vec::new(),
+            luma_sum:       Vec::new(),
+        }
+    }
+
+    /// Advances the burst phase, this should be called once per frame.
+    pub fn next_frame(&mut self) {
+        self.burst_phase = (self.burst_phase + 1) % 3;
+    }
+
+    /// Filters a raw frame into RGB.
+    pub fn filter(&mut self, raw: &[u16], width: usize, height: usize) -> Vec<u32> {
+        assert_eq!(raw.len(), width * height, "Input buffer does not match dimensions.");
+        let out_w = ntsc_output_width(width);
+        let mut out = vec![0; out_w * height];
+
+        let mut line = vec![[0.0f32; 3]; out_w];
+        for y in 0..height {
+            let row = &raw[y * width..(y + 1) * width];
+            let line_phase = y * LINE_PHASE_SHIFT;
+            if self.setup.merge_fields {
+                //Always in the same order, so the burst phase can't matter.
+                for (n, shift) in (0..SAMPLES_PER_CYCLE).step_by(LINE_PHASE_SHIFT).enumerate() {
+                    let phase = (line_phase + shift) % SAMPLES_PER_CYCLE;
+                    self.decode_line(row, phase, &mut line, 1.0 / (n + 1) as f32);
+                }
+            }
+            else {
+                let phase = (self.burst_phase * LINE_PHASE_SHIFT + line_phase) % SAMPLES_PER_CYCLE;
+                self.decode_line(row, phase, &mut line, 1.0);
+            }
+
+            for (o, rgb) in out[y * out_w..(y + 1) * out_w].iter_mut().zip(line.iter()) {
+                *o = pack_rgb(rgb[0], rgb[1], rgb[2]);
+            }
+        }
+        out
+    }
+
+    /// Generates and decodes one scanline starting at the given phase.
+    /// The result is mixed into out by weight, 1.0 replaces what's there.
+    fn decode_line(&mut self, row: &[u16], phase: usize, out: &mut [[f32; 3]], weight: f32) {
+        let s = self.setup;
+        let n = row.len() * SAMPLES_PER_PIXEL;
+
+        //Composite signal for the whole line.
+        self.signal.clear();
+        for (x, &px) in row.iter().enumerate() {
+            for k in 0..SAMPLES_PER_PIXEL {
+                let p = (phase + x * SAMPLES_PER_PIXEL + k) % SAMPLES_PER_CYCLE;
+                self.signal.push(ntsc_signal(px, p));
+            }
+        }
+
+        //Prefix sums, so that box averages are O(1).
+        self.luma_sum.clear();
+        self.luma_sum.push(0.0);
+        for i in 0..n {
+            let last = self.luma_sum[i];
+            self.luma_sum.push(last + self.signal[i]);
+        }
+        let signal = &self.signal;
+        let sums = &self.luma_sum;
+        let box_avg = |center: usize, len: usize| -> f32 {
+            let lo = (center + 1).saturating_sub(len / 2 + 1).min(n);
+            let hi = (lo + len).min(n);
+            if hi == lo { 0.0 } else { (sums[hi] - sums[lo]) / (hi - lo) as f32 }
+        };
+
+        let artifact_amt = (s.artifacts + 1.0) * 0.5 * 0.6;
+        let fringe_amt = (s.fringing + 1.0) * 0.5;
+        let hue = s.hue * 6.0 + HUE_FUDGE;
+        let saturation = s.saturation + 1.0;
+        let contrast = s.contrast + 1.0;
+
+        //Luma pass, needs to be done first for the sharpness kernel.
+        let out_w = out.len();
+        let mut luma = vec![0.0f32; out_w];
+        for (k, y) in luma.iter_mut().enumerate() {
+            let c = k * SAMPLES_PER_OUTPUT + SAMPLES_PER_OUTPUT / 2;
+            let clean = box_avg(c, SAMPLES_PER_CYCLE);
+            //A short window still carries part of the subcarrier: artifacts.
+            let dirty = box_avg(c, SAMPLES_PER_OUTPUT);
+            *y = clean + artifact_amt * (dirty - clean);
+        }
+
+        for k in 0..out_w {
+            let c = k * SAMPLES_PER_OUTPUT + SAMPLES_PER_OUTPUT / 2;
+
+            let left = luma[k.saturating_sub(1)];
+            let right = luma[(k + 1).min(out_w - 1)];
+            let y = luma[k] + s.sharpness * (luma[k] - (left + right) * 0.5);
+
+            //Chroma demodulation over one subcarrier cycle.
+            let start = (c + 1).saturating_sub(SAMPLES_PER_CYCLE / 2 + 1);
+            let end = (start + SAMPLES_PER_CYCLE).min(n);
+            let (mut i, mut q) = (0.0, 0.0);
+            for (p, &sample) in signal.iter().enumerate().take(end).skip(start) {
+                //Removing the local luma keeps edges from leaking into chroma.
+                let level = sample - (1.0 - fringe_amt) * box_avg(p, SAMPLES_PER_CYCLE);
+                let angle = PI * ((phase + p) as f32 + hue) / 6.0;
+                i += level * angle.cos();
+                q += level * angle.sin();
+            }
+            i = i / SAMPLES_PER_CYCLE as f32 * saturation;
+            q = q / SAMPLES_PER_CYCLE as f32 * saturation;
+            let y = y * contrast + s.brightness * 0.5;
+
+            let rgb = [
+                y + 0.946_882 * i + 0.623_557 * q,
+                y - 0.274_788 * i - 0.635_691 * q,
+                y - 1.108_545 * i + 1.709_007 * q,
+            ];
+            for ch in 0..3 {
+                out[k][ch] = out[k][ch] * (1.0 - weight) + rgb[ch] * weight;
+            }
+        }
+    }
+}
+
+/// Returns the normalized composite level of a raw pixel at a subcarrier phase.
+/// 0.0 is black, 1.0 is white.
+fn ntsc_signal(pixel: u16, phase: usize) -> f32 {
+    let in_phase = |color: usize| (color + phase) % 12 < 6;
+
+    let color = (pixel & 0x0F) as usize;
+    let mut level = ((pixel >> 4) & 3) as usize;
+    let emphasis = (pixel >> 6) & 7;
+    if color > 13 { level = 1; }
+
+    let mut low = LEVELS[level];
+    let mut high = LEVELS[4 + level];
+    if color == 0 { low = high; }
+    if color > 12 { high = low; }
+
+    let mut signal = if in_phase(color) { high } else { low };
+    if (emphasis & 1 != 0 && in_phase(0))
+        || (emphasis & 2 != 0 && in_phase(4))
+        || (emphasis & 4 != 0 && in_phase(8)) {
+        signal *= NTSC_EMPHASIS_ATTENUATION;
+    }
+
+    (signal - BLACK) / (WHITE - BLACK)
+}
+
+/// Packs three 0.0-1.0 channels into 0x00RRGGBB, clamping.
+fn pack_rgb(r: f32, g: f32, b: f32) -> u32 {
+    let to_u8 = |v: f32| (v.clamp(0.0, 1.0) * 255.0 + 0.5) as u32;
+    (to_u8(r) << 16) | (to_u8(g) << 8) | to_u8(b)
+}
+
+#[cfg(test)]
+#[path = "./filter_test.rs"]
+pub mod filter_test;
@@ -0,0 +1,87 @@
+/*  Unit test module of the video filters (filter.rs).
+ */
+use crate::core::filter::*;
+
+#[cfg(test)]
+pub mod filter_test {
+    use super::*;
+
+    #[test]
+    fn test_rgb_lookup(){
+        assert_eq!(raw_to_rgb(0x0F, &NES_PALETTE), 0x000000);
+        assert_eq!(raw_to_rgb(0x30, &NES_PALETTE), 0xFFFEFF);
+
+        //Emphasizing red dims green and blue, but not red.
+        let red = raw_to_rgb(0x30 | (1 << 6), &NES_PALETTE);
+        assert_eq!(red >> 16, 0xFF);
+        assert!(red & 0xFF < 0xFF);
+    }
+
+    #[test]
+    fn test_scale_nearest(){
+        let img = [1, 2, 3, 4];
+        let out = scale_nearest(&img, 2, 2, 2);
+        assert_eq!(out, vec![1, 1, 2, 2,
+                             1, 1, 2, 2,
+                             3, 3, 4, 4,
+                             3, 3, 4, 4]);
+    }
+
+    #[test]
+    fn test_scanlines(){
+        let out = scanlines(&[0xFFFFFF, 0x808080], 2, 1, 1.0);
+        assert_eq!(out, vec![0xFFFFFF, 0x808080, 0, 0]);
+
+        let out = scanlines(&[0xFFFFFF], 1, 1, 0.0);
+        assert_eq!(out, vec![0xFFFFFF, 0xFFFFFF]);
+    }
+
+    #[test]
+    fn test_ntsc_gray_is_gray(){
+        //Gray has no chroma, so every output pixel should be neutral.
+        let raw = vec![0x10u16; 16 * 4];
+        let mut ntsc = NtscFilter::new(NtscSetup::composite());
+        let out = ntsc.filter(&raw, 16, 4);
+
+        assert_eq!(out.len(), ntsc_output_width(16) * 4);
+        for &px in out[8..24].iter() {
+            let r = (px >> 16) & 0xFF;
+            let b = px & 0xFF;
+            assert!((r as i32 - b as i32).abs() <= 2, "Pixel {:06X} not gray", px);
+        }
+    }
+
+    #[test]
+    fn test_ntsc_dot_crawl(){
+        //A colored/black checker pattern changes between burst phases,
+        // unless the fields are merged.
+        let raw: Vec<u16> = (0..32 * 2).map(|i| if i % 2 == 0 { 0x16 } else { 0x0F }).collect();
+
+        let mut ntsc = NtscFilter::new(NtscSetup::composite());
+        let a = ntsc.filter(&raw, 32, 2);
+        ntsc.next_frame();
+        let b = ntsc.filter(&raw, 32, 2);
+        assert_ne!(a, b);
+
+        //Merged, every frame comes out the same.
+        let mut merged = NtscFilter::new(NtscSetup { merge_fields: true, ..NtscSetup::composite() });
+        let a = merged.filter(&raw, 32, 2);
+        for _ in 0..3 {
+            merged.next_frame();
+            assert_eq!(merged.filter(&raw, 32, 2), a);
+        }
+    }
+
+    #[test]
+    fn test_svideo_has_no_artifacts(){
+        //A flat color gets subcarrier stripes on composite, but not on
+        // S-Video. The line ends are left out, the filters fade in there.
+        let raw = vec![0x16u16; 32];
+        let flat = |out: &[u32]| out[8..56].iter().all(|&px| px == out[8]);
+
+        let composite = NtscFilter::new(NtscSetup::composite()).filter(&raw, 32, 1);
+        assert!(!flat(&composite));
+        let svideo = NtscFilter::new(NtscSetup::svideo()).filter(&raw, 32, 1);
+        assert!(flat(&svideo), "{:06X?}", &svideo[8..56]);
+    }
+}
@@ -0,0 +1,244 @@
+/* The 16 byte header at the start of .nes files, iNES or NES 2.0.
+ * iNES only has room for an 8-bit mapper number and ROM sizes in 16/8kb
+ *  units. NES 2.0 keeps the same layout, but uses the bytes iNES left as
+ *  padding for everything else a board needs:
+ *
+ * 0-3  "NES" $1A
+ * 4    PRG-ROM size LSB (16kb units)
+ * 5    CHR-ROM size LSB (8kb units)
+ * 6    MMMM FTBM   Mapper D0-D3, Four screen, Trainer, Battery, Mirroring
+ * 7    MMMM 10TT   Mapper D4-D7, NES 2.0 id (10), console Type
+ * 8    SSSS MMMM   Submapper, mapper D8-D11
+ * 9    CCCC PPPP   CHR-ROM and PRG-ROM size MSBs. $F means the LSB is an
+ *                   exponent-multiplier: EEEE EEMM, 2^E * (MM*2+1) bytes.
+ * 10   pppp PPPP   PRG-NVRAM and PRG-RAM shift counts, 64 << n bytes (0 = none)
+ * 11   cccc CCCC   CHR-NVRAM and CHR-RAM shift counts
+ * 12   ---- --TT   CPU/PPU timing: NTSC, PAL, multi-region, Dendy
+ * 13   HHHH PPPP   Vs. System hardware and PPU type, or the extended console
+ *                   type in the low nibble
+ * 14   ---- --RR   Number of miscellaneous ROMs
+ * 15   --DD DDDD   Default expansion device
+ *
+ * Dumps from before iNES grew byte 7 often have junk in bytes 7-15, like a
+ *  ripper's "DiskDude!" signature. Taken at face value that garbles the
+ *  mapper's high nibble, so headers that aren't NES 2.0 and don't have
+ *  bytes 12-15 clear are read as archaic iNES: only bytes 4-6 count.
+ *
+ * https://wiki.nesdev.com/w/index.php/NES_2.0
+ * https://wiki.nesdev.com/w/index.php/INES
+ */
+
+use crate::core::mapper::Mirroring;
+
+pub const HEADER_SIZE: usize = 16;
+pub const TRAINER_SIZE: usize = 512;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HeaderFormat {
+    /// iNES 0.7, or an iNES header with junk after byte 6.
+    Archaic,
+    INes,
+    Nes2,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConsoleType {
+    Nes,
+    VsSystem,
+    Playchoice10,
+    /// Byte 13's extended console type (Famiclone, VT01, ...).
+    Extended(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Timing {
+    Ntsc,
+    Pal,
+    /// Runs on either.
+    MultiRegion,
+    Dendy,
+}
+
+/// A parsed header. Sizes are in bytes. iNES headers fill in what they
+///  can, and leave the NES 2.0 only fields at their defaults.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Header {
+    pub format:         HeaderFormat,
+    pub mapper:         u16,
+    pub submapper:      u8,
+    pub prg_rom_size:   usize,
+    pub chr_rom_size:   usize,
+    pub prg_ram_size:   usize,
+    /// Battery backed PRG-RAM.
+    pub prg_nvram_size: usize,
+    pub chr_ram_size:   usize,
+    pub chr_nvram_size: usize,
+    pub mirroring:      Mirroring,
+    pub battery:        bool,
+    pub trainer:        bool,
+    pub console:        ConsoleType,
+    pub timing:         Timing,
+    /// Vs. System PPU and hardware types (byte 13), when it is one.
+    pub vs_ppu:         u8,
+    pub vs_hardware:    u8,
+    pub misc_roms:      u8,
+    /// Default expansion device (byte 15), 0 is unspecified.
+    pub expansion:      u8,
+}
+
+impl Default for Header {
+    /// An empty NROM header, for carts that haven't been read yet.
+    fn default() -> Header {
+        Header {
+            format:         HeaderFormat::INes,
+            mapper:         0,
+            submapper:      0,
+            prg_rom_size:   0,
+            chr_rom_size:   0,
+            prg_ram_size:   0,
+            prg_nvram_size: 0,
+            chr_ram_size:   0,
+            chr_nvram_size: 0,
+            mirroring:      Mirroring::Horizontal,
+            battery:        false,
+            trainer:        false,
+            console:        ConsoleType::Nes,
+            timing:         Timing::Ntsc,
+            vs_ppu:         0,
+            vs_hardware:    0,
+            misc_roms:      0,
+            expansion:      0,
+        }
+    }
+}
+
+impl Header {
+    /// Parses the first 16 bytes of a ROM file.
+    pub fn parse(bytes: &[u8]) -> Result<Header, String> {
+        if bytes.len() < HEADER_SIZE {
+            return Err(format!("The header is only {} bytes.", bytes.len()));
+        }
+        if &bytes[0..4] != b"NES\x1A" {
+            return Err("Missing the \"NES\\x1A\" identifier.".to_string());
+        }
+        let flags6 = bytes[6];
+        let flags7 = bytes[7];
+        let mut header = Header {
+            format:         HeaderFormat::INes,
+            mapper:         ((flags7 & 0xF0) | (flags6 >> 4)) as u16,
+            submapper:      0,
+            prg_rom_size:   bytes[4] as usize * 0x4000,
+            chr_rom_size:   bytes[5] as usize * 0x2000,
+            prg_ram_size:   0,
+            prg_nvram_size: 0,
+            chr_ram_size:   0,
+            chr_nvram_size: 0,
+            mirroring:      Mirroring::from_header(flags6),
+            battery:        flags6 & 0x02 != 0,
+            trainer:        flags6 & 0x04 != 0,
+            console:        ConsoleType::Nes,
+            timing:         Timing::Ntsc,
+            vs_ppu:         0,
+            vs_hardware:    0,
+            misc_roms:      0,
+            expansion:      0,
+        };
+
+        if flags7 & 0x0C == 0x08 {
+            header.parse_nes2(bytes)?;
+        }
+        else if flags7 & 0x0C != 0 || bytes[12..16].iter().any(|&b| b != 0) {
+            //Archaic: byte 7 on can't be trusted, not even for the mapper.
+            header.format = HeaderFormat::Archaic;
+            header.mapper = (flags6 >> 4) as u16;
+            if header.battery { header.prg_nvram_size = 0x2000; } else { header.prg_ram_size = 0x2000; }
+            if header.chr_rom_size == 0 {
+                header.chr_ram_size = 0x2000;
+            }
+        }
+        else {
+            //iNES: byte 8 is PRG-RAM in 8kb units, with 0 meaning 8kb, and
+            // boards without CHR-ROM have 8kb of CHR-RAM.
+            let prg_ram = bytes[8].max(1) as usize * 0x2000;
+            if header.battery { header.prg_nvram_size = prg_ram; } else { header.prg_ram_size = prg_ram; }
+            if header.chr_rom_size == 0 {
+                header.chr_ram_size = 0x2000;
+            }
+            header.console = match flags7 & 0x03 {
+                1 => ConsoleType::VsSystem,
+                2 => ConsoleType::Playchoice10,
+                _ => ConsoleType::Nes,
+            };
+            if bytes[9] & 1 != 0 {
+                header.timing = Timing::Pal;
+            }
+        }
+        Ok(header)
+    }
+
+    fn parse_nes2(&mut self, bytes: &[u8]) -> Result<(), String> {
+        self.format = HeaderFormat::Nes2;
+        self.mapper |= ((bytes[8] & 0x0F) as u16) << 8;
+        self.submapper = bytes[8] >> 4;
+        self.prg_rom_size = rom_size(bytes[4], bytes[9] & 0x0F, 0x4000)
+            .ok_or("The PRG-ROM size is too big.")?;
+        self.chr_rom_size = rom_size(bytes[5], bytes[9] >> 4, 0x2000)
+            .ok_or("The CHR-ROM size is too big.")?;
+        self.prg_ram_size = ram_size(bytes[10] & 0x0F);
+        self.prg_nvram_size = ram_size(bytes[10] >> 4);
+        self.chr_ram_size = ram_size(bytes[11] & 0x0F);
+        self.chr_nvram_size = ram_size(bytes[11] >> 4);
+        self.timing = match bytes[12] & 0x03 {
+            0 => Timing::Ntsc,
+            1 => Timing::Pal,
+            2 => Timing::MultiRegion,
+            _ => Timing::Dendy,
+        };
+        self.console = match bytes[7] & 0x03 {
+            0 => ConsoleType::Nes,
+            1 => {
+                self.vs_ppu = bytes[13] & 0x0F;
+                self.vs_hardware = bytes[13] >> 4;
+                ConsoleType::VsSystem
+            }
+            2 => ConsoleType::Playchoice10,
+            _ => ConsoleType::Extended(bytes[13] & 0x0F),
+        };
+        self.misc_roms = bytes[14] & 0x03;
+        self.expansion = bytes[15] & 0x3F;
+        Ok(())
+    }
+
+    pub fn is_nes2(&self) -> bool {
+        self.format == HeaderFormat::Nes2
+    }
+
+    /// Where the PRG-ROM starts in the file.
+    pub fn prg_offset(&self) -> usize {
+        HEADER_SIZE + if self.trainer { TRAINER_SIZE } else { 0 }
+    }
+}
+
+/// A NES 2.0 ROM size, from its LSB, MSB nibble, and unit size. An MSB of
+///  $F makes the LSB an exponent and multiplier instead.
+fn rom_size(lsb: u8, msb: u8, unit: usize) -> Option<usize> {
+    if msb == 0x0F {
+        let exponent = (lsb >> 2) as u32;
+        let multiplier = (lsb & 0x03) as usize * 2 + 1;
+        1usize.checked_shl(exponent)
+            .filter(|_| exponent < usize::BITS - 3)
+            .map(|size| size * multiplier)
+    }
+    else {
+        Some((((msb as usize) << 8) | lsb as usize) * unit)
+    }
+}
+
+/// A NES 2.0 RAM size from its shift count.
+fn ram_size(shift: u8) -> usize {
+    if shift == 0 { 0 } else { 64 << shift }
+}
+
+#[cfg(test)]
+#[path = "./header_test.rs"]
+pub mod header_test;
@@ -0,0 +1,109 @@
+/*  Unit test module of the iNES/NES 2.0 header (header.rs).
+ */
+use crate::core::header::*;
+
+#[cfg(test)]
+pub mod header_test {
+    use super::*;
+    use crate::core::mapper::Mirroring;
+
+    fn header(bytes: &[u8]) -> [u8; 16] {
+        let mut head = [0; 16];
+        head[..4].copy_from_slice(b"NES\x1A");
+        head[4..4 + bytes.len()].copy_from_slice(bytes);
+        head
+    }
+
+    #[test]
+    fn test_ines(){
+        //Mapper 4, 128kb PRG, 128kb CHR, vertical, battery.
+        let h = Header::parse(&header(&[8, 16, 0x43, 0x00])).unwrap();
+        assert_eq!(h.format, HeaderFormat::INes);
+        assert_eq!(h.mapper, 4);
+        assert_eq!(h.prg_rom_size, 128 * 1024);
+        assert_eq!(h.chr_rom_size, 128 * 1024);
+        assert_eq!(h.mirroring, Mirroring::Vertical);
+        assert!(h.battery);
+        assert_eq!(h.prg_nvram_size, 8192);
+        assert_eq!(h.prg_ram_size, 0);
+        assert_eq!(h.chr_ram_size, 0);
+        assert_eq!(h.timing, Timing::Ntsc);
+
+        //No CHR-ROM means CHR-RAM. Trainer, four screen, PAL, Vs. System.
+        let h = Header::parse(&header(&[2, 0, 0x1C, 0x41, 2, 1])).unwrap();
+        assert_eq!(h.mapper, 0x41);
+        assert_eq!(h.chr_ram_size, 8192);
+        assert_eq!(h.prg_ram_size, 2 * 8192);
+        assert!(h.trainer);
+        assert_eq!(h.prg_offset(), 16 + 512);
+        assert_eq!(h.mirroring, Mirroring::FourScreen);
+        assert_eq!(h.console, ConsoleType::VsSystem);
+        assert_eq!(h.timing, Timing::Pal);
+
+        assert!(Header::parse(b"NES\x1A").is_err());
+        assert!(Header::parse(&[0; 16]).is_err());
+    }
+
+    #[test]
+    fn test_nes2(){
+        let h = Header::parse(&header(&[
+            0x02, 0x01,     //PRG LSB, CHR LSB
+            0x12,           //Mapper D0-D3 1, battery
+            0x48,           //Mapper D4-D7 4, NES 2.0
+            0x53,           //Submapper 5, mapper D8-D11 3
+            0x01,           //PRG MSB 1 ($102 * 16kb), CHR MSB 0
+            0x77,           //8kb PRG-RAM, 8kb PRG-NVRAM
+            0x07,           //8kb CHR-RAM
+            0x03,           //Dendy
+            0x00, 0x00,
+            0x08,           //Four Score expansion
+        ])).unwrap();
+        assert!(h.is_nes2());
+        assert_eq!(h.mapper, 0x341);
+        assert_eq!(h.submapper, 5);
+        assert_eq!(h.prg_rom_size, 0x102 * 0x4000);
+        assert_eq!(h.chr_rom_size, 0x2000);
+        assert_eq!(h.prg_ram_size, 8192);
+        assert_eq!(h.prg_nvram_size, 8192);
+        assert_eq!(h.chr_ram_size, 8192);
+        assert_eq!(h.chr_nvram_size, 0);
+        assert_eq!(h.timing, Timing::Dendy);
+        assert_eq!(h.expansion, 0x08);
+
+        //Exponent-multiplier sizes: 2^7 * 3 = 384 byte PRG, 2^10 * 1 CHR.
+        let h = Header::parse(&header(&[0x1D, 0x28, 0, 0x08, 0, 0xFF])).unwrap();
+        assert_eq!(h.prg_rom_size, 384);
+        assert_eq!(h.chr_rom_size, 1024);
+        assert!(Header::parse(&header(&[0xFC, 0, 0, 0x08, 0, 0x0F])).is_err());
+
+        //Vs. System PPU and hardware, and extended console types.
+        let h = Header::parse(&header(&[1, 1, 0, 0x09, 0, 0, 0, 0, 0, 0x23])).unwrap();
+        assert_eq!(h.console, ConsoleType::VsSystem);
+        assert_eq!((h.vs_ppu, h.vs_hardware), (3, 2));
+        let h = Header::parse(&header(&[1, 1, 0, 0x0B, 0, 0, 0, 0, 0, 0x05])).unwrap();
+        assert_eq!(h.console, ConsoleType::Extended(5));
+    }
+
+    #[test]
+    fn test_archaic(){
+        //"DiskDude!" over bytes 7-15: mapper 4, not $44.
+        let mut head = header(&[8, 16, 0x40]);
+        head[7..16].copy_from_slice(b"DiskDude!");
+        let h = Header::parse(&head).unwrap();
+        assert_eq!(h.format, HeaderFormat::Archaic);
+        assert_eq!(h.mapper, 4);
+        assert_eq!(h.console, ConsoleType::Nes);
+        assert_eq!(h.prg_ram_size, 8192);
+
+        //Junk only in bytes 12-15 is enough.
+        let h = Header::parse(&header(&[2, 1, 0x11, 0x10, 0, 0, 0, 0, 0, 0, 0, b'x'])).unwrap();
+        assert_eq!(h.format, HeaderFormat::Archaic);
+        assert_eq!(h.mapper, 1);
+        assert_eq!(h.mirroring, Mirroring::Vertical);
+
+        //A clean iNES header keeps its high nibble.
+        let h = Header::parse(&header(&[2, 1, 0x11, 0x10])).unwrap();
+        assert_eq!(h.format, HeaderFormat::INes);
+        assert_eq!(h.mapper, 0x11);
+    }
+}
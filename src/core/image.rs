@@ -0,0 +1,165 @@
+/* Minimal RGB image type, with PNG and PPM encoders.
+ * Used for headless screenshots, so frames can be dumped and compared by
+ *  hash without a window or any image crates.
+ * PNG output uses uncompressed (stored) deflate blocks. The files are larger
+ *  than they need to be, but every viewer reads them.
+ */
+
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::Path;
+
+use crate::core::checksum::{adler32, crc32, Crc32};
+use crate::core::filter::raw_to_rgb;
+
+/// An RGB image, pixels are packed as 0x00RRGGBB, row major.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub width:  usize,
+    pub height: usize,
+    pub pixels: Vec<u32>,
+}
+
+/// Image formats the encoders support.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImageFormat {
+    Png,
+    Ppm,
+}
+
+impl ImageFormat {
+    /// Picks a format from a file extension, PNG unless it says ppm.
+    pub fn from_path(path: &Path) -> ImageFormat {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("ppm") => ImageFormat::Ppm,
+            _ => ImageFormat::Png,
+        }
+    }
+}
+
+impl Image {
+    /// Creates a black image.
+    pub fn new(width: usize, height: usize) -> Image {
+        Image { width, height, pixels: vec![0; width * height] }
+    }
+
+    /// Wraps an existing RGB buffer.
+    pub fn from_rgb(width: usize, height: usize, pixels: Vec<u32>) -> Image {
+        assert_eq!(pixels.len(), width * height, "Pixel buffer does not match dimensions.");
+        Image { width, height, pixels }
+    }
+
+    /// Converts raw PPU output with the given palette.
+    pub fn from_raw(width: usize, height: usize, raw: &[u16], palette: &[u32; 64]) -> Image {
+        let pixels = raw.iter().map(|&p| raw_to_rgb(p, palette)).collect();
+        Image::from_rgb(width, height, pixels)
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> u32 {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, rgb: u32) {
+        if x < self.width && y < self.height {
+            self.pixels[y * self.width + x] = rgb;
+        }
+    }
+
+    /// Pixels as packed R, G, B bytes.
+    pub fn rgb_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.pixels.len() * 3);
+        for &px in &self.pixels {
+            out.push((px >> 16) as u8);
+            out.push((px >> 8) as u8);
+            out.push(px as u8);
+        }
+        out
+    }
+
+    /// CRC-32 of the pixel data (not of any file encoding), so that hashes
+    ///  stay stable if the encoders change.
+    pub fn crc32(&self) -> u32 {
+        crc32(&self.rgb_bytes())
+    }
+
+    /// Binary PPM (P6).
+    pub fn encode_ppm(&self) -> Vec<u8> {
+        let mut out = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        out.extend_from_slice(&self.rgb_bytes());
+        out
+    }
+
+    /// 8-bit truecolor PNG.
+    pub fn encode_png(&self) -> Vec<u8> {
+        let mut out = vec![0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+        let mut ihdr = Vec::with_capacity(13);
+        ihdr.extend_from_slice(&(self.width as u32).to_be_bytes());
+        ihdr.extend_from_slice(&(self.height as u32).to_be_bytes());
+        ihdr.extend_from_slice(&[8, 2, 0, 0, 0]); //depth, RGB, deflate, filter, no interlace
+        write_png_chunk(&mut out, b"IHDR", &ihdr);
+
+        //Every scanline is prefixed by its filter type, 0 = none.
+        let rgb = self.rgb_bytes();
+        let mut scanlines = Vec::with_capacity(rgb.len() + self.height);
+        for row in rgb.chunks(self.width * 3) {
+            scanlines.push(0);
+            scanlines.extend_from_slice(row);
+        }
+        write_png_chunk(&mut out, b"IDAT", &zlib_stored(&scanlines));
+        write_png_chunk(&mut out, b"IEND", &[]);
+        out
+    }
+
+    /// Encodes in the given format.
+    pub fn encode(&self, format: ImageFormat) -> Vec<u8> {
+        match format {
+            ImageFormat::Png => self.encode_png(),
+            ImageFormat::Ppm => self.encode_ppm(),
+        }
+    }
+
+    /// Writes the image, the format is picked from the file extension.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut f = File::create(path)?;
+        f.write_all(&self.encode(ImageFormat::from_path(path)))
+    }
+}
+
+fn write_png_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+
+    let mut crc = Crc32::new();
+    crc.update(kind);
+    crc.update(data);
+    out.extend_from_slice(&crc.finish().to_be_bytes());
+}
+
+/// Wraps data in a zlib stream made of uncompressed deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    const MAX_BLOCK: usize = 0xFFFF;
+    let mut out = vec![0x78, 0x01];
+
+    let mut blocks = data.chunks(MAX_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let len = block.len() as u16;
+        out.push(last as u8);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+#[cfg(test)]
+#[path = "./image_test.rs"]
+pub mod image_test;
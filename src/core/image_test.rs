@@ -0,0 +1,51 @@
+/*  Unit test module of the image encoders (image.rs).
+ */
+use crate::core::image::*;
+
+#[cfg(test)]
+pub mod image_test {
+    use super::*;
+    use crate::core::checksum::*;
+
+    #[test]
+    fn test_checksums(){
+        //Well known check values.
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E60398);
+        assert_eq!(md5(b""), [0xd4, 0x1d, 0x8c, 0xd9, 0x8f, 0x00, 0xb2, 0x04,
+                              0xe9, 0x80, 0x09, 0x98, 0xec, 0xf8, 0x42, 0x7e]);
+        assert_eq!(md5(&[b'a'; 100])[..4], [0x36, 0xa9, 0x2c, 0xc9]);
+        assert_eq!(sha1(b"abc")[..], [0xa9, 0x99, 0x3e, 0x36, 0x47, 0x06, 0x81, 0x6a, 0xba, 0x3e,
+                                      0x25, 0x71, 0x78, 0x50, 0xc2, 0x6c, 0x9c, 0xd0, 0xd8, 0x9d]);
+        assert_eq!(sha1(&[b'a'; 1000])[..4], [0x29, 0x1e, 0x9a, 0x6c]);
+    }
+
+    #[test]
+    fn test_ppm(){
+        let img = Image::from_rgb(2, 1, vec![0xFF0000, 0x0000FF]);
+        let ppm = img.encode_ppm();
+        assert!(ppm.starts_with(b"P6\n2 1\n255\n"));
+        assert_eq!(&ppm[ppm.len() - 6..], &[0xFF, 0, 0, 0, 0, 0xFF]);
+    }
+
+    #[test]
+    fn test_png(){
+        let img = Image::from_rgb(3, 2, vec![0x102030; 6]);
+        let png = img.encode_png();
+        assert_eq!(&png[..8], &[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]);
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(&png[png.len() - 8..png.len() - 4], b"IEND");
+
+        //IHDR crc covers the type and data.
+        let ihdr_crc = u32::from_be_bytes([png[29], png[30], png[31], png[32]]);
+        assert_eq!(ihdr_crc, crc32(&png[12..29]));
+    }
+
+    #[test]
+    fn test_format_from_path(){
+        use std::path::Path;
+        assert_eq!(ImageFormat::from_path(Path::new("a.PPM")), ImageFormat::Ppm);
+        assert_eq!(ImageFormat::from_path(Path::new("a.png")), ImageFormat::Png);
+        assert_eq!(ImageFormat::from_path(Path::new("a")), ImageFormat::Png);
+    }
+}
@@ -0,0 +1,568 @@
+/* Input devices, and the ports they plug into.
+ * The NES has two controller ports, read at $4016 and $4017. The Famicom
+ *  also has an expansion port, whose devices answer on the same two
+ *  registers (mostly on D1-D4, where the built in pads use D0).
+ * $4016 writes go to every device, they carry the strobe (OUT0) and two
+ *  more output lines (OUT1, OUT2) that some expansion devices use.
+ *
+ * Devices here besides the standard controller (controller.rs):
+ *  - Four Score: 4 players, the ports read 2 pads each and a signature.
+ *  - Zapper: light sense on D3 (0 = light), trigger on D4. The Famicom
+ *    one plugs into the expansion port, and only answers on $4017.
+ *  - Arkanoid "Vaus" paddle: button on D3, an inverted 8-bit knob
+ *    position shifted out on D4, MSB first. The Famicom version uses
+ *    the expansion port: button on $4016 D1, position on $4017 D1.
+ *  - Family BASIC keyboard (expansion port): a 9x8 key matrix, scanned a
+ *    half row at a time through the $4016 output lines.
+ *  - Power Pad (controller port) and Family Trainer (expansion port): the
+ *    same 12 button floor mat, serial on the NES, a matrix on the Famicom.
+ *
+ * Which devices are plugged in comes from an InputConfig, so it can be
+ *  set per game from the command line.
+ *
+ * http://wiki.nesdev.com/w/index.php/Input_devices
+ */
+
+use std::any::Any;
+
+use crate::core::controller::{Controller, OPEN_BUS};
+use crate::core::filter::{raw_to_rgb, NES_HEIGHT, NES_PALETTE, NES_WIDTH};
+use crate::core::ppu::PPU;
+
+/// Index of the Famicom expansion port, after ports 0 and 1.
+pub const PORT_EXPANSION: usize = 2;
+
+/// Anything that plugs into a controller port or the expansion port.
+pub trait InputDevice {
+    fn name(&self) -> &'static str;
+    /// A $4016 write: OUT0 (strobe) in bit 0, OUT1 and OUT2 above it.
+    fn write(&mut self, val: u8);
+    /// A read of $4016 (register 0) or $4017 (register 1). Returns the
+    ///  data lines D0-D4, without open bus. The PPU, and the palette its
+    ///  output is shown in, are there for devices that look at the screen.
+    fn read(&mut self, register: usize, ppu: &PPU, palette: &[u32; 64]) -> u8;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+//~PORTS~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+/// The two controller ports, and the expansion port.
+#[allow(non_snake_case)]
+pub struct INPUT {
+    pub ports:      [Box<dyn InputDevice>; 2],
+    pub expansion:  Option<Box<dyn InputDevice>>,
+    /// The palette the screen is drawn with (NES::palette()).
+    pub palette:    &'static [u32; 64],
+}
+
+impl INPUT {
+    /// Standard controllers in both ports, nothing in the expansion port.
+    pub fn new() -> INPUT {
+        INPUT {
+            ports:      [Box::new(Controller::new()), Box::new(Controller::new())],
+            expansion:  None,
+            palette:    &NES_PALETTE,
+        }
+    }
+
+    /// Plugs a device into port 0, 1 or PORT_EXPANSION.
+    pub fn plug(&mut self, port: usize, device: Box<dyn InputDevice>) {
+        if port == PORT_EXPANSION {
+            self.expansion = Some(device);
+        }
+        else {
+            self.ports[port] = device;
+        }
+    }
+
+    pub fn unplug_expansion(&mut self) {
+        self.expansion = None;
+    }
+
+    /// The device in a port, if it's a T.
+    pub fn device_mut<T: InputDevice + 'static>(&mut self, port: usize) -> Option<&mut T> {
+        let device = if port == PORT_EXPANSION { self.expansion.as_mut()? } else { &mut self.ports[port] };
+        device.as_any_mut().downcast_mut::<T>()
+    }
+
+    /// The pad for a player: players 0 and 1 are the ports, and with a
+    ///  Four Score plugged in, 2 and 3 are its second pads.
+    pub fn pad_mut(&mut self, player: usize) -> Option<&mut Controller> {
+        let port = player % 2;
+        if self.ports[port].as_any().is::<FourScore>() {
+            return self.device_mut::<FourScore>(port).and_then(|f| f.pads.get_mut(player / 2));
+        }
+        if player < 2 { self.device_mut::<Controller>(port) } else { None }
+    }
+
+    /// Sets a player's buttons (BUTTON_*), if they have a pad.
+    pub fn set_buttons(&mut self, player: usize, buttons: u8) {
+        if let Some(pad) = self.pad_mut(player) {
+            pad.buttons = buttons;
+        }
+    }
+
+    pub fn set_button(&mut self, player: usize, button: u8, pressed: bool) {
+        if let Some(pad) = self.pad_mut(player) {
+            pad.set_button(button, pressed);
+        }
+    }
+
+    /// $4016 write, seen by every device.
+    pub fn write(&mut self, val: u8) {
+        for port in self.ports.iter_mut() {
+            port.write(val);
+        }
+        if let Some(expansion) = self.expansion.as_mut() {
+            expansion.write(val);
+        }
+    }
+
+    /// $4016 (register 0) or $4017 (register 1) read.
+    pub fn read(&mut self, register: usize, ppu: &PPU) -> u8 {
+        let mut val = OPEN_BUS | (self.ports[register].read(register, ppu, self.palette) & 0x1F);
+        if let Some(expansion) = self.expansion.as_mut() {
+            val |= expansion.read(register, ppu, self.palette) & 0x1E;
+        }
+        val
+    }
+}
+
+impl Default for INPUT {
+    fn default() -> INPUT { INPUT::new() }
+}
+
+//~FOUR~SCORE~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+/// Signatures, sent after both pads (read LSB first).
+const FOUR_SCORE_SIGNATURE: [u32; 2] = [0x08, 0x04];
+
+/// One half of a Four Score: each port reads 8 bits from its first pad,
+///  8 from its second, then an 8 bit signature, then 1s.
+pub struct FourScore {
+    /// The first and second pad on this port (players 1/3 or 2/4).
+    pub pads:   [Controller; 2],
+    port:       usize,
+    shift:      u32,
+    reads:      u8,
+    strobe:     bool,
+}
+
+impl FourScore {
+    /// A Four Score takes both ports, so plug one in for each.
+    pub fn new(port: usize) -> FourScore {
+        FourScore { pads: [Controller::new(), Controller::new()], port, shift: 0, reads: 0, strobe: false }
+    }
+
+    fn reload(&mut self) {
+        self.shift = self.pads[0].buttons as u32
+                   | (self.pads[1].buttons as u32) << 8
+                   | FOUR_SCORE_SIGNATURE[self.port] << 16;
+        self.reads = 0;
+    }
+}
+
+impl InputDevice for FourScore {
+    fn name(&self) -> &'static str { "Four Score" }
+    fn write(&mut self, val: u8) {
+        self.strobe = val & 1 != 0;
+        if self.strobe {
+            self.reload();
+        }
+    }
+    fn read(&mut self, _register: usize, _ppu: &PPU, _palette: &[u32; 64]) -> u8 {
+        if self.strobe {
+            self.reload();
+            return self.pads[0].buttons & 1;
+        }
+        if self.reads >= 24 {
+            return 1;
+        }
+        let bit = (self.shift & 1) as u8;
+        self.shift >>= 1;
+        self.reads += 1;
+        bit
+    }
+    fn as_any(&self) -> &dyn Any { self }
+    fn as_any_mut(&mut self) -> &mut dyn Any { self }
+}
+
+//~ZAPPER~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+/// How many scanlines the Zapper's sensor keeps seeing a bright pixel
+///  after the beam has drawn it.
+pub const ZAPPER_LIGHT_LINES: u16 = 20;
+/// How bright (average of R, G and B) a pixel has to be to be seen.
+pub const ZAPPER_LIGHT_LEVEL: u32 = 0x80;
+
+/// The Zapper light gun.
+#[derive(Debug, Clone, Default)]
+pub struct Zapper {
+    /// Screen position it's pointed at, None when it's off screen.
+    pub aim:        Option<(usize, usize)>,
+    pub trigger:    bool,
+    pub famicom:    bool,
+}
+
+impl Zapper {
+    pub fn new(famicom: bool) -> Zapper {
+        Zapper { famicom, ..Zapper::default() }
+    }
+
+    /// True when the pixel aimed at was drawn bright a short time ago, in
+    ///  the palette the screen shows. The PPU is still drawing, so the frame
+    ///  it's drawing is what's checked.
+    pub fn light_sensed(&self, ppu: &PPU, palette: &[u32; 64]) -> bool {
+        let (x, y) = match self.aim {
+            Some((x, y)) if x < NES_WIDTH && y < NES_HEIGHT => (x, y),
+            _ => return false,
+        };
+        let scanline = ppu.scanline as usize;
+        //Pixels are drawn at dots 1-256.
+        let drawn = scanline > y || (scanline == y && ppu.cycle as usize > x);
+        if !drawn || scanline >= y + ZAPPER_LIGHT_LINES as usize || scanline >= NES_HEIGHT {
+            return false;
+        }
+        let rgb = raw_to_rgb(ppu.drawing_buffer()[y * NES_WIDTH + x], palette);
+        let level = ((rgb >> 16) & 0xFF) + ((rgb >> 8) & 0xFF) + (rgb & 0xFF);
+        level >= ZAPPER_LIGHT_LEVEL * 3
+    }
+}
+
+impl InputDevice for Zapper {
+    fn name(&self) -> &'static str { "Zapper" }
+    fn write(&mut self, _val: u8) {}
+    fn read(&mut self, register: usize, ppu: &PPU, palette: &[u32; 64]) -> u8 {
+        //On the expansion port, $4016 is the pads' alone.
+        if self.famicom && register == 0 {
+            return 0;
+        }
+        let light = if self.light_sensed(ppu, palette) { 0 } else { 0x08 };
+        let trigger = if self.trigger { 0x10 } else { 0 };
+        light | trigger
+    }
+    fn as_any(&self) -> &dyn Any { self }
+    fn as_any_mut(&mut self) -> &mut dyn Any { self }
+}
+
+//~PADDLE~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+/// Knob range of a real Vaus controller (games clamp to about this).
+pub const PADDLE_MIN: u8 = 98;
+pub const PADDLE_MAX: u8 = 242;
+
+/// The Arkanoid "Vaus" paddle, NES (controller port) or Famicom
+///  (expansion port) version.
+#[derive(Debug, Clone)]
+pub struct Paddle {
+    pub position:   u8,
+    pub button:     bool,
+    pub famicom:    bool,
+    shift:          u8,
+}
+
+impl Paddle {
+    pub fn new(famicom: bool) -> Paddle {
+        Paddle { position: PADDLE_MIN, button: false, famicom, shift: 0 }
+    }
+
+    fn next_bit(&mut self) -> u8 {
+        let bit = self.shift >> 7;
+        self.shift <<= 1;
+        bit
+    }
+}
+
+impl InputDevice for Paddle {
+    fn name(&self) -> &'static str { "Arkanoid paddle" }
+    fn write(&mut self, val: u8) {
+        //The position is latched (inverted) by the strobe.
+        if val & 1 != 0 {
+            self.shift = !self.position;
+        }
+    }
+    fn read(&mut self, register: usize, _ppu: &PPU, _palette: &[u32; 64]) -> u8 {
+        let button = self.button as u8;
+        match (self.famicom, register) {
+            (false, _) => (button << 3) | (self.next_bit() << 4),
+            (true, 0)  => button << 1,
+            (true, _)  => self.next_bit() << 1,
+        }
+    }
+    fn as_any(&self) -> &dyn Any { self }
+    fn as_any_mut(&mut self) -> &mut dyn Any { self }
+}
+
+//~KEYBOARD~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+/// Family BASIC keyboard matrix: 9 rows of two 4 key columns, the keys
+///  in each column are read on D1-D4 in this order.
+pub static KEYBOARD_MATRIX: [[[&str; 4]; 2]; 9] = [
+    [["]", "[", "RETURN", "F8"],        ["STOP", "YEN", "RSHIFT", "KANA"]],
+    [[";", ":", "@", "F7"],             ["^", "-", "/", "_"]],
+    [["K", "L", "O", "F6"],             ["0", "P", ",", "."]],
+    [["J", "U", "I", "F5"],             ["8", "9", "N", "M"]],
+    [["H", "G", "Y", "F4"],             ["6", "7", "V", "B"]],
+    [["D", "R", "T", "F3"],             ["4", "5", "C", "F"]],
+    [["A", "S", "W", "F2"],             ["3", "E", "Z", "X"]],
+    [["CTR", "Q", "ESC", "F1"],         ["2", "1", "GRPH", "LSHIFT"]],
+    [["CLR", "UP", "RIGHT", "LEFT"],    ["DOWN", "SPACE", "DEL", "INS"]],
+];
+
+/// The Family BASIC keyboard.
+/// $4016 write   ---- -KCR   K: enable, C: column, R: reset to row 0.
+///                           The row advances when C goes from 1 to 0.
+/// $4017 read    ---K KKK-   The 4 keys of the selected half row, 0 = pressed.
+#[derive(Debug, Clone, Default)]
+pub struct Keyboard {
+    /// Pressed keys, one bit per key in each half row (bit 0 = D1).
+    pub keys:   [[u8; 2]; 9],
+    row:        usize,
+    column:     usize,
+    enabled:    bool,
+}
+
+impl Keyboard {
+    pub fn new() -> Keyboard {
+        Keyboard::default()
+    }
+
+    /// Presses or releases a key by its KEYBOARD_MATRIX name. Returns
+    ///  false for names that aren't on the keyboard.
+    pub fn set_key(&mut self, name: &str, pressed: bool) -> bool {
+        for (row, columns) in KEYBOARD_MATRIX.iter().enumerate() {
+            for (column, keys) in columns.iter().enumerate() {
+                if let Some(bit) = keys.iter().position(|k| k.eq_ignore_ascii_case(name)) {
+                    if pressed { self.keys[row][column] |= 1 << bit; }
+                    else { self.keys[row][column] &= !(1 << bit); }
+                    return true;
+                }
+            }
+        }
+        false
+    }
+}
+
+impl InputDevice for Keyboard {
+    fn name(&self) -> &'static str { "Family BASIC keyboard" }
+    fn write(&mut self, val: u8) {
+        let column = ((val >> 1) & 1) as usize;
+        self.enabled = val & 0x04 != 0;
+        if self.enabled {
+            if val & 1 != 0 {
+                self.row = 0;
+            }
+            else if self.column == 1 && column == 0 {
+                //Past the last row, reads come back as no keys (row 9 is
+                // how software checks a keyboard is there).
+                self.row = (self.row + 1).min(KEYBOARD_MATRIX.len());
+            }
+        }
+        self.column = column;
+    }
+    fn read(&mut self, register: usize, _ppu: &PPU, _palette: &[u32; 64]) -> u8 {
+        if register == 0 || !self.enabled {
+            return 0;
+        }
+        let pressed = self.keys.get(self.row).map_or(0, |r| r[self.column]);
+        (!pressed & 0x0F) << 1
+    }
+    fn as_any(&self) -> &dyn Any { self }
+    fn as_any_mut(&mut self) -> &mut dyn Any { self }
+}
+
+//~POWER~PAD~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+/// NES Power Pad read order: 8 buttons on D3, then 4 on D4 (1-based).
+const POWER_PAD_D3: [u8; 8] = [2, 1, 5, 9, 6, 10, 11, 7];
+const POWER_PAD_D4: [u8; 4] = [4, 3, 12, 8];
+
+/// The Power Pad (NES, controller port) or Family Trainer (Famicom,
+///  expansion port) floor mat. Buttons are numbered 1-12, as printed on
+///  side B.
+/// NES: the strobe latches, then D3 and D4 shift out (1 = pressed).
+/// Famicom: $4016 bits 0-2 select rows, each 0 bit lets a row of 4
+///  buttons through to $4017 D1-D4 (0 = pressed): bit 0 is 4-1, bit 1
+///  is 8-5, bit 2 is 12-9.
+#[derive(Debug, Clone, Default)]
+pub struct PowerPad {
+    /// Bit n is button n + 1.
+    pub buttons:    u16,
+    pub famicom:    bool,
+    shift_d3:       u8,
+    shift_d4:       u8,
+    rows:           u8,
+}
+
+impl PowerPad {
+    pub fn new(famicom: bool) -> PowerPad {
+        PowerPad { famicom, rows: 0x07, ..PowerPad::default() }
+    }
+
+    /// Presses or releases a button, 1-12.
+    pub fn set_button(&mut self, button: u8, pressed: bool) {
+        if (1..=12).contains(&button) {
+            let bit = 1 << (button - 1);
+            if pressed { self.buttons |= bit; } else { self.buttons &= !bit; }
+        }
+    }
+
+    fn pressed(&self, button: u8) -> u8 {
+        ((self.buttons >> (button - 1)) & 1) as u8
+    }
+}
+
+impl InputDevice for PowerPad {
+    fn name(&self) -> &'static str {
+        if self.famicom { "Family Trainer" } else { "Power Pad" }
+    }
+    fn write(&mut self, val: u8) {
+        if self.famicom {
+            self.rows = val & 0x07;
+        }
+        else if val & 1 != 0 {
+            self.shift_d3 = POWER_PAD_D3.iter().enumerate()
+                .fold(0, |acc, (i, &b)| acc | (self.pressed(b) << i));
+            //Once the 4 D4 buttons are out, the rest read as pressed.
+            self.shift_d4 = POWER_PAD_D4.iter().enumerate()
+                .fold(0xF0, |acc, (i, &b)| acc | (self.pressed(b) << i));
+        }
+    }
+    fn read(&mut self, register: usize, _ppu: &PPU, _palette: &[u32; 64]) -> u8 {
+        if self.famicom {
+            if register == 0 {
+                return 0;
+            }
+            let mut released = 0x0F;
+            for row in 0..3 {
+                if self.rows & (1 << row) == 0 {
+                    for i in 0..4 {
+                        //Each row reads its buttons high to low, 4-1.
+                        released &= !(self.pressed(row * 4 + 4 - i) << i);
+                    }
+                }
+            }
+            return released << 1;
+        }
+        let out = ((self.shift_d3 & 1) << 3) | ((self.shift_d4 & 1) << 4);
+        self.shift_d3 = (self.shift_d3 >> 1) | 0x80;
+        self.shift_d4 = (self.shift_d4 >> 1) | 0x80;
+        out
+    }
+    fn as_any(&self) -> &dyn Any { self }
+    fn as_any_mut(&mut self) -> &mut dyn Any { self }
+}
+
+//~CONFIG~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+/// Every device that can be configured, by name.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeviceKind {
+    Controller,
+    FourScore,
+    Zapper,
+    Paddle,
+    FamicomPaddle,
+    Keyboard,
+    PowerPad,
+    FamilyTrainer,
+}
+
+impl DeviceKind {
+    pub const ALL: [DeviceKind; 8] = [DeviceKind::Controller, DeviceKind::FourScore,
+        DeviceKind::Zapper, DeviceKind::Paddle, DeviceKind::FamicomPaddle,
+        DeviceKind::Keyboard, DeviceKind::PowerPad, DeviceKind::FamilyTrainer];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DeviceKind::Controller      => "controller",
+            DeviceKind::FourScore       => "four-score",
+            DeviceKind::Zapper          => "zapper",
+            DeviceKind::Paddle          => "paddle",
+            DeviceKind::FamicomPaddle   => "famicom-paddle",
+            DeviceKind::Keyboard        => "keyboard",
+            DeviceKind::PowerPad        => "power-pad",
+            DeviceKind::FamilyTrainer   => "family-trainer",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<DeviceKind> {
+        DeviceKind::ALL.iter().cloned().find(|d| d.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Famicom devices only fit the expansion port, and the Four Score and
+    ///  NES devices only fit the controller ports. The Zapper fits both.
+    pub fn fits(self, port: usize) -> bool {
+        match self {
+            DeviceKind::FamicomPaddle | DeviceKind::Keyboard | DeviceKind::FamilyTrainer
+                => port == PORT_EXPANSION,
+            DeviceKind::Zapper => true,
+            _ => port != PORT_EXPANSION,
+        }
+    }
+
+    /// A new device of this kind, for a port.
+    pub fn create(self, port: usize) -> Box<dyn InputDevice> {
+        match self {
+            DeviceKind::Controller      => Box::new(Controller::new()),
+            DeviceKind::FourScore       => Box::new(FourScore::new(port % 2)),
+            DeviceKind::Zapper          => Box::new(Zapper::new(port == PORT_EXPANSION)),
+            DeviceKind::Paddle          => Box::new(Paddle::new(false)),
+            DeviceKind::FamicomPaddle   => Box::new(Paddle::new(true)),
+            DeviceKind::Keyboard        => Box::new(Keyboard::new()),
+            DeviceKind::PowerPad        => Box::new(PowerPad::new(false)),
+            DeviceKind::FamilyTrainer   => Box::new(PowerPad::new(true)),
+        }
+    }
+}
+
+/// What's plugged in where.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputConfig {
+    pub ports:      [DeviceKind; 2],
+    pub expansion:  Option<DeviceKind>,
+}
+
+impl InputConfig {
+    /// Two pads, empty expansion port.
+    pub fn new() -> InputConfig {
+        InputConfig { ports: [DeviceKind::Controller; 2], expansion: None }
+    }
+
+    /// Puts a device in port 0, 1 or PORT_EXPANSION. A Four Score fills
+    ///  both controller ports. Errors if the device doesn't fit there.
+    pub fn set(&mut self, port: usize, kind: DeviceKind) -> Result<(), String> {
+        if port > PORT_EXPANSION || !kind.fits(port) {
+            let name = if port == PORT_EXPANSION { "the expansion port".to_string() }
+                       else { format!("port {}", port + 1) };
+            return Err(format!("A {} doesn't fit {}.", kind.name(), name));
+        }
+        if port == PORT_EXPANSION {
+            self.expansion = Some(kind);
+        }
+        else if kind == DeviceKind::FourScore {
+            self.ports = [kind; 2];
+        }
+        else {
+            //Pulling one side of a Four Score out pulls the whole thing.
+            if self.ports[1 - port] == DeviceKind::FourScore {
+                self.ports[1 - port] = DeviceKind::Controller;
+            }
+            self.ports[port] = kind;
+        }
+        Ok(())
+    }
+
+    /// Plugs everything in, replacing what was there.
+    pub fn apply(&self, input: &mut INPUT) {
+        for (port, &kind) in self.ports.iter().enumerate() {
+            input.plug(port, kind.create(port));
+        }
+        match self.expansion {
+            Some(kind) => input.plug(PORT_EXPANSION, kind.create(PORT_EXPANSION)),
+            None => input.unplug_expansion(),
+        }
+    }
+}
+
+impl Default for InputConfig {
+    fn default() -> InputConfig { InputConfig::new() }
+}
+
+#[cfg(test)]
+#[path = "./input_test.rs"]
+pub mod input_test;
@@ -0,0 +1,243 @@
+/*  Unit test module of the input devices (input.rs).
+ */
+use crate::core::input::*;
+
+#[cfg(test)]
+pub mod input_test {
+    use super::*;
+    use crate::core::controller::*;
+    use crate::core::memory::MEM;
+
+    /// Strobes, then reads a register n times, keeping the given data bit.
+    fn read_bits(mem: &mut MEM, address: u16, n: usize, bit: u8) -> Vec<u8> {
+        mem.set(0x4016, 1);
+        mem.set(0x4016, 0);
+        (0..n).map(|_| (mem.get(address) >> bit) & 1).collect()
+    }
+
+    #[test]
+    fn test_four_score(){
+        let mut mem = MEM::new_empty();
+        mem.INPUT.plug(0, Box::new(FourScore::new(0)));
+        mem.INPUT.plug(1, Box::new(FourScore::new(1)));
+        mem.INPUT.set_buttons(0, BUTTON_A);
+        mem.INPUT.set_buttons(1, BUTTON_B);
+        mem.INPUT.set_buttons(2, BUTTON_START);
+        mem.INPUT.set_button(3, BUTTON_RIGHT, true);
+
+        let port1 = read_bits(&mut mem, 0x4016, 26, 0);
+        assert_eq!(&port1[0..8], &[1, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(&port1[8..16], &[0, 0, 0, 1, 0, 0, 0, 0]);
+        assert_eq!(&port1[16..24], &[0, 0, 0, 1, 0, 0, 0, 0]);   //Signature
+        assert_eq!(&port1[24..], &[1, 1]);
+
+        let port2 = read_bits(&mut mem, 0x4017, 24, 0);
+        assert_eq!(&port2[0..8], &[0, 1, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(&port2[8..16], &[0, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(&port2[16..24], &[0, 0, 1, 0, 0, 0, 0, 0]);
+
+        //Without one, there are no players 3 and 4.
+        let mut mem = MEM::new_empty();
+        assert!(mem.INPUT.pad_mut(2).is_none());
+        assert!(mem.INPUT.pad_mut(1).is_some());
+    }
+
+    #[test]
+    fn test_zapper(){
+        let mut mem = MEM::new_empty();
+        let mut zapper = Zapper::new(false);
+        zapper.trigger = true;
+        mem.INPUT.plug(1, Box::new(zapper));
+
+        //Off screen: no light (D3 high), trigger held (D4 high).
+        assert_eq!(mem.get(0x4017), 0x40 | 0x18);
+
+        //A white backdrop, drawn down to scanline 50.
+        mem.set(0x2006, 0x3F);
+        mem.set(0x2006, 0x00);
+        mem.set(0x2007, 0x30);
+        mem.set(0x2001, 0x0A);
+        while !(mem.PPU.frame >= 1 && mem.PPU.scanline == 50) {
+            mem.step_ppu();
+        }
+
+        let sees = |mem: &mut MEM, x, y| {
+            mem.INPUT.device_mut::<Zapper>(1).unwrap().aim = Some((x, y));
+            mem.get(0x4017) & 0x08 == 0
+        };
+        assert!(sees(&mut mem, 100, 40));
+        //Drawn too long ago, or not drawn yet.
+        assert!(!sees(&mut mem, 100, 10));
+        assert!(!sees(&mut mem, 100, 60));
+
+        //A black backdrop isn't seen. (Palette writes need rendering off.)
+        mem.set(0x2001, 0x00);
+        mem.set(0x2006, 0x3F);
+        mem.set(0x2006, 0x00);
+        mem.set(0x2007, 0x0F);
+        mem.set(0x2001, 0x0A);
+        let frame = mem.PPU.frame;
+        while !(mem.PPU.frame > frame && mem.PPU.scanline == 50) {
+            mem.step_ppu();
+        }
+        assert!(!sees(&mut mem, 100, 40));
+    }
+
+    #[test]
+    fn test_zapper_uses_screen_palette(){
+        use crate::core::filter::{NES_PALETTE, RP2C04_PALETTES};
+        let bright = |rgb: u32| ((rgb >> 16) & 0xFF) + ((rgb >> 8) & 0xFF) + (rgb & 0xFF)
+            >= ZAPPER_LIGHT_LEVEL * 3;
+        //A color that's bright on a NES, but dark on an RP2C04-0001.
+        let color = (0..64).find(|&i| bright(NES_PALETTE[i]) && !bright(RP2C04_PALETTES[0][i]))
+            .unwrap() as u8;
+
+        let mut mem = MEM::new_empty();
+        mem.INPUT.plug(1, Box::new(Zapper::new(false)));
+        mem.set(0x2006, 0x3F);
+        mem.set(0x2006, 0x00);
+        mem.set(0x2007, color);
+        mem.set(0x2001, 0x0A);
+        while !(mem.PPU.frame >= 1 && mem.PPU.scanline == 50) {
+            mem.step_ppu();
+        }
+        mem.INPUT.device_mut::<Zapper>(1).unwrap().aim = Some((100, 40));
+        assert_eq!(mem.get(0x4017) & 0x08, 0);
+
+        mem.INPUT.palette = &RP2C04_PALETTES[0];
+        assert_eq!(mem.get(0x4017) & 0x08, 0x08);
+    }
+
+    #[test]
+    fn test_famicom_zapper(){
+        let mut mem = MEM::new_empty();
+        let mut zapper = Zapper::new(true);
+        zapper.trigger = true;
+        mem.INPUT.plug(PORT_EXPANSION, Box::new(zapper));
+
+        //Light and trigger are on $4017 only, $4016 is just the pad.
+        assert_eq!(mem.get(0x4016), 0x40);
+        assert_eq!(mem.get(0x4017), 0x40 | 0x18);
+
+        //A Zapper configured into the expansion port is a Famicom one.
+        let zapper = DeviceKind::Zapper.create(PORT_EXPANSION);
+        assert!(zapper.as_any().downcast_ref::<Zapper>().unwrap().famicom);
+    }
+
+    #[test]
+    fn test_paddle(){
+        let mut mem = MEM::new_empty();
+        let mut paddle = Paddle::new(false);
+        paddle.position = 0xA5;
+        paddle.button = true;
+        mem.INPUT.plug(1, Box::new(paddle));
+
+        //The position comes out inverted, MSB first, on D4.
+        let bits = read_bits(&mut mem, 0x4017, 8, 4);
+        assert_eq!(bits, vec![0, 1, 0, 1, 1, 0, 1, 0]);
+        assert_eq!(mem.get(0x4017) & 0x08, 0x08);
+
+        //The Famicom one sits in the expansion port, next to the pads.
+        let mut mem = MEM::new_empty();
+        let mut paddle = Paddle::new(true);
+        paddle.position = 0xF0;
+        mem.INPUT.plug(PORT_EXPANSION, Box::new(paddle));
+        mem.INPUT.set_buttons(0, BUTTON_A);
+        mem.INPUT.device_mut::<Paddle>(PORT_EXPANSION).unwrap().button = true;
+        mem.set(0x4016, 1);
+        mem.set(0x4016, 0);
+        assert_eq!(mem.get(0x4016), 0x40 | 0x02 | 0x01);
+        let bits: Vec<u8> = (0..8).map(|_| (mem.get(0x4017) >> 1) & 1).collect();
+        assert_eq!(bits, vec![0, 0, 0, 0, 1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn test_keyboard(){
+        let mut mem = MEM::new_empty();
+        mem.INPUT.plug(PORT_EXPANSION, Box::new(Keyboard::new()));
+        let kb = mem.INPUT.device_mut::<Keyboard>(PORT_EXPANSION).unwrap();
+        assert!(kb.set_key("return", true));
+        assert!(kb.set_key("X", true));
+        assert!(kb.set_key("SPACE", true));
+        assert!(!kb.set_key("NOPE", true));
+
+        //Disabled, it reads nothing.
+        mem.set(0x4016, 0x00);
+        assert_eq!(mem.get(0x4017) & 0x1E, 0x00);
+
+        //Reset to row 0, then walk every half row (0 = pressed).
+        mem.set(0x4016, 0x05);
+        let mut scan = Vec::new();
+        for _ in 0..10 {
+            mem.set(0x4016, 0x04);
+            scan.push((mem.get(0x4017) >> 1) & 0x0F);
+            mem.set(0x4016, 0x06);
+            scan.push((mem.get(0x4017) >> 1) & 0x0F);
+        }
+        assert_eq!(scan[0], 0x0B);      //RETURN
+        assert_eq!(scan[13], 0x07);     //X
+        assert_eq!(scan[17], 0x0D);     //SPACE
+        assert!(scan.iter().enumerate()
+            .all(|(i, &k)| [0, 13, 17].contains(&i) || k == 0x0F));
+        //Row 9 reads as all released, how games find the keyboard.
+        assert_eq!(&scan[18..], &[0x0F, 0x0F]);
+    }
+
+    #[test]
+    fn test_power_pad(){
+        let mut mem = MEM::new_empty();
+        let mut pad = PowerPad::new(false);
+        pad.set_button(1, true);
+        pad.set_button(3, true);
+        pad.set_button(9, true);
+        mem.INPUT.plug(1, Box::new(pad));
+
+        mem.set(0x4016, 1);
+        mem.set(0x4016, 0);
+        let reads: Vec<u8> = (0..9).map(|_| mem.get(0x4017)).collect();
+        let d3: Vec<u8> = reads.iter().map(|r| (r >> 3) & 1).collect();
+        let d4: Vec<u8> = reads.iter().map(|r| (r >> 4) & 1).collect();
+        //D3: 2 1 5 9 6 10 11 7, D4: 4 3 12 8, then 1s.
+        assert_eq!(d3, vec![0, 1, 0, 1, 0, 0, 0, 0, 1]);
+        assert_eq!(d4, vec![0, 1, 0, 0, 1, 1, 1, 1, 1]);
+
+        //The Family Trainer: a 0 bit selects each row of 4.
+        let mut mem = MEM::new_empty();
+        let mut mat = PowerPad::new(true);
+        mat.set_button(1, true);
+        mat.set_button(7, true);
+        mem.INPUT.plug(PORT_EXPANSION, Box::new(mat));
+        mem.set(0x4016, 0x06);
+        assert_eq!((mem.get(0x4017) >> 1) & 0x0F, 0x07);    //4 3 2 [1]
+        mem.set(0x4016, 0x05);
+        assert_eq!((mem.get(0x4017) >> 1) & 0x0F, 0x0D);    //8 [7] 6 5
+        mem.set(0x4016, 0x03);
+        assert_eq!((mem.get(0x4017) >> 1) & 0x0F, 0x0F);
+        mem.set(0x4016, 0x07);
+        assert_eq!((mem.get(0x4017) >> 1) & 0x0F, 0x0F);
+    }
+
+    #[test]
+    fn test_input_config(){
+        assert_eq!(DeviceKind::from_name("Power-Pad"), Some(DeviceKind::PowerPad));
+        assert_eq!(DeviceKind::from_name("joystick"), None);
+
+        let mut config = InputConfig::new();
+        assert!(config.set(PORT_EXPANSION, DeviceKind::PowerPad).is_err());
+        assert!(config.set(0, DeviceKind::Keyboard).is_err());
+        config.set(PORT_EXPANSION, DeviceKind::Keyboard).unwrap();
+        config.set(0, DeviceKind::FourScore).unwrap();
+        assert_eq!(config.ports, [DeviceKind::FourScore; 2]);
+        config.set(1, DeviceKind::Zapper).unwrap();
+        assert_eq!(config.ports, [DeviceKind::Controller, DeviceKind::Zapper]);
+
+        let mut mem = MEM::new_empty();
+        config.apply(&mut mem.INPUT);
+        assert!(mem.INPUT.device_mut::<Controller>(0).is_some());
+        assert!(mem.INPUT.device_mut::<Zapper>(1).is_some());
+        assert!(mem.INPUT.device_mut::<Keyboard>(PORT_EXPANSION).is_some());
+
+        InputConfig::new().apply(&mut mem.INPUT);
+        assert!(mem.INPUT.device_mut::<Keyboard>(PORT_EXPANSION).is_none());
+    }
+}
@@ -0,0 +1,257 @@
+/* Maps host keys and buttons to NES buttons, with turbo.
+ * This sits in front of the controllers: the frontend says which host keys
+ *  are down (by name, whatever it calls them), and once a frame the mapper
+ *  works out what each player is holding. Games only ever see the pads.
+ *
+ * Maps are loaded from a small text file, so setups can be shared:
+ *
+ *   # Lines starting with # or ; are comments.
+ *   [player1]
+ *   a = x, j            # Any number of keys per button.
+ *   b = z
+ *   select = rshift
+ *   start = return
+ *   up = up
+ *   turbo a = s         # Held, it presses and releases A over and over.
+ *   turbo rate = 15     # Turbo presses per second, for all of this player's
+ *   turbo rate a = 30   #  turbo buttons, or just one of them.
+ *   [player2]
+ *   a = pad1.button0
+ *
+ * Key names aren't case sensitive. Rates are per second at 60 frames a
+ *  second, and get rounded to a whole number of frames: 30 is the fastest.
+ */
+
+use std::fmt::Write as FmtWrite;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::core::controller::*;
+
+/// Turbo presses per second, when a map doesn't say.
+pub const DEFAULT_TURBO_RATE: u32 = 15;
+
+/// Button names in BUTTON_* bit order.
+pub const BUTTON_NAMES: [&str; 8] = ["a", "b", "select", "start", "up", "down", "left", "right"];
+
+/// BUTTON_* bit for a name.
+pub fn button_from_name(name: &str) -> Option<u8> {
+    BUTTON_NAMES.iter().position(|b| b.eq_ignore_ascii_case(name)).map(|i| 1 << i)
+}
+
+/// One host key, pressing one player's button.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Binding {
+    /// Lower case host key name.
+    pub key:    String,
+    /// 0-3.
+    pub player: usize,
+    /// A BUTTON_* bit.
+    pub button: u8,
+    pub turbo:  bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputMap {
+    pub bindings:       Vec<Binding>,
+    /// Turbo presses per second, by player and button bit.
+    pub turbo_rates:    [[u32; 8]; 4],
+}
+
+impl Default for InputMap {
+    /// Player 1 on the arrow keys, Z/X for B/A, right shift and return for
+    ///  select and start.
+    fn default() -> InputMap {
+        let mut map = InputMap::empty();
+        let keys = [("x", BUTTON_A), ("z", BUTTON_B), ("rshift", BUTTON_SELECT),
+                    ("return", BUTTON_START), ("up", BUTTON_UP), ("down", BUTTON_DOWN),
+                    ("left", BUTTON_LEFT), ("right", BUTTON_RIGHT)];
+        for &(key, button) in keys.iter() {
+            map.bind(key, 0, button, false);
+        }
+        map.bind("s", 0, BUTTON_A, true);
+        map.bind("a", 0, BUTTON_B, true);
+        map
+    }
+}
+
+impl InputMap {
+    /// A map with nothing bound.
+    pub fn empty() -> InputMap {
+        InputMap {
+            bindings:       Vec::new(),
+            turbo_rates:    [[DEFAULT_TURBO_RATE; 8]; 4],
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<InputMap> {
+        let text = fs::read_to_string(path)?;
+        InputMap::parse(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_config())
+    }
+
+    /// Adds a binding. A key can press several buttons.
+    pub fn bind(&mut self, key: &str, player: usize, button: u8, turbo: bool) {
+        let binding = Binding { key: key.to_ascii_lowercase(), player, button, turbo };
+        if !self.bindings.contains(&binding) {
+            self.bindings.push(binding);
+        }
+    }
+
+    /// Removes everything bound to a key.
+    pub fn unbind(&mut self, key: &str) {
+        self.bindings.retain(|b| !b.key.eq_ignore_ascii_case(key));
+    }
+
+    /// How many frames turbo holds a button down (and then up) for.
+    pub fn turbo_frames(&self, player: usize, button: u8) -> u64 {
+        let rate = self.turbo_rates[player][button.trailing_zeros() as usize].max(1);
+        ((30 + rate / 2) / rate).max(1) as u64
+    }
+
+    /// Parses the text of a map file.
+    pub fn parse(text: &str) -> Result<InputMap, String> {
+        let mut map = InputMap::empty();
+        let mut player = None;
+        for (n, line) in text.lines().enumerate() {
+            let line = line.split(['#', ';']).next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |what: &str| format!("{} on line {}: {}", what, n + 1, line);
+            if line.starts_with('[') && line.ends_with(']') {
+                let section = line[1..line.len() - 1].trim().to_ascii_lowercase();
+                player = match section.strip_prefix("player").and_then(|p| p.parse::<usize>().ok()) {
+                    Some(p) if (1..=4).contains(&p) => Some(p - 1),
+                    _ => return Err(error("Unknown section")),
+                };
+                continue;
+            }
+            let player = player.ok_or_else(|| error("Binding outside of a [playerN] section"))?;
+            let mut parts = line.splitn(2, '=');
+            let left: Vec<&str> = parts.next().unwrap_or("").split_whitespace().collect();
+            let right = parts.next().ok_or_else(|| error("Missing '='"))?.trim();
+
+            match left.as_slice() {
+                ["turbo", "rate"] | ["turbo", "rate", _] => {
+                    let rate: u32 = right.parse().ok().filter(|&r| r > 0)
+                        .ok_or_else(|| error("Bad turbo rate"))?;
+                    match left.get(2) {
+                        Some(name) => {
+                            let button = button_from_name(name).ok_or_else(|| error("Unknown button"))?;
+                            map.turbo_rates[player][button.trailing_zeros() as usize] = rate;
+                        }
+                        None => map.turbo_rates[player] = [rate; 8],
+                    }
+                }
+                ["turbo", name] | [name] => {
+                    let button = button_from_name(name).ok_or_else(|| error("Unknown button"))?;
+                    for key in right.split(',').map(str::trim).filter(|k| !k.is_empty()) {
+                        map.bind(key, player, button, left.len() == 2);
+                    }
+                }
+                _ => return Err(error("Bad binding")),
+            }
+        }
+        Ok(map)
+    }
+
+    /// Writes the map in the format parse() reads.
+    pub fn to_config(&self) -> String {
+        let mut out = String::new();
+        for player in 0..4 {
+            if !self.bindings.iter().any(|b| b.player == player) {
+                continue;
+            }
+            let _ = writeln!(out, "[player{}]", player + 1);
+            for &turbo in [false, true].iter() {
+                for (bit, name) in BUTTON_NAMES.iter().enumerate() {
+                    let keys: Vec<&str> = self.bindings.iter()
+                        .filter(|b| b.player == player && b.button == 1 << bit && b.turbo == turbo)
+                        .map(|b| b.key.as_str())
+                        .collect();
+                    if !keys.is_empty() {
+                        let _ = writeln!(out, "{}{} = {}", if turbo { "turbo " } else { "" },
+                                         name, keys.join(", "));
+                    }
+                }
+            }
+            for (bit, name) in BUTTON_NAMES.iter().enumerate() {
+                let rate = self.turbo_rates[player][bit];
+                if rate != DEFAULT_TURBO_RATE {
+                    let _ = writeln!(out, "turbo rate {} = {}", name, rate);
+                }
+            }
+        }
+        out
+    }
+}
+
+//~MAPPER~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+/// Tracks which host keys are down, and turns them into pad buttons.
+#[derive(Debug, Clone)]
+pub struct InputMapper {
+    pub map:    InputMap,
+    /// Keys that are down, with the frame they went down on (turbo
+    ///  starts pressed).
+    held:       Vec<(String, u64)>,
+    frame:      u64,
+}
+
+impl InputMapper {
+    pub fn new(map: InputMap) -> InputMapper {
+        InputMapper { map, held: Vec::new(), frame: 0 }
+    }
+
+    pub fn key_down(&mut self, key: &str) {
+        let key = key.to_ascii_lowercase();
+        if !self.held.iter().any(|(k, _)| *k == key) {
+            self.held.push((key, self.frame));
+        }
+    }
+
+    pub fn key_up(&mut self, key: &str) {
+        self.held.retain(|(k, _)| !k.eq_ignore_ascii_case(key));
+    }
+
+    /// Lets go of everything, e.g. when the window loses focus.
+    pub fn release_all(&mut self) {
+        self.held.clear();
+    }
+
+    pub fn is_down(&self, key: &str) -> bool {
+        self.held.iter().any(|(k, _)| k.eq_ignore_ascii_case(key))
+    }
+
+    /// Moves to a new frame, for turbo timing.
+    pub fn set_frame(&mut self, frame: u64) {
+        self.frame = frame;
+    }
+
+    /// What each of the 4 players is holding this frame.
+    pub fn buttons(&self) -> [u8; 4] {
+        let mut buttons = [0; 4];
+        for binding in &self.map.bindings {
+            let pressed_at = match self.held.iter().find(|(k, _)| *k == binding.key) {
+                Some(&(_, frame)) => frame,
+                None => continue,
+            };
+            if binding.turbo {
+                let frames = self.map.turbo_frames(binding.player, binding.button);
+                if !(self.frame.saturating_sub(pressed_at) / frames).is_multiple_of(2) {
+                    continue;
+                }
+            }
+            buttons[binding.player] |= binding.button;
+        }
+        buttons
+    }
+}
+
+#[cfg(test)]
+#[path = "./keymap_test.rs"]
+pub mod keymap_test;
@@ -0,0 +1,92 @@
+/*  Unit test module of the host key map and turbo (keymap.rs).
+ */
+use crate::core::keymap::*;
+
+#[cfg(test)]
+pub mod keymap_test {
+    use super::*;
+    use crate::core::nes::NES;
+    use crate::core::ppu::ppu_test::ppu_test::test_rom;
+
+    const CONFIG: &str = "# Shared setup\n\
+                          [Player1]\n\
+                          a = X, j   ; two keys\n\
+                          b = z\n\
+                          turbo a = s\n\
+                          turbo rate = 10\n\
+                          turbo rate a = 30\n\
+                          [player2]\n\
+                          start = pad1.button9\n";
+
+    #[test]
+    fn test_parse(){
+        let map = InputMap::parse(CONFIG).unwrap();
+        assert_eq!(map.bindings.len(), 5);
+        assert_eq!(map.bindings[0], Binding { key: "x".to_string(), player: 0, button: BUTTON_A, turbo: false });
+        assert_eq!(map.bindings[1].key, "j");
+        assert!(map.bindings[3].turbo);
+        assert_eq!(map.bindings[4], Binding { key: "pad1.button9".to_string(), player: 1,
+                                              button: BUTTON_START, turbo: false });
+        assert_eq!(map.turbo_frames(0, BUTTON_A), 1);
+        assert_eq!(map.turbo_frames(0, BUTTON_B), 3);
+        assert_eq!(map.turbo_frames(1, BUTTON_A), 2);
+
+        //Writing it out and reading it back gives the same map.
+        assert_eq!(InputMap::parse(&map.to_config()).unwrap(), map);
+        assert_eq!(InputMap::parse(&InputMap::default().to_config()).unwrap(), InputMap::default());
+
+        assert!(InputMap::parse("a = x\n").is_err());
+        assert!(InputMap::parse("[player5]\n").is_err());
+        assert!(InputMap::parse("[player1]\nturbo c = x\n").is_err());
+        assert!(InputMap::parse("[player1]\nturbo rate = 0\n").is_err());
+        assert!(InputMap::parse("[player1]\na x\n").is_err());
+    }
+
+    #[test]
+    fn test_turbo(){
+        let mut mapper = InputMapper::new(InputMap::parse(CONFIG).unwrap());
+        mapper.set_frame(100);
+        mapper.key_down("J");
+        mapper.key_down("pad1.button9");
+        assert_eq!(mapper.buttons(), [BUTTON_A, BUTTON_START, 0, 0]);
+        mapper.key_up("j");
+        assert_eq!(mapper.buttons()[0], 0);
+
+        //Turbo starts pressed, and toggles every frame at 30 a second.
+        mapper.key_down("s");
+        let a: Vec<u8> = (100..106).map(|f| { mapper.set_frame(f); mapper.buttons()[0] }).collect();
+        assert_eq!(a, vec![BUTTON_A, 0, BUTTON_A, 0, BUTTON_A, 0]);
+
+        //Holding the normal key too keeps it down.
+        mapper.key_down("x");
+        mapper.set_frame(101);
+        assert_eq!(mapper.buttons()[0], BUTTON_A);
+
+        mapper.release_all();
+        assert!(!mapper.is_down("x"));
+        assert_eq!(mapper.buttons(), [0; 4]);
+    }
+
+    #[test]
+    fn test_nes_keys(){
+        let mut nes = NES::new(test_rom("keymap", &[0x4C, 0x00, 0x80])).unwrap();
+        //Without a map, keys do nothing.
+        nes.key_down("x");
+        nes.step_frame();
+        assert_eq!(nes.cpu.memory.INPUT.pad_mut(0).unwrap().buttons, 0);
+
+        nes.set_input_map(InputMap::default());
+        nes.key_down("x");
+        nes.key_down("return");
+        nes.step_frame();
+        assert_eq!(nes.cpu.memory.INPUT.pad_mut(0).unwrap().buttons, BUTTON_A | BUTTON_START);
+        nes.key_up("x");
+        nes.step_frame();
+        assert_eq!(nes.cpu.memory.INPUT.pad_mut(0).unwrap().buttons, BUTTON_START);
+
+        nes.clear_input_map();
+        nes.set_buttons(0, BUTTON_B);
+        nes.step_frame();
+        assert_eq!(nes.cpu.memory.INPUT.pad_mut(0).unwrap().buttons, BUTTON_B);
+    }
+}
@@ -3,23 +3,90 @@ pub use crate::core::{cartridge::*};
 /// new_map initializes a Boxed struct with the mapper trait to act as
 ///  a mapper in the memory map for the cpu.
 /// A mapper number is supplied, and a simple match selects the appropriate val
-pub fn new_map (map_num: u8, cart: Box<CART>) -> Box<dyn MAP> {
+pub fn new_map (map_num: u16, cart: Box<CART>) -> Result<Box<dyn MAP>, LoadError> {
     debug!("START -> Mapper Initialization in mappper #{}.", map_num);
     match map_num {
-        0 => Box::new(Nrom{cart}) as Box<dyn MAP>,
-        1 => Box::new(MMC1{cart}) as Box<dyn MAP>,
-        _ => panic!("Mapper {} has not been implemented!", map_num),
+        0 => Ok(Box::new(Nrom{cart}) as Box<dyn MAP>),
+        1 => Ok(Box::new(MMC1{cart}) as Box<dyn MAP>),
+        99 => Ok(Box::new(Vs99{cart, bank: 0}) as Box<dyn MAP>),
+        _ => Err(LoadError::UnsupportedMapper(map_num)),
+    }
+}
+
+/// Nametable mirroring, as wired on the board (or set by the mapper).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mirroring {
+    Horizontal,
+    Vertical,
+    SingleLow,
+    SingleHigh,
+    FourScreen,
+}
+impl Mirroring {
+    /// Reads the mirroring bits out of iNES header byte 6.
+    pub fn from_header(flags6: u8) -> Mirroring {
+        if flags6 & 0b1000 != 0 {
+            Mirroring::FourScreen
+        }
+        else if flags6 & 1 != 0 {
+            Mirroring::Vertical
+        }
+        else {
+            Mirroring::Horizontal
+        }
     }
 }
 
 /// Basic MAP trait, to be used in mappers.
-/// So far, only has a get and set function, but might eventually
-///  have bank switching functions.
+/// A board built around a cartridge image only has to hand it out with
+///  cart() and cart_mut(): the rest defaults to a board without bank
+///  switching (NROM), and mappers override what they wire differently.
 pub trait MAP {
-    fn get(&self, address: u16) -> u8;
-    fn set(&mut self, address: u16, val: u8);
-    fn get_chr(&self, address: u16) -> u8;
-    fn set_chr(&mut self, address: u16, val: u8);
+    /// The cartridge image the board reads from. None on boards that
+    ///  aren't built around one (the NSF player).
+    fn cart(&self) -> Option<&CART> { None }
+    fn cart_mut(&mut self) -> Option<&mut CART> { None }
+    fn get(&self, address: u16) -> u8 {
+        self.cart().map_or(0, |cart| cart.read_unbanked(address))
+    }
+    fn set(&mut self, address: u16, val: u8) {
+        if let (0x6000..=0x7FFF, Some(cart)) = (address, self.cart_mut()) {
+            cart.write_prg_ram(address, val);
+        }
+    }
+    fn get_chr(&self, address: u16) -> u8 {
+        self.cart().and_then(|cart| cart.CHR.get(address as usize).copied()).unwrap_or(0)
+    }
+    fn set_chr(&mut self, address: u16, val: u8) {
+        if let Some(cart) = self.cart_mut() {
+            cart.write_chr_ram(address, val);
+        }
+    }
+    /// Current nametable mirroring, used by the PPU.
+    fn mirroring(&self) -> Mirroring {
+        self.cart().map_or(Mirroring::Horizontal, |cart| cart.header.mirroring)
+    }
+    /// Output of on-cartridge sound hardware (VRC6, N163, ...), on the same
+    ///  scale as the APU mixer output. Clocked along with the CPU.
+    fn expansion_audio(&self) -> f32 { 0.0 }
+    /// Runs one CPU cycle, for mappers with timers or expansion sound.
+    fn step(&mut self) {}
+    /// True while the board holds the CPU IRQ line low (scanline counters,
+    ///  cycle timers, ...). Polled along with the APU's IRQ.
+    fn irq(&self) -> bool { false }
+    /// Battery backed PRG-RAM, to keep in a .sav file between runs.
+    fn battery_ram(&self) -> Option<&[u8]> {
+        self.cart().and_then(|cart| cart.battery_ram())
+    }
+    fn battery_ram_mut(&mut self) -> Option<&mut [u8]> {
+        self.cart_mut().and_then(|cart| cart.battery_ram_mut())
+    }
+    /// Whatever the board does when the console is switched on.
+    fn power_on(&mut self) {
+        if let Some(cart) = self.cart_mut() {
+            cart.power_on();
+        }
+    }
 }
 
 /// Compatability goes up the ladder, I'm afraid.
@@ -27,12 +94,7 @@ pub trait MAP {
 /// This is a lesson on why proper planning/studying/unit test tooling should
 ///  be done before implementation.
 pub struct EMPTY_MAP; 
-impl MAP for EMPTY_MAP{
-    fn get(&self, address: u16) -> u8{ 0 }
-    fn set(&mut self, address: u16, val: u8){ }
-    fn get_chr(&self, address: u16) -> u8{ 0 }
-    fn set_chr(&mut self, address: u16, val: u8){ }
-}
+impl MAP for EMPTY_MAP {}
 
 /// Mapper #00, NROM
 /// Probably the most simple mapper.
@@ -43,81 +105,52 @@ pub struct Nrom {
     pub cart: Box<CART>,
 }
 impl MAP for Nrom {
-    fn get(&self, address: u16) -> u8 {
-        // -- Check flags 6, bit 1 if PRG RAM Exists
-        // ---- If so, check size with flags 8.
-        // ---- Load $6000-7FFF with RAM data.
-        // -- Check flags 4, for PRG ROM size (in 16 kb units)
-        // ---- Load $8000-$BFFF with ROM.
-        // ---- If ROM size is 256, load $C000-$FFFF with last half of ROM
-        // ---- If ROM size is 128, load $C000-$FFFF with ROM.
-        //if self.cart.head[6] & 2 > 0 { //   if 
-        if address < 0x8000 {
-            0 //PRG RAM function -- DNE on this mapper.
-        }
-        else if self.cart.PRG.len() > 16384 {
-            self.cart.PRG[
-                if address > 0xBFFF {
-                    address & 0x3FFF
-                }
-                else {
-                    address & 0x7FFF
-                }
-            as usize]
-        }
-        else {
-            self.cart.PRG[(address & 0x7FFF) as usize] //This should start it at 0.
-        }
-    }
-    fn set(&mut self, address: u16, val: u8) {
-        
-    }
-    fn get_chr(&self, address: u16) -> u8 {
-        0
-    }
-    fn set_chr(&mut self, address: u16, val: u8){
-
-    }
+    fn cart(&self) -> Option<&CART> { Some(&self.cart) }
+    fn cart_mut(&mut self) -> Option<&mut CART> { Some(&mut self.cart) }
 }
 
 pub struct MMC1 {
     pub cart: Box<CART>,
 }
 impl MAP for MMC1 {
+    fn cart(&self) -> Option<&CART> { Some(&self.cart) }
+    fn cart_mut(&mut self) -> Option<&mut CART> { Some(&mut self.cart) }
+}
+
+/// Mapper #99, the Vs. System's own board.
+/// Bit 2 of writes to $4016 picks the 8kb CHR bank, and on games with 40kb
+///  of PRG, the 8kb PRG bank at $8000 as well (the first or the last).
+/// USED: Vs. Super Mario Bros., Vs. Tennis, Vs. Gumshoe, etc.
+pub struct Vs99 {
+    pub cart: Box<CART>,
+    bank: usize,
+}
+impl MAP for Vs99 {
+    fn cart(&self) -> Option<&CART> { Some(&self.cart) }
+    fn cart_mut(&mut self) -> Option<&mut CART> { Some(&mut self.cart) }
     fn get(&self, address: u16) -> u8 {
-        // -- Check flags 6, bit 1 if PRG RAM Exists
-        // ---- If so, check size with flags 8.
-        // ---- Load $6000-7FFF with RAM data.
-        // -- Check flags 4, for PRG ROM size (in 16 kb units)
-        // ---- Load $8000-$BFFF with ROM.
-        // ---- If ROM size is 256, load $C000-$FFFF with last half of ROM
-        // ---- If ROM size is 128, load $C000-$FFFF with ROM.
-        //if self.cart.head[6] & 2 > 0 { //   if 
-//        bfc tech
-        if address < 0x8000 {
-            0 //PRG RAM function.
-        }
-        else if self.cart.PRG.len() > 16384 {
-            self.cart.PRG[
-                if address > 0xBFFF {
-                    address & 0x3FFF
-                }
-                else {
-                    address & 0x7FFF
-                }
-            as usize]
+        let prg = &self.cart.PRG;
+        if prg.len() > 0x8000 && (0x8000..0xA000).contains(&address) {
+            prg[self.bank * 0x8000 + (address & 0x1FFF) as usize]
         }
         else {
-            self.cart.PRG[(address & 0x7FFF) as usize] //This should start it at 0.
+            self.cart.read_unbanked(address)
         }
     }
     fn set(&mut self, address: u16, val: u8) {
-        
+        if address == 0x4016 {
+            self.bank = (val >> 2 & 1) as usize;
+        }
+        else if (0x6000..0x8000).contains(&address) {
+            self.cart.write_prg_ram(address, val);
+        }
     }
     fn get_chr(&self, address: u16) -> u8 {
-        0
+        let len = self.cart.CHR.len().max(1);
+        *self.cart.CHR.get((self.bank * 0x2000 + address as usize) % len).unwrap_or(&0)
     }
-    fn set_chr(&mut self, address: u16, val: u8){
-
+    fn power_on(&mut self) {
+        self.cart.power_on();
+        self.bank = 0;
     }
-}
\ No newline at end of file
+}
@@ -2,6 +2,10 @@
  * Author: Spalynx
  *--------------Memory Map---------------------------------------------
  * $0000-$07FF     =      Internal CPU RAM
+ * $0800-$1FFF     =      Mirrors of $0000-$07FF
+ * $2000-$2007     =      PPU registers
+ * $2008-$3FFF     =      Mirrors of $2000-$2007
+ * $4000-$401F     =      APU and I/O registers ($4014 is OAM DMA)
  * $4020-$FFFF     =      Cartridge Space and Misc (Interrupt Vectors).
  *---------------------------------------------------------------------
  */
@@ -15,10 +19,13 @@ pub use crate::core::*;
 pub struct MEM {
     RAM:	[u8; 0x800],        //2kb internal RAM.
     pub CART:   Box<MAP>,    //Cartridge Space
-    PPU:    u8,         //TODO
-    APU:    u8,         //TODO
-    INPUT:  u8,         //TODO
-
+    pub PPU:    PPU,
+    pub APU:    APU,
+    pub INPUT:  INPUT,
+    /// DIP switches and coins, on Vs. System boards.
+    pub VS:     Option<VsSystem>,
+    /// CPU cycles to stall, picked up by the CPU after each instruction.
+    pub stall:  u16,
 }
 
 impl MEM {
@@ -29,35 +36,59 @@ impl MEM {
         return MEM {
             RAM:	    [0; 0x800],
             CART:	    Box::new(EMPTY_MAP),
-            PPU:        0,
-            APU:        0,
-            INPUT:      0,
+            PPU:        PPU::new(),
+            APU:        APU::new(),
+            INPUT:      INPUT::new(),
+            VS:         None,
+            stall:      0,
         }
     }
     //Initializes the full memory map of the NES.
-    // TODO: PPU, APU, and INPUT registers are unimplemented!
-    pub fn new(mapper: Box<MAP>, ppu: u8, apu: u8, input: u8) -> MEM {
+    pub fn new(mapper: Box<MAP>, ppu: PPU, apu: APU, input: INPUT) -> MEM {
         return MEM {
             RAM:	    [0; 0x800],
             CART:	    mapper,
             PPU:        ppu,
             APU:        apu,
             INPUT:      input,
+            VS:         None,
+            stall:      0,
         }
     }
     
 
     //Obtains values from full memory map.
-    pub fn get(&self, address: u16) -> u8 {
-        if address <= 0x800{
-            //2kb internal ram
-            self.RAM[address as usize]
+    //Takes &mut self, since reading some registers changes their state.
+    pub fn get(&mut self, address: u16) -> u8 {
+        if address < 0x2000 {
+            //2kb internal ram, mirrored.
+            self.RAM[(address & 0x7FF) as usize]
+        }
+        else if address < 0x4000 {
+            let ppu = self.VS.as_ref().map(|vs| vs.ppu);
+            let val = self.PPU.read_register(&*self.CART, vs_ppu_register(ppu, address));
+            match ppu.and_then(|ppu| ppu.status_id()) {
+                Some(id) if address & 7 == 2 => (val & 0xE0) | id,
+                _ => val,
+            }
+        }
+        else if address == 0x4015 {
+            self.APU.read_status()
+        }
+        else if address == 0x4016 || address == 0x4017 {
+            let register = (address - 0x4016) as usize;
+            let val = self.INPUT.read(register, &self.PPU);
+            match self.VS.as_ref() {
+                //No open bus, the cabinet has a use for every bit.
+                Some(vs) => (val & 0x03) | vs.read(register),
+                None => val,
+            }
         }
         else if address >= 0x4020 {
             self.CART.get(address) 
         }
         else {
-            panic!("Other values in the memory map not implemented yet!");
+            0
         }
     }
 
@@ -69,18 +100,81 @@ impl MEM {
 
     // block any illegal storing.
     pub fn set(&mut self, address: u16, val: u8){
-        if address <= 0x800 {
-            //2kb internal ram
-            self.RAM[address as usize] = val;
+        if address < 0x2000 {
+            //2kb internal ram, mirrored.
+            self.RAM[(address & 0x7FF) as usize] = val;
+        }
+        else if address < 0x4000 {
+            let address = vs_ppu_register(self.VS.as_ref().map(|vs| vs.ppu), address);
+            self.PPU.write_register(&mut *self.CART, address, val);
+        }
+        else if address == 0x4014 {
+            self.oam_dma(val);
+        }
+        else if address == 0x4016 {
+            self.INPUT.write(val);
+            //Vs. System boards switch banks with it too. Other boards never
+            // see it, even if they decode $4000-$5FFF.
+            if self.VS.is_some() {
+                self.CART.set(address, val);
+            }
+        }
+        else if address <= 0x4013 || address == 0x4015 || address == 0x4017 {
+            self.APU.write_register(address, val);
         }
         else if address >= 0x4020 {
+            if let (0x4020, Some(vs)) = (address, self.VS.as_mut()) {
+                vs.write_counter(val);
+            }
             //~6kb Cartridge space.
             self.CART.set(address, val);
         }
-        else {
-            panic!("Other values in the memory map not implemented yet!");
+    }
+
+    /// Zeroes the internal 2kb of RAM.
+    pub fn clear_ram(&mut self) {
+        self.RAM = [0; 0x800];
+    }
+
+    /// The 2kb of internal RAM, e.g. to hash at the end of a movie.
+    pub fn ram(&self) -> &[u8] {
+        &self.RAM
+    }
+
+    /// Copies page $XX00-$XXFF into OAM. The CPU is halted for 513 cycles
+    ///  (514 on odd cycles, which we don't track).
+    fn oam_dma(&mut self, page: u8) {
+        let base = (page as u16) << 8;
+        for i in 0..256 {
+            let val = self.get(base | i);
+            self.PPU.write_dma(val);
+        }
+        self.stall += 513;
+    }
+
+    /// Runs one CPU cycle of the APU. When the DMC needs a sample byte, it
+    ///  is read over the CPU bus, and the CPU is stalled for it.
+    pub fn step_apu(&mut self) {
+        self.APU.step();
+        self.CART.step();
+        if let Some(address) = self.APU.dmc.fetch_address() {
+            let val = self.get(address);
+            self.APU.dmc.fill_buffer(val);
+            self.stall += DMC_STALL_CYCLES;
         }
     }
+
+    /// The mixed audio level this cycle: the APU, plus any expansion sound
+    ///  on the cartridge, with the channel controls applied.
+    pub fn audio_output(&self) -> f32 {
+        let expansion = self.APU.controls.gain(Channel::Expansion) * self.CART.expansion_audio();
+        self.APU.output() + expansion
+    }
+
+    /// Runs one PPU dot. The PPU needs the mapper for CHR and mirroring.
+    pub fn step_ppu(&mut self) {
+        self.PPU.step(&*self.CART);
+    }
     //Sets a value in the zero page.
     //Much faster, only has to access the first page of memory.
     pub fn set_zp(&mut self, address: u8, val: u8) {
@@ -102,6 +196,14 @@ impl MEM {
     }
 }
 
+/// RC2C05 PPUs have $2000 and $2001 swapped.
+fn vs_ppu_register(ppu: Option<VsPpu>, address: u16) -> u16 {
+    match ppu {
+        Some(ppu) if ppu.swaps_registers() && address & 6 == 0 => address ^ 1,
+        _ => address,
+    }
+}
+
 
 /*Memory locations $200 to $5ff map to the screen pixels. Different values will
 draw different colour pixels. The colours are:
@@ -3,11 +3,44 @@ pub mod memory;
 pub mod nes;
 pub mod cartridge;
 pub mod mapper;
+pub mod filter;
+pub mod ppu;
+pub mod apu;
+pub mod ppu_debug;
+pub mod image;
+pub mod checksum;
+pub mod audio;
+pub mod wav;
+pub mod expansion;
+pub mod nsf;
+pub mod controller;
+pub mod input;
+pub mod movie;
+pub mod keymap;
+pub mod header;
+pub mod playchoice;
+pub mod vs_system;
+pub mod romdb;
 
 pub use crate::core::cartridge::*;
 pub use crate::core::cpu::*;
 pub use crate::core::memory::*;
 pub use crate::core::nes::*;
 pub use crate::core::mapper::*;
+pub use crate::core::filter::*;
+pub use crate::core::ppu::*;
+pub use crate::core::apu::*;
+pub use crate::core::image::*;
+pub use crate::core::audio::*;
+pub use crate::core::wav::*;
+pub use crate::core::nsf::*;
+pub use crate::core::controller::*;
+pub use crate::core::input::*;
+pub use crate::core::movie::*;
+pub use crate::core::keymap::*;
+pub use crate::core::header::*;
+pub use crate::core::playchoice::*;
+pub use crate::core::vs_system::*;
+pub use crate::core::romdb::*;
 
 //A note to make: When casting u16 to usize, that means that as long as this is running on a 16-bit architecture, this should run? It is pointer sized...    Odd.
\ No newline at end of file
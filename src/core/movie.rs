@@ -0,0 +1,371 @@
+/* Input movies, in FCEUX's FM2 text format.
+ * A movie is the input for every frame from power on, so replaying it on
+ *  the same ROM gives back the same run. Good for bug reports, and for
+ *  regression tests that check a hash at the end.
+ *
+ * An FM2 file is a header of "key value" lines, then one line per frame:
+ *   |c|RLDUTSBA|RLDUTSBA||
+ *  c is a command bitfield (1 = soft reset, 2 = power cycle), then one field
+ *  per port, then one for the Famicom expansion port (always empty here).
+ *  Pad fields are the buttons Right Left Down Up sTart Select B A, with '.'
+ *  for released. Zapper fields are "x y trigger". With a Four Score there
+ *  are four pad fields instead of two.
+ * Only the text format is supported, not binary FM2.
+ *
+ * http://fceux.com/web/help/fm2.html
+ */
+
+use std::fmt::Write as FmtWrite;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const MOVIE_SOFT_RESET: u8 = 1;
+pub const MOVIE_POWER:      u8 = 2;
+
+/// FM2 port types.
+pub const SI_NONE:      u8 = 0;
+pub const SI_GAMEPAD:   u8 = 1;
+pub const SI_ZAPPER:    u8 = 2;
+
+/// Button letters, from bit 7 down to bit 0 of BUTTON_*.
+const BUTTON_CHARS: &[u8; 8] = b"RLDUTSBA";
+
+/// The input for one frame.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MovieFrame {
+    /// MOVIE_* commands, run before the frame.
+    pub commands:   u8,
+    /// Buttons of players 1-4 (3 and 4 only with a Four Score).
+    pub pads:       [u8; 4],
+    /// Zapper aim and trigger, for ports that have one.
+    pub zappers:    [Option<(u8, u8, bool)>; 2],
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Movie {
+    pub emu_version:    u32,
+    pub rerecord_count: u32,
+    pub pal:            bool,
+    pub rom_filename:   String,
+    /// MD5 of the ROM's PRG and CHR data.
+    pub rom_checksum:   [u8; 16],
+    pub guid:           String,
+    pub four_score:     bool,
+    /// SI_* type of each port. Ignored with a Four Score.
+    pub ports:          [u8; 2],
+    pub comments:       Vec<String>,
+    /// Header lines we don't use (subtitles etc.), kept for saving.
+    pub extra:          Vec<(String, String)>,
+    pub frames:         Vec<MovieFrame>,
+}
+
+impl Default for Movie {
+    fn default() -> Movie {
+        Movie {
+            emu_version:    0,
+            rerecord_count: 0,
+            pal:            false,
+            rom_filename:   String::new(),
+            rom_checksum:   [0; 16],
+            guid:           String::new(),
+            four_score:     false,
+            ports:          [SI_GAMEPAD, SI_GAMEPAD],
+            comments:       Vec::new(),
+            extra:          Vec::new(),
+            frames:         Vec::new(),
+        }
+    }
+}
+
+impl Movie {
+    /// An empty movie for a ROM, with two pads.
+    pub fn new(rom_filename: &str, rom_checksum: [u8; 16]) -> Movie {
+        let mut movie = Movie {
+            rom_filename: rom_filename.to_string(),
+            rom_checksum,
+            ..Movie::default()
+        };
+        movie.guid = movie.make_guid();
+        movie
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Movie> {
+        let text = fs::read_to_string(path)?;
+        Movie::parse(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_fm2())
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Parses the text of an FM2 file.
+    pub fn parse(text: &str) -> Result<Movie, String> {
+        let mut movie = Movie::default();
+        let mut version = None;
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim_end_matches('\r');
+            if line.starts_with('|') {
+                let frame = movie.parse_frame(line)
+                    .ok_or_else(|| format!("Bad input on line {}: {}", n + 1, line))?;
+                movie.frames.push(frame);
+                continue;
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+            let mut parts = line.splitn(2, ' ');
+            let key = parts.next().unwrap_or("");
+            let value = parts.next().unwrap_or("").to_string();
+            let number = || value.trim().parse::<u32>()
+                .map_err(|_| format!("Bad {} on line {}: {}", key, n + 1, value));
+            match key {
+                "version"       => version = Some(number()?),
+                "emuVersion"    => movie.emu_version = number()?,
+                "rerecordCount" => movie.rerecord_count = number()?,
+                "palFlag"       => movie.pal = number()? != 0,
+                "romFilename"   => movie.rom_filename = value,
+                "romChecksum"   => movie.rom_checksum = parse_checksum(&value)
+                    .ok_or_else(|| format!("Bad romChecksum: {}", value))?,
+                "guid"          => movie.guid = value,
+                "fourscore"     => movie.four_score = number()? != 0,
+                "port0"         => movie.ports[0] = number()? as u8,
+                "port1"         => movie.ports[1] = number()? as u8,
+                "binary"        => if number()? != 0 {
+                    return Err("Binary FM2 movies aren't supported.".to_string());
+                },
+                "comment"       => movie.comments.push(value),
+                //Always 0 for NES games.
+                "port2" | "FDS" | "NewPPU" | "microphone" => {}
+                _               => movie.extra.push((key.to_string(), value)),
+            }
+        }
+        match version {
+            Some(3) => Ok(movie),
+            Some(v) => Err(format!("FM2 version {} isn't supported.", v)),
+            None => Err("Not an FM2 movie, there's no version.".to_string()),
+        }
+    }
+
+    /// What each input field of a frame line holds, in order.
+    fn field_types(&self) -> Vec<u8> {
+        if self.four_score { vec![SI_GAMEPAD; 4] } else { self.ports.to_vec() }
+    }
+
+    fn parse_frame(&self, line: &str) -> Option<MovieFrame> {
+        //"|c|port|port|exp|" splits into "", c, port, port, exp, "".
+        let fields: Vec<&str> = line.split('|').collect();
+        let mut frame = MovieFrame {
+            commands: fields.get(1)?.trim().parse().ok()?,
+            ..MovieFrame::default()
+        };
+        for (i, &kind) in self.field_types().iter().enumerate() {
+            let field = fields.get(2 + i)?;
+            match kind {
+                SI_GAMEPAD => frame.pads[i] = parse_buttons(field)?,
+                SI_ZAPPER => {
+                    let mut numbers = field.split_whitespace().map(|v| v.parse::<u32>().ok());
+                    let x = numbers.next()??;
+                    let y = numbers.next()??;
+                    let trigger = numbers.next()?? != 0;
+                    frame.zappers[i] = Some((x.min(255) as u8, y.min(255) as u8, trigger));
+                }
+                _ => {}
+            }
+        }
+        Some(frame)
+    }
+
+    /// Writes the movie as FM2 text.
+    pub fn to_fm2(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "version 3");
+        let _ = writeln!(out, "emuVersion {}", self.emu_version);
+        let _ = writeln!(out, "rerecordCount {}", self.rerecord_count);
+        let _ = writeln!(out, "palFlag {}", self.pal as u8);
+        let _ = writeln!(out, "romFilename {}", self.rom_filename);
+        let _ = writeln!(out, "romChecksum base64:{}", base64_encode(&self.rom_checksum));
+        let _ = writeln!(out, "guid {}", self.guid);
+        let _ = writeln!(out, "fourscore {}", self.four_score as u8);
+        let _ = writeln!(out, "microphone 0");
+        let _ = writeln!(out, "port0 {}", if self.four_score { SI_GAMEPAD } else { self.ports[0] });
+        let _ = writeln!(out, "port1 {}", if self.four_score { SI_GAMEPAD } else { self.ports[1] });
+        let _ = writeln!(out, "port2 0");
+        let _ = writeln!(out, "FDS 0");
+        let _ = writeln!(out, "NewPPU 0");
+        for comment in &self.comments {
+            let _ = writeln!(out, "comment {}", comment);
+        }
+        for (key, value) in &self.extra {
+            let _ = writeln!(out, "{} {}", key, value);
+        }
+
+        let types = self.field_types();
+        for frame in &self.frames {
+            let _ = write!(out, "|{}|", frame.commands);
+            for (i, &kind) in types.iter().enumerate() {
+                match kind {
+                    SI_GAMEPAD => out.push_str(&format_buttons(frame.pads[i])),
+                    SI_ZAPPER => {
+                        let (x, y, trigger) = frame.zappers[i].unwrap_or((0, 0, false));
+                        let _ = write!(out, "{} {} {} 0 0", x, y, trigger as u8);
+                    }
+                    _ => {}
+                }
+                out.push('|');
+            }
+            out.push_str("|\n");
+        }
+        out
+    }
+
+    /// FCEUX style GUID, made from the ROM checksum and the clock.
+    fn make_guid(&self) -> String {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or(0);
+        let mut seed = self.rom_checksum.to_vec();
+        seed.extend_from_slice(&now.to_le_bytes());
+        let id = crate::core::checksum::md5(&seed);
+        let hex: String = id.iter().map(|b| format!("{:02X}", b)).collect();
+        format!("{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+    }
+}
+
+/// "RLDUTSBA" or "........" to BUTTON_* bits. Anything but '.' or ' ' is
+///  a pressed button.
+fn parse_buttons(field: &str) -> Option<u8> {
+    let field = field.as_bytes();
+    if field.len() != 8 {
+        return None;
+    }
+    Some(field.iter().enumerate()
+        .filter(|&(_, &c)| c != b'.' && c != b' ')
+        .fold(0, |acc, (i, _)| acc | (0x80 >> i)))
+}
+
+fn format_buttons(buttons: u8) -> String {
+    BUTTON_CHARS.iter().enumerate()
+        .map(|(i, &c)| if buttons & (0x80 >> i) != 0 { c as char } else { '.' })
+        .collect()
+}
+
+/// "base64:..." (or a plain 32 digit hex string) to an MD5.
+fn parse_checksum(value: &str) -> Option<[u8; 16]> {
+    let value = value.trim();
+    let bytes = match value.strip_prefix("base64:") {
+        Some(b64) => base64_decode(b64)?,
+        None => (0..value.len()).step_by(2)
+            .map(|i| value.get(i..i + 2).and_then(|h| u8::from_str_radix(h, 16).ok()))
+            .collect::<Option<Vec<u8>>>()?,
+    };
+    let mut checksum = [0; 16];
+    if bytes.len() != 16 {
+        return None;
+    }
+    checksum.copy_from_slice(&bytes);
+    Some(checksum)
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub fn base64_encode(data: &[u8]) -> String {
+    let mut out = String::new();
+    for chunk in data.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |acc, (i, &b)| acc | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(n >> (18 - 6 * i)) as usize & 0x3F] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+pub fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let (mut bits, mut count) = (0u32, 0);
+    for c in text.trim_end_matches('=').bytes() {
+        let value = BASE64.iter().position(|&b| b == c)? as u32;
+        bits = (bits << 6) | value;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            out.push((bits >> count) as u8);
+        }
+    }
+    Some(out)
+}
+
+//~PLAYBACK~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MovieMode {
+    Record,
+    Play,
+}
+
+/// A movie being recorded or played back by the NES, a frame at a time.
+#[derive(Debug, Clone)]
+pub struct MovieSession {
+    pub movie:  Movie,
+    pub mode:   MovieMode,
+    /// The next frame to record or play.
+    pub frame:  usize,
+    /// Commands to record with the next frame.
+    pending:    u8,
+}
+
+impl MovieSession {
+    pub fn new(movie: Movie, mode: MovieMode) -> MovieSession {
+        MovieSession { movie, mode, frame: 0, pending: 0 }
+    }
+
+    /// Notes a reset or power cycle, for the next recorded frame.
+    pub fn add_command(&mut self, command: u8) {
+        if self.mode == MovieMode::Record {
+            self.pending |= command;
+        }
+    }
+
+    /// True once playback has run out of frames.
+    pub fn is_finished(&self) -> bool {
+        self.mode == MovieMode::Play && self.frame >= self.movie.frames.len()
+    }
+
+    /// Called as a frame starts. When playing, returns the input to use.
+    ///  When recording, stores the input that was held.
+    pub fn next_frame(&mut self, held: MovieFrame) -> Option<MovieFrame> {
+        match self.mode {
+            MovieMode::Record => {
+                let mut frame = held;
+                frame.commands = self.pending;
+                self.pending = 0;
+                self.movie.frames.push(frame);
+                self.frame += 1;
+                None
+            }
+            MovieMode::Play => {
+                let frame = self.movie.frames.get(self.frame).cloned();
+                if frame.is_some() {
+                    self.frame += 1;
+                }
+                frame
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[path = "./movie_test.rs"]
+pub mod movie_test;
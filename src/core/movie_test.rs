@@ -0,0 +1,155 @@
+/*  Unit test module of FM2 movies (movie.rs).
+ */
+use crate::core::movie::*;
+
+#[cfg(test)]
+pub mod movie_test {
+    use super::*;
+    use crate::core::controller::*;
+    use crate::core::nes::NES;
+    use crate::core::ppu::ppu_test::ppu_test::test_rom;
+
+    #[test]
+    fn test_base64(){
+        assert_eq!(base64_encode(b"Man"), "TWFu");
+        assert_eq!(base64_encode(b"Ma"), "TWE=");
+        assert_eq!(base64_encode(b"M"), "TQ==");
+        assert_eq!(base64_decode("TWE=").unwrap(), b"Ma");
+        assert!(base64_decode("T*==").is_none());
+    }
+
+    #[test]
+    fn test_fm2_format(){
+        let text = "version 3\n\
+                    emuVersion 22020\n\
+                    rerecordCount 5\n\
+                    palFlag 0\n\
+                    romFilename Some Game\n\
+                    romChecksum base64:XTRJsl2yMfBiD03/7pyT5w==\n\
+                    guid 452DE2C3-EF43-2FA9-77AC-0677FC51543B\n\
+                    fourscore 0\n\
+                    port0 1\n\
+                    port1 2\n\
+                    port2 0\n\
+                    comment author someone\n\
+                    subtitle 10 Hello\n\
+                    |0|........|0 0 0 0 0||\n\
+                    |1|R..UT..A|128 120 1 0 0||\n\
+                    |2|........|0 0 0 0 0||\n";
+        let movie = Movie::parse(text).unwrap();
+        assert_eq!(movie.emu_version, 22020);
+        assert_eq!(movie.rerecord_count, 5);
+        assert_eq!(movie.rom_filename, "Some Game");
+        assert_eq!(movie.rom_checksum[..4], [0x5D, 0x34, 0x49, 0xB2]);
+        assert_eq!(movie.ports, [SI_GAMEPAD, SI_ZAPPER]);
+        assert_eq!(movie.comments, vec!["author someone"]);
+        assert_eq!(movie.extra, vec![("subtitle".to_string(), "10 Hello".to_string())]);
+        assert_eq!(movie.len(), 3);
+        assert_eq!(movie.frames[1].commands, MOVIE_SOFT_RESET);
+        assert_eq!(movie.frames[1].pads[0], BUTTON_RIGHT | BUTTON_UP | BUTTON_START | BUTTON_A);
+        assert_eq!(movie.frames[1].zappers[1], Some((128, 120, true)));
+        assert_eq!(movie.frames[2].commands, MOVIE_POWER);
+
+        //Saving and loading gives back the same movie.
+        assert_eq!(Movie::parse(&movie.to_fm2()).unwrap(), movie);
+
+        //Four Score movies have four pad fields.
+        let four = Movie::parse("version 3\nfourscore 1\n|0|.......A|......B.|.....S..|....T...||\n").unwrap();
+        assert_eq!(four.frames[0].pads, [BUTTON_A, BUTTON_B, BUTTON_SELECT, BUTTON_START]);
+
+        assert!(Movie::parse("version 2\n").is_err());
+        assert!(Movie::parse("|0|........|........||\n").is_err());
+        assert!(Movie::parse("version 3\nbinary 1\n").is_err());
+        assert!(Movie::parse("version 3\n|0|...|........||\n").is_err());
+    }
+
+    #[test]
+    fn test_record_and_replay(){
+        //Polls pad 1 forever, ORing each button's reads into $20-$27.
+        let mut code = vec![
+            0xA9, 0x01, 0x8D, 0x16, 0x40,   //LDA #1, STA $4016
+            0xA9, 0x00, 0x8D, 0x16, 0x40,   //LDA #0, STA $4016
+        ];
+        for button in 0x20..0x28 {
+            code.extend_from_slice(&[
+                0xAD, 0x16, 0x40, 0x29, 0x01,   //LDA $4016, AND #1
+                0x05, button, 0x85, button,     //ORA $2x, STA $2x
+            ]);
+        }
+        code.extend_from_slice(&[0x4C, 0x00, 0x80]);   //JMP $8000
+        let rom = test_rom("movie", &code);
+
+        let mut nes = NES::new(&rom).unwrap();
+        nes.record_movie();
+        for frame in 0..8 {
+            match frame {
+                3 => nes.set_buttons(0, BUTTON_A | BUTTON_START),
+                5 => nes.reset(),
+                6 => nes.set_buttons(0, 0),
+                _ => {}
+            }
+            nes.step_frame();
+        }
+        let movie = nes.stop_movie().unwrap();
+        assert_eq!(movie.len(), 8);
+        assert_eq!(movie.rom_checksum, nes.rom_checksum());
+        assert_eq!(movie.frames[3].pads[0], BUTTON_A | BUTTON_START);
+        assert_eq!(movie.frames[5].commands, MOVIE_SOFT_RESET);
+        let held = [1, 0, 0, 1, 0, 0, 0, 0];
+        assert_eq!(nes.cpu.memory.ram()[0x20..0x28], held);
+        let hash = nes.replay_hash();
+
+        //Played back through FM2 text on a fresh NES, it ends the same way.
+        let movie = Movie::parse(&movie.to_fm2()).unwrap();
+        let mut replay = NES::new(&rom).unwrap();
+        replay.set_buttons(0, BUTTON_B);
+        replay.play_movie(movie.clone()).unwrap();
+        let mut frames = 0;
+        while !replay.movie_finished() {
+            replay.step_frame();
+            frames += 1;
+        }
+        assert_eq!(frames, 8);
+        assert_eq!(replay.cpu.memory.ram()[0x20..0x28], held);
+        assert_eq!(replay.replay_hash(), hash);
+
+        //Movies from other ROMs are refused.
+        let mut other = movie;
+        other.rom_checksum[0] ^= 1;
+        assert!(replay.play_movie(other).is_err());
+    }
+
+    #[test]
+    fn test_replay_after_running(){
+        //Counts power ons in PRG-RAM, and copies the count to $10.
+        let rom = test_rom("movie_power", &[
+            0xEE, 0x00, 0x60,       //INC $6000
+            0xAD, 0x00, 0x60,       //LDA $6000
+            0x85, 0x10,             //STA $10
+            0x4C, 0x08, 0x80,       //JMP $8008
+        ]);
+        let mut nes = NES::new(&rom).unwrap();
+        for _ in 0..5 {
+            nes.step_frame();
+        }
+
+        //Recording starts from a fresh boot, whatever ran before.
+        nes.record_movie();
+        for _ in 0..4 {
+            nes.step_frame();
+        }
+        let movie = nes.stop_movie().unwrap();
+        assert_eq!(nes.cpu.memory.ram()[0x10], 1);
+        let hash = nes.replay_hash();
+
+        //And so does playing it back on the same NES.
+        for _ in 0..4 {
+            nes.step_frame();
+        }
+        nes.play_movie(movie).unwrap();
+        while !nes.movie_finished() {
+            nes.step_frame();
+        }
+        assert_eq!(nes.replay_hash(), hash);
+    }
+}
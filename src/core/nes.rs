@@ -9,52 +9,172 @@
 pub use crate::core::*;
 pub use crate::core::cpu::OP_SIZES;
 
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::core::checksum::{crc32, md5, Crc32};
+use std::sync::Arc;
+
 const DEBUG_ROM: bool = true;
 
+/// How often battery RAM is written out while running, if it changed, so a
+///  crash loses at most this many frames of saving (10 seconds).
+pub const SAVE_FLUSH_FRAMES: u64 = 600;
+
 
 pub struct NES {
-    cpu:    CPU,
+    pub cpu:    CPU,
+    pub audio:  Audio,
+    recorder:   Option<AudioRecorder>,
+    /// Set when playing an NSF instead of running a cartridge.
+    nsf:        Option<NsfPlayer>,
+    /// ROM file name and MD5 of its PRG and CHR, for movies.
+    rom_name:   String,
+    rom_md5:    [u8; 16],
+    /// A movie being recorded or played back.
+    movie:      Option<MovieSession>,
+    /// Host keys to pads, when the frontend drives input by key.
+    keys:       Option<InputMapper>,
+    /// The .sav file battery RAM is kept in, and the CRC-32 of what's in
+    ///  it, so unchanged RAM isn't written again.
+    save_path:  Option<PathBuf>,
+    saved_crc:  u32,
+    /// The RGB palette frames are drawn with.
+    palette:    &'static [u32; 64],
+    /// INST-ROM and PROM, when running a PlayChoice-10 game.
+    playchoice: Option<Playchoice>,
 }
 impl NES {
     //Loads values for each hardware device, including rom-file. 
-    pub fn new(file_n: &'static str) -> NES {
-        //Creates a CART type that will be read as memory later.
-        let mut cart = CART::new(file_n);
-
-        //Reads values from ROM file into memory.
+    //Fails if the ROM can't be read, or needs a mapper we don't have.
+    pub fn new<P: AsRef<Path>>(file_n: P) -> Result<NES, LoadError> {
+        //Reads values from ROM file into a CART, that will be read as memory later.
         //This CART data can now be used to propogate 16-bit address space. 
-        match cart.read_cart(){
-            Ok(e)    => debug!("COMPLETE -> ROM read."),
-            Err(e)   => panic!("ERROR    -> ROM read."),
-        }
+        let cart = CART::open(file_n)?;
+        debug!("COMPLETE -> ROM read.");
+        NES::from_cart(cart)
+    }
+
+    /// Boots a cartridge that's already been loaded, e.g. with
+    ///  CART::from_bytes(include_bytes!("game.nes")).
+    pub fn from_cart(cart: CART) -> Result<NES, LoadError> {
+        let mut rom = cart.PRG.clone();
+        rom.extend_from_slice(&cart.CHR);
+        let rom_md5 = md5(&rom);
+        let rom_name = Path::new(&cart.filename).file_name()
+            .map_or(String::new(), |n| n.to_string_lossy().into_owned());
+        //Battery RAM goes in a .sav next to the ROM.
+        let save_path = if cart.header.battery && !cart.filename.is_empty() {
+            Some(Path::new(&cart.filename).with_extension("sav"))
+        } else { None };
+        //PlayChoice-10 and Vs. System games run on RGB PPUs.
+        let playchoice = Playchoice::from_cart(&cart);
+        let mut palette = if playchoice.is_some() { &RGB_PPU_PALETTE } else { &NES_PALETTE };
+        let vs = if cart.header.console == ConsoleType::VsSystem {
+            let ppu = VsPpu::from_header(cart.header.vs_ppu).unwrap_or_else(|| {
+                warn!("CART     -> Unknown Vs. System PPU {}, using an RP2C03B.", cart.header.vs_ppu);
+                VsPpu::Rp2c03b
+            });
+            palette = ppu.palette().unwrap_or_else(|| {
+                warn!("CART     -> No palette for the {:?}, colors will be wrong.", ppu);
+                &RGB_PPU_PALETTE
+            });
+            Some(VsSystem::new(ppu))
+        } else { None };
 
         //Find and create mapper.
-        let map_num = (cart.HEAD[7] & 240) 
-                        + (((cart.HEAD[6]) & 240) >> 4);
-        let mapper: Box<MAP> = new_map(map_num, Box::new(cart));
+        let map_num = cart.header.mapper;
+        let mapper: Box<dyn MAP> = new_map(map_num, Box::new(cart))?;
         debug!("COMPLETE -> Mapper init.");
 
         //PPU init
-        let ppu: u8 = 0;
+        let ppu = PPU::new();
         debug!("COMPLETE -> PPU init.");
         //APU init
-        let apu: u8 = 0;
+        let apu = APU::new();
         debug!("COMPLETE -> APU init.");
         //Input init
-        let input: u8 = 0;
+        let mut input = INPUT::new();
+        input.palette = palette;
         debug!("COMPLETE -> INPUT init.");
 
         //Main memory map init
         let mut memory = MEM::new(mapper, ppu, apu, input); 
+        memory.VS = vs;
         debug!("COMPLETE -> MEM init.");
 
 
         //CPU init
-        NES{
-            cpu: CPU::new(memory),
+        let mut cpu = CPU::new(memory);
+        cpu.reset();
+        debug!("COMPLETE -> CPU reset, PC: {:04X}.", cpu.pc);
+
+        let mut nes = NES{
+            cpu,
+            audio:  Audio::default(),
+            recorder: None,
+            nsf:    None,
+            rom_name,
+            rom_md5,
+            movie:  None,
+            keys:   None,
+            save_path,
+            saved_crc: 0,
+            palette,
+            playchoice,
+        };
+        nes.saved_crc = nes.battery_crc();
+        if let Err(e) = nes.load_battery() {
+            warn!("CART     -> Could not load battery RAM: {}", e);
         }
+        //A trainer goes over whatever the .sav had at $7000.
+        nes.cpu.memory.CART.power_on();
+        Ok(nes)
 
     }
+
+    /// Boots into an NSF player, starting at the tune's default track.
+    pub fn new_nsf(nsf: Nsf) -> NES {
+        if nsf.unsupported_expansion() != 0 {
+            warn!("NSF      -> Expansion sound {:02X} isn't emulated, it will be silent.",
+                  nsf.unsupported_expansion());
+        }
+        let mapper: Box<dyn MAP> = Box::new(NsfMapper::new(&nsf));
+        let memory = MEM::new(mapper, PPU::new(), APU::new(), INPUT::new());
+        let mut nes = NES {
+            cpu:    CPU::new(memory),
+            audio:  Audio::default(),
+            recorder: None,
+            nsf:    None,
+            rom_name: String::new(),
+            rom_md5: [0; 16],
+            movie:  None,
+            keys:   None,
+            save_path: None,
+            saved_crc: 0,
+            palette: &NES_PALETTE,
+            playchoice: None,
+        };
+        let mut player = NsfPlayer::new(nsf);
+        let track = player.track;
+        player.start_track(&mut nes.cpu, track);
+        debug!("COMPLETE -> NSF init, track {}/{}.", track + 1, player.nsf.total_songs);
+        nes.nsf = Some(player);
+        nes
+    }
+
+    /// The NSF player, when playing one.
+    pub fn nsf(&self) -> Option<&NsfPlayer> {
+        self.nsf.as_ref()
+    }
+
+    /// Restarts the NSF at a track (0-based). Does nothing for cartridges.
+    pub fn select_track(&mut self, track: u8) {
+        if let Some(player) = self.nsf.as_mut() {
+            player.start_track(&mut self.cpu, track);
+        }
+    }
     //+ Further Boot Stuff.
     //-+ Read CHR ROM write data to PPU
     //-+ Code starts to read its ROM data and writes to APU registers
@@ -67,8 +187,37 @@ impl NES {
         //CPU running code
 
         //Run a step from each piece of hardware!
-        self.cpu.step();
-        //self.cpu.memory.ppu.step(); //Theoretically how PPU is called.        
+        let start = self.cpu.cycles;
+        let idle = match self.nsf.as_mut() {
+            Some(player) => player.step(&mut self.cpu),
+            None => false,
+        };
+        if !idle {
+            self.cpu.step();
+        }
+        let elapsed = self.cpu.cycles - start;
+
+        //The PPU runs 3 dots per CPU cycle, the APU runs at CPU speed.
+        for _ in 0..elapsed * 3 {
+            self.cpu.memory.step_ppu();
+        }
+        for _ in 0..elapsed {
+            self.cpu.memory.step_apu();
+            let sample = self.audio.push(self.cpu.memory.audio_output());
+            if let Some(recorder) = self.recorder.as_mut() {
+                if let Err(e) = recorder.record(&self.cpu.memory.APU, sample) {
+                    error!("ERROR    -> WAV recording stopped: {}", e);
+                    self.recorder = None;
+                }
+            }
+        }
+        if self.cpu.memory.PPU.nmi_pending {
+            self.cpu.memory.PPU.nmi_pending = false;
+            self.cpu.trigger_nmi();
+        }
+        else if self.cpu.memory.APU.irq_pending() || self.cpu.memory.CART.irq() {
+            self.cpu.trigger_irq();
+        }
 
 
 /*
@@ -89,11 +238,468 @@ impl NES {
         */
     }
 
+    /// Runs until the PPU finishes the current frame.
+    pub fn step_frame(&mut self) {
+        self.step_keys();
+        self.step_movie();
+        let frame = self.cpu.memory.PPU.frame;
+        while self.cpu.memory.PPU.frame == frame {
+            self.step();
+        }
+        if let Some(vs) = self.cpu.memory.VS.as_mut() {
+            vs.step_frame();
+        }
+        if self.frame_count().is_multiple_of(SAVE_FLUSH_FRAMES) {
+            if let Err(e) = self.flush_battery() {
+                error!("ERROR    -> Could not save battery RAM: {}", e);
+            }
+        }
+    }
+
+    /// The .sav file battery RAM is loaded from and saved to. None for
+    ///  carts without a battery, or that weren't loaded from a file.
+    pub fn save_path(&self) -> Option<&Path> {
+        self.save_path.as_deref()
+    }
+
+    /// Moves the .sav file, e.g. into a saves folder, or gives carts
+    ///  loaded from memory one. Call load_battery() to read it.
+    ///  None stops saving.
+    pub fn set_save_path(&mut self, path: Option<PathBuf>) {
+        self.save_path = path;
+    }
+
+    /// Reads the .sav file into battery RAM. Ok(false) if there's no file
+    ///  yet, or nothing to load it into.
+    pub fn load_battery(&mut self) -> io::Result<bool> {
+        let path = match (&self.save_path, self.cpu.memory.CART.battery_ram()) {
+            (Some(path), Some(_)) => path.clone(),
+            _ => return Ok(false),
+        };
+        let data = match fs::read(&path) {
+            Ok(data) => data,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e),
+        };
+        if let Some(ram) = self.cpu.memory.CART.battery_ram_mut() {
+            if data.len() != ram.len() {
+                warn!("CART     -> {} is {} bytes, battery RAM is {}.",
+                      path.display(), data.len(), ram.len());
+            }
+            let len = data.len().min(ram.len());
+            ram[..len].copy_from_slice(&data[..len]);
+        }
+        self.saved_crc = self.battery_crc();
+        debug!("COMPLETE -> Battery RAM loaded from {}.", path.display());
+        Ok(true)
+    }
+
+    /// Writes battery RAM to the .sav file, if it changed since it was
+    ///  loaded or last written. Returns whether it wrote anything.
+    pub fn flush_battery(&mut self) -> io::Result<bool> {
+        let crc = self.battery_crc();
+        let (path, ram) = match (&self.save_path, self.cpu.memory.CART.battery_ram()) {
+            (Some(path), Some(ram)) if crc != self.saved_crc => (path, ram),
+            _ => return Ok(false),
+        };
+        //Write then rename, so a crash mid-write keeps the old save.
+        let temp = path.with_extension("sav.tmp");
+        fs::write(&temp, ram)?;
+        fs::rename(&temp, path)?;
+        self.saved_crc = crc;
+        Ok(true)
+    }
+
+    fn battery_crc(&self) -> u32 {
+        self.cpu.memory.CART.battery_ram().map_or(0, crc32)
+    }
+
+    /// Movies start with blank battery RAM, as FCEUX's do, so they play
+    ///  the same whatever's in the .sav. The .sav is written first, and
+    ///  left alone from then on.
+    fn detach_battery(&mut self) {
+        if let Err(e) = self.flush_battery() {
+            error!("ERROR    -> Could not save battery RAM: {}", e);
+        }
+        self.save_path = None;
+        if let Some(ram) = self.cpu.memory.CART.battery_ram_mut() {
+            ram.iter_mut().for_each(|b| *b = 0);
+        }
+    }
+
+    /// Drives the pads from host keys through a map, from the next frame
+    ///  on. Keys that were down stay down.
+    pub fn set_input_map(&mut self, map: InputMap) {
+        match self.keys.as_mut() {
+            Some(mapper) => mapper.map = map,
+            None => self.keys = Some(InputMapper::new(map)),
+        }
+    }
+
+    /// Goes back to setting buttons directly.
+    pub fn clear_input_map(&mut self) {
+        self.keys = None;
+    }
+
+    /// A host key went down. Does nothing without an input map.
+    pub fn key_down(&mut self, key: &str) {
+        if let Some(mapper) = self.keys.as_mut() {
+            mapper.key_down(key);
+        }
+    }
+
+    pub fn key_up(&mut self, key: &str) {
+        if let Some(mapper) = self.keys.as_mut() {
+            mapper.key_up(key);
+        }
+    }
+
+    /// Sets the pads from the keys that are down, as a frame starts.
+    fn step_keys(&mut self) {
+        let frame = self.frame_count();
+        if let Some(mapper) = self.keys.as_mut() {
+            mapper.set_frame(frame);
+            let buttons = mapper.buttons();
+            for (player, &held) in buttons.iter().enumerate() {
+                self.cpu.memory.INPUT.set_buttons(player, held);
+            }
+        }
+    }
+
+    /// Presses the reset button. Unlike a power cycle, RAM and the board
+    ///  keep their state; the CPU, PPU and APU reset.
+    pub fn reset(&mut self) {
+        if let Some(session) = self.movie.as_mut() {
+            session.add_command(MOVIE_SOFT_RESET);
+        }
+        self.cpu.memory.PPU.reset();
+        self.cpu.memory.APU.reset();
+        self.cpu.reset();
+    }
+
+    /// Turns the NES off and on: RAM is cleared, and the CPU, PPU and APU
+    ///  start over. The board powers on too, so mapper registers reset and
+    ///  only battery-backed PRG-RAM keeps its contents, the way a movie
+    ///  expects a fresh boot to look. Channel controls and input devices are
+    ///  host settings, and are kept.
+    pub fn power_cycle(&mut self) {
+        if let Some(session) = self.movie.as_mut() {
+            session.add_command(MOVIE_POWER);
+        }
+        let memory = &mut self.cpu.memory;
+        memory.clear_ram();
+        memory.CART.power_on();
+        memory.stall = 0;
+        memory.PPU = PPU::new();
+        let controls = memory.APU.controls.clone();
+        memory.APU = APU::new();
+        memory.APU.controls = controls;
+        self.cpu.a = 0;
+        self.cpu.x = 0;
+        self.cpu.y = 0;
+        self.cpu.status = 0x24;
+        self.cpu.interrupt = 0;
+        self.cpu.stall = 0;
+        self.cpu.reset();
+    }
+
+    /// MD5 of the ROM's PRG and CHR, as FM2 movies identify it.
+    pub fn rom_checksum(&self) -> [u8; 16] {
+        self.rom_md5
+    }
+
+    /// Power cycles, and starts recording the input of every frame into a
+    ///  movie. The movie's ports follow what's plugged in.
+    pub fn record_movie(&mut self) {
+        self.movie = None;
+        self.detach_battery();
+        self.power_cycle();
+        let mut movie = Movie::new(&self.rom_name, self.rom_md5);
+        let input = &self.cpu.memory.INPUT;
+        movie.four_score = input.ports[0].as_any().is::<FourScore>();
+        for (port, device) in input.ports.iter().enumerate() {
+            movie.ports[port] = if device.as_any().is::<Controller>() { SI_GAMEPAD }
+                else if device.as_any().is::<Zapper>() { SI_ZAPPER }
+                else { SI_NONE };
+        }
+        self.movie = Some(MovieSession::new(movie, MovieMode::Record));
+    }
+
+    /// Power cycles, plugs in the movie's devices, and plays it back a
+    ///  frame at a time from step_frame(). Refuses movies made on another
+    ///  ROM, unless they have no checksum.
+    pub fn play_movie(&mut self, movie: Movie) -> Result<(), String> {
+        if movie.rom_checksum != [0; 16] && movie.rom_checksum != self.rom_md5 {
+            return Err(format!("The movie was made with another ROM ({}).", movie.rom_filename));
+        }
+        self.movie = None;
+        self.detach_battery();
+        self.set_four_score(movie.four_score);
+        if !movie.four_score {
+            for (port, &kind) in movie.ports.iter().enumerate() {
+                if kind == SI_ZAPPER {
+                    self.plug(port, Box::new(Zapper::new(false)));
+                }
+            }
+        }
+        self.power_cycle();
+        self.movie = Some(MovieSession::new(movie, MovieMode::Play));
+        Ok(())
+    }
+
+    /// Stops recording or playback, and returns the movie.
+    pub fn stop_movie(&mut self) -> Option<Movie> {
+        self.movie.take().map(|session| session.movie)
+    }
+
+    pub fn movie(&self) -> Option<&MovieSession> {
+        self.movie.as_ref()
+    }
+
+    /// True once a movie being played back has run out of input.
+    pub fn movie_finished(&self) -> bool {
+        self.movie.as_ref().is_some_and(|session| session.is_finished())
+    }
+
+    /// Records or plays back the input for the frame that's starting.
+    fn step_movie(&mut self) {
+        let mut session = match self.movie.take() {
+            Some(session) => session,
+            None => return,
+        };
+        let input = &mut self.cpu.memory.INPUT;
+        let mut held = MovieFrame::default();
+        for player in 0..4 {
+            held.pads[player] = input.pad_mut(player).map_or(0, |pad| pad.buttons);
+        }
+        for port in 0..2 {
+            held.zappers[port] = input.device_mut::<Zapper>(port).map(|z| {
+                let (x, y) = z.aim.unwrap_or((0, 0));
+                (x.min(255) as u8, y.min(255) as u8, z.trigger)
+            });
+        }
+
+        if let Some(frame) = session.next_frame(held) {
+            if frame.commands & MOVIE_POWER != 0 {
+                self.power_cycle();
+            }
+            else if frame.commands & MOVIE_SOFT_RESET != 0 {
+                self.reset();
+            }
+            for player in 0..4 {
+                self.set_buttons(player, frame.pads[player]);
+            }
+            for (port, zapper) in frame.zappers.iter().enumerate() {
+                if let (Some((x, y, trigger)), Some(z)) = (zapper, self.device_mut::<Zapper>(port)) {
+                    z.aim = Some((*x as usize, *y as usize));
+                    z.trigger = *trigger;
+                }
+            }
+        }
+        self.movie = Some(session);
+    }
+
+    /// CRC-32 of RAM followed by the last frame, to check a replay ended
+    ///  up where it should.
+    pub fn replay_hash(&self) -> u32 {
+        let mut crc = Crc32::new();
+        crc.update(self.cpu.memory.ram());
+        for pixel in self.frame_buffer() {
+            crc.update(&pixel.to_le_bytes());
+        }
+        crc.finish()
+    }
+
+    /// Number of frames the PPU has output since power on.
+    pub fn frame_count(&self) -> u64 {
+        self.cpu.memory.PPU.frame
+    }
+
+    /// Restarts the audio pipeline at a new host sample rate. Samples still
+    ///  in the old ring buffer are lost, so fetch audio_ring() again.
+    ///  Recordings keep the rate they were started at.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.audio = Audio::new(sample_rate);
+    }
+
+    /// Where finished audio samples go, at the host sample rate.
+    pub fn audio_ring(&self) -> Arc<RingBuffer> {
+        self.audio.ring()
+    }
+
+    /// Sets a channel's volume, 1.0 is normal. Negative values are clamped.
+    pub fn set_channel_volume(&mut self, channel: Channel, volume: f32) {
+        self.cpu.memory.APU.controls.volume[channel as usize] = volume.max(0.0);
+    }
+
+    pub fn set_channel_muted(&mut self, channel: Channel, muted: bool) {
+        self.cpu.memory.APU.controls.muted[channel as usize] = muted;
+    }
+
+    /// While any channel is soloed, only soloed channels play.
+    pub fn set_channel_solo(&mut self, channel: Channel, solo: bool) {
+        self.cpu.memory.APU.controls.solo[channel as usize] = solo;
+    }
+
+    pub fn channel_controls(&self) -> &ChannelControls {
+        &self.cpu.memory.APU.controls
+    }
+
+    /// Sets all of the buttons a player holds, as BUTTON_* bits. Players
+    ///  0 and 1 are the ports, 2 and 3 need a Four Score.
+    pub fn set_buttons(&mut self, player: usize, buttons: u8) {
+        self.cpu.memory.INPUT.set_buttons(player, buttons);
+    }
+
+    /// Presses or releases one of a player's buttons.
+    pub fn set_button(&mut self, player: usize, button: u8, pressed: bool) {
+        self.cpu.memory.INPUT.set_button(player, button, pressed);
+    }
+
+    /// Plugs a device into port 0, 1 or PORT_EXPANSION.
+    pub fn plug(&mut self, port: usize, device: Box<dyn InputDevice>) {
+        self.cpu.memory.INPUT.plug(port, device);
+    }
+
+    /// Plugs a Four Score into both ports (or goes back to two pads).
+    pub fn set_four_score(&mut self, enabled: bool) {
+        for port in 0..2 {
+            if enabled {
+                self.plug(port, Box::new(FourScore::new(port)));
+            } else {
+                self.plug(port, Box::new(Controller::new()));
+            }
+        }
+    }
+
+    /// Plugs in the devices a config asks for.
+    pub fn configure_input(&mut self, config: &InputConfig) {
+        config.apply(&mut self.cpu.memory.INPUT);
+    }
+
+    /// The device in a port, if it's a T. E.g. to aim a Zapper:
+    ///  nes.device_mut::<Zapper>(1).unwrap().aim = Some((128, 120));
+    pub fn device_mut<T: InputDevice + 'static>(&mut self, port: usize) -> Option<&mut T> {
+        self.cpu.memory.INPUT.device_mut::<T>(port)
+    }
+
+    /// Starts recording audio to a WAV file, at the current sample rate.
+    ///  With per_channel, each APU channel is also written to its own file
+    ///  next to it (song.wav -> song_pulse1.wav, ...). Any recording that
+    ///  was already running is finished first.
+    pub fn start_recording<P: AsRef<Path>>(&mut self, path: P, per_channel: bool) -> io::Result<()> {
+        self.stop_recording()?;
+        self.recorder = Some(AudioRecorder::new(path, self.audio.sample_rate(), per_channel)?);
+        Ok(())
+    }
+
+    /// Finishes the recording, and returns what was written (mixed file
+    ///  first) with the CRC-32 of each file's samples.
+    pub fn stop_recording(&mut self) -> io::Result<Vec<RecordedFile>> {
+        match self.recorder.take() {
+            Some(recorder) => recorder.finish(),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    /// The last full frame, in raw PPU format (see filter.rs).
+    pub fn frame_buffer(&self) -> &[u16] {
+        self.cpu.memory.PPU.frame_buffer()
+    }
+
+    /// The last full frame as an RGB image, in the console's palette.
+    pub fn screenshot(&self) -> Image {
+        Image::from_raw(NES_WIDTH, NES_HEIGHT, self.frame_buffer(), self.palette)
+    }
+
+    /// The palette the PPU's colors turn into: NES_PALETTE, or
+    ///  RGB_PPU_PALETTE for arcade boards.
+    pub fn palette(&self) -> &'static [u32; 64] {
+        self.palette
+    }
+
+    /// Draws frames with another palette, e.g. NES_PALETTE to see an arcade
+    ///  game in home console colors.
+    pub fn set_palette(&mut self, palette: &'static [u32; 64]) {
+        self.palette = palette;
+        self.cpu.memory.INPUT.palette = palette;
+    }
+
+    /// DIP switches, coins and the service button, on Vs. System games.
+    pub fn vs_system(&mut self) -> Option<&mut VsSystem> {
+        self.cpu.memory.VS.as_mut()
+    }
+
+    /// Drops a coin into slot 0 or 1 of a Vs. System cabinet. Does nothing
+    ///  on other games.
+    pub fn insert_coin(&mut self, slot: usize) {
+        if let Some(vs) = self.vs_system() {
+            vs.insert_coin(slot);
+        }
+    }
+
+    /// The INST-ROM and PROM, when running a PlayChoice-10 game.
+    pub fn playchoice(&self) -> Option<&Playchoice> {
+        self.playchoice.as_ref()
+    }
+
+    /// Writes the last full frame to disk, as PNG or PPM by file extension.
+    pub fn save_screenshot<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.screenshot().save(path)
+    }
+
+    /// Pattern table 0 or 1 as seen by the PPU, drawn with palette 0-7.
+    pub fn pattern_table_image(&self, table: u16, palette: u16) -> Image {
+        ppu_debug::pattern_table_image(&self.cpu.memory.PPU, self.palette, &*self.cpu.memory.CART,
+                                       table, palette)
+    }
+
+    /// All four nametables, with the current scroll rectangle outlined.
+    pub fn nametable_image(&self) -> Image {
+        ppu_debug::nametable_image(&self.cpu.memory.PPU, self.palette, &*self.cpu.memory.CART)
+    }
+
+    /// The 64 OAM sprites.
+    pub fn oam_image(&self) -> Image {
+        ppu_debug::oam_image(&self.cpu.memory.PPU, self.palette, &*self.cpu.memory.CART)
+    }
+
+    /// The 32 palette RAM entries.
+    pub fn palette_image(&self) -> Image {
+        ppu_debug::palette_image(&self.cpu.memory.PPU, self.palette)
+    }
+
+    /// Writes all of the PPU debug views as PNGs into a directory:
+    ///  pattern0.png, pattern1.png, nametables.png, oam.png, palette.png,
+    ///  and instructions.png for PlayChoice-10 games with an INST-ROM.
+    pub fn dump_ppu<P: AsRef<Path>>(&self, dir: P, palette: u16) -> io::Result<()> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)?;
+        if let Some(image) = self.playchoice.as_ref().and_then(Playchoice::instruction_image) {
+            image.save(dir.join("instructions.png"))?;
+        }
+        self.pattern_table_image(0, palette).save(dir.join("pattern0.png"))?;
+        self.pattern_table_image(1, palette).save(dir.join("pattern1.png"))?;
+        self.nametable_image().save(dir.join("nametables.png"))?;
+        self.oam_image().save(dir.join("oam.png"))?;
+        self.palette_image().save(dir.join("palette.png"))
+    }
+
     //Ends fde loop, deallocates if needed.
     //This likely won't be needed considering the ultimate lack of
     // pointers needed so far, but it's a logical state to at least
     // define for now.
     pub fn shutdown (&mut self){
+        if let Err(e) = self.stop_recording() {
+            error!("ERROR    -> Could not finish WAV recording: {}", e);
+        }
+        if let Err(e) = self.flush_battery() {
+            error!("ERROR    -> Could not save battery RAM: {}", e);
+        }
 
     }
 
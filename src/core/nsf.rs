@@ -0,0 +1,411 @@
+/* NSF and NSFe music files.
+ * An NSF is a ripped sound engine: 6502 code and data, an INIT routine
+ *  that's called once per track, and a PLAY routine called at a fixed
+ *  rate (usually 60 Hz). There's no PPU involved, the player calls the
+ *  routines directly. See nesdev "NSF" and "NSFe".
+ *
+ * Memory, through NsfMapper:
+ * $5FF8-$5FFF     =      4kb bank select for $8000-$FFFF (bankswitched tunes)
+ * $6000-$7FFF     =      8kb RAM
+ * $8000-$FFFF     =      Program data
+ *
+ * Expansion sound: VRC6 is emulated (see expansion.rs). The other chips
+ *  are recognized, but their writes are dropped.
+ */
+
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::Path;
+
+use crate::core::cpu::CPU;
+use crate::core::expansion::Vrc6Audio;
+use crate::core::mapper::MAP;
+
+/// Expansion sound flags, header byte $7B (or the NSFe INFO chunk).
+pub const EXPANSION_VRC6:   u8 = 0x01;
+pub const EXPANSION_VRC7:   u8 = 0x02;
+pub const EXPANSION_FDS:    u8 = 0x04;
+pub const EXPANSION_MMC5:   u8 = 0x08;
+pub const EXPANSION_N163:   u8 = 0x10;
+pub const EXPANSION_S5B:    u8 = 0x20;
+
+/// Region flags, header byte $7A.
+pub const REGION_PAL:   u8 = 0x01;
+pub const REGION_DUAL:  u8 = 0x02;
+
+/// Play rate in microseconds, when a file doesn't say.
+pub const DEFAULT_NTSC_SPEED: u16 = 16639;
+pub const DEFAULT_PAL_SPEED:  u16 = 19997;
+
+/// Where INIT and PLAY return to. Nothing is ever run from here, the
+///  player sees the CPU arrive and leaves it idle until the next call.
+pub const NSF_RETURN: u16 = 0x5FF0;
+
+const NSF_HEADER_SIZE: usize = 0x80;
+const BANK_SIZE: usize = 0x1000;
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Reads a null-terminated (or 32 byte padded) string.
+fn read_string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+fn read_word(bytes: &[u8], at: usize) -> u16 {
+    bytes[at] as u16 | ((bytes[at + 1] as u16) << 8)
+}
+
+/// A loaded NSF or NSFe file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Nsf {
+    /// NSF version, 0 for NSFe.
+    pub version:        u8,
+    pub total_songs:    u8,
+    /// 1-based, like the header.
+    pub starting_song:  u8,
+    pub load_address:   u16,
+    pub init_address:   u16,
+    pub play_address:   u16,
+    pub name:           String,
+    pub artist:         String,
+    pub copyright:      String,
+    /// PLAY period in microseconds.
+    pub ntsc_speed:     u16,
+    pub pal_speed:      u16,
+    /// Initial banks for $8000-$FFFF, all zero when not bankswitched.
+    pub bankswitch:     [u8; 8],
+    pub region:         u8,
+    pub expansion:      u8,
+    /// NSFe only, may be shorter than total_songs.
+    pub track_labels:   Vec<String>,
+    /// NSFe only, track lengths in milliseconds (negative is unknown).
+    pub track_times:    Vec<i32>,
+    pub data:           Vec<u8>,
+}
+
+impl Nsf {
+    /// Loads an NSF or NSFe, telling them apart by their magic.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Nsf> {
+        let mut bytes = Vec::new();
+        File::open(path)?.read_to_end(&mut bytes)?;
+        Nsf::from_bytes(&bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Nsf> {
+        if bytes.starts_with(b"NESM\x1A") {
+            Nsf::parse_nsf(bytes)
+        }
+        else if bytes.starts_with(b"NSFE") {
+            Nsf::parse_nsfe(bytes)
+        }
+        else {
+            Err(invalid("Not an NSF or NSFe file."))
+        }
+    }
+
+    fn parse_nsf(bytes: &[u8]) -> io::Result<Nsf> {
+        if bytes.len() < NSF_HEADER_SIZE {
+            return Err(invalid("NSF header is cut short."));
+        }
+        let mut bankswitch = [0; 8];
+        bankswitch.copy_from_slice(&bytes[0x70..0x78]);
+
+        //NSF2 can give the program length, so metadata can follow it.
+        let length = bytes[0x7D] as usize | (bytes[0x7E] as usize) << 8 | (bytes[0x7F] as usize) << 16;
+        let end = if bytes[5] >= 2 && length > 0 {
+            (NSF_HEADER_SIZE + length).min(bytes.len())
+        } else {
+            bytes.len()
+        };
+
+        Ok(Nsf {
+            version:        bytes[5],
+            total_songs:    bytes[6],
+            starting_song:  bytes[7].max(1),
+            load_address:   read_word(bytes, 0x08),
+            init_address:   read_word(bytes, 0x0A),
+            play_address:   read_word(bytes, 0x0C),
+            name:           read_string(&bytes[0x0E..0x2E]),
+            artist:         read_string(&bytes[0x2E..0x4E]),
+            copyright:      read_string(&bytes[0x4E..0x6E]),
+            ntsc_speed:     read_word(bytes, 0x6E),
+            pal_speed:      read_word(bytes, 0x78),
+            bankswitch,
+            region:         bytes[0x7A] & 3,
+            expansion:      bytes[0x7B],
+            track_labels:   Vec::new(),
+            track_times:    Vec::new(),
+            data:           bytes[NSF_HEADER_SIZE..end].to_vec(),
+        })
+    }
+
+    /// NSFe is a list of chunks: length (4 bytes), id (4 bytes), data.
+    fn parse_nsfe(bytes: &[u8]) -> io::Result<Nsf> {
+        let mut nsf = Nsf {
+            ntsc_speed: DEFAULT_NTSC_SPEED,
+            pal_speed:  DEFAULT_PAL_SPEED,
+            starting_song: 1,
+            ..Nsf::default()
+        };
+        let (mut has_info, mut has_data) = (false, false);
+
+        let mut at = 4;
+        while at + 8 <= bytes.len() {
+            let length = u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]]) as usize;
+            let id = &bytes[at + 4..at + 8];
+            let chunk = bytes.get(at + 8..at + 8 + length).ok_or_else(|| invalid("NSFe chunk is cut short."))?;
+            at += 8 + length;
+
+            match id {
+                b"INFO" => {
+                    if chunk.len() < 8 {
+                        return Err(invalid("NSFe INFO chunk is too small."));
+                    }
+                    nsf.load_address = read_word(chunk, 0);
+                    nsf.init_address = read_word(chunk, 2);
+                    nsf.play_address = read_word(chunk, 4);
+                    nsf.region = chunk[6] & 3;
+                    nsf.expansion = chunk[7];
+                    nsf.total_songs = chunk.get(8).cloned().unwrap_or(1);
+                    nsf.starting_song = chunk.get(9).cloned().unwrap_or(0) + 1;
+                    has_info = true;
+                }
+                b"DATA" => {
+                    nsf.data = chunk.to_vec();
+                    has_data = true;
+                }
+                b"BANK" => {
+                    for (bank, &b) in nsf.bankswitch.iter_mut().zip(chunk.iter()) {
+                        *bank = b;
+                    }
+                }
+                b"RATE" => {
+                    if chunk.len() >= 2 { nsf.ntsc_speed = read_word(chunk, 0); }
+                    if chunk.len() >= 4 { nsf.pal_speed = read_word(chunk, 2); }
+                }
+                b"auth" => {
+                    let mut strings = chunk.split(|&b| b == 0).map(read_string);
+                    nsf.name = strings.next().unwrap_or_default();
+                    nsf.artist = strings.next().unwrap_or_default();
+                    nsf.copyright = strings.next().unwrap_or_default();
+                }
+                b"tlbl" => {
+                    nsf.track_labels = chunk.split(|&b| b == 0).map(read_string).collect();
+                    //The last label is terminated too, so split leaves an empty tail.
+                    nsf.track_labels.pop();
+                }
+                b"time" => {
+                    nsf.track_times = chunk.chunks_exact(4)
+                        .map(|t| i32::from_le_bytes([t[0], t[1], t[2], t[3]]))
+                        .collect();
+                }
+                b"NEND" => break,
+                //Chunks starting with a capital letter must be understood.
+                _ if id[0].is_ascii_uppercase() => {
+                    return Err(invalid("NSFe has a required chunk we don't support."));
+                }
+                _ => {}
+            }
+        }
+
+        if !has_info || !has_data {
+            return Err(invalid("NSFe is missing its INFO or DATA chunk."));
+        }
+        Ok(nsf)
+    }
+
+    /// Bankswitched tunes have any non-zero initial bank.
+    pub fn is_bankswitched(&self) -> bool {
+        self.bankswitch.iter().any(|&b| b != 0)
+    }
+
+    /// PAL only tunes are told so with X = 1 in INIT, and use the PAL rate.
+    pub fn is_pal(&self) -> bool {
+        self.region & (REGION_PAL | REGION_DUAL) == REGION_PAL
+    }
+
+    /// PLAY period in CPU cycles. The APU always runs at the NTSC clock,
+    ///  so PAL tunes play at their own rate but NTSC pitch.
+    pub fn play_period(&self) -> f64 {
+        let speed = if self.is_pal() { self.pal_speed } else { self.ntsc_speed };
+        let speed = if speed == 0 {
+            if self.is_pal() { DEFAULT_PAL_SPEED } else { DEFAULT_NTSC_SPEED }
+        } else {
+            speed
+        };
+        speed as f64 * 1.789_773
+    }
+
+    /// Expansion chips the tune wants that we don't emulate.
+    pub fn unsupported_expansion(&self) -> u8 {
+        self.expansion & !EXPANSION_VRC6
+    }
+}
+
+//~MAPPER~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+/// The NSF "cartridge": 4kb banks of program data, 8kb of RAM, and any
+///  expansion sound.
+pub struct NsfMapper {
+    rom:            Vec<u8>,
+    pub banks:      [usize; 8],
+    bankswitched:   bool,
+    pub ram:        [u8; 0x2000],
+    pub vrc6:       Option<Vrc6Audio>,
+}
+
+impl NsfMapper {
+    pub fn new(nsf: &Nsf) -> NsfMapper {
+        let bankswitched = nsf.is_bankswitched();
+        let rom = if bankswitched {
+            //Data is placed at the load address' offset into its bank.
+            let mut rom = vec![0; (nsf.load_address as usize) & 0xFFF];
+            rom.extend_from_slice(&nsf.data);
+            let size = rom.len().div_ceil(BANK_SIZE).max(1) * BANK_SIZE;
+            rom.resize(size, 0);
+            rom
+        }
+        else {
+            let mut rom = vec![0; BANK_SIZE * 8];
+            let start = (nsf.load_address as usize).saturating_sub(0x8000);
+            let len = nsf.data.len().min(rom.len().saturating_sub(start));
+            rom[start..start + len].copy_from_slice(&nsf.data[..len]);
+            rom
+        };
+
+        let mut mapper = NsfMapper {
+            rom,
+            banks:  [0, 1, 2, 3, 4, 5, 6, 7],
+            bankswitched,
+            ram:    [0; 0x2000],
+            vrc6:   if nsf.expansion & EXPANSION_VRC6 != 0 { Some(Vrc6Audio::new()) } else { None },
+        };
+        if bankswitched {
+            for (i, &bank) in nsf.bankswitch.iter().enumerate() {
+                mapper.select_bank(i, bank);
+            }
+        }
+        mapper
+    }
+
+    fn select_bank(&mut self, slot: usize, bank: u8) {
+        self.banks[slot] = bank as usize % (self.rom.len() / BANK_SIZE);
+    }
+}
+
+impl MAP for NsfMapper {
+    fn get(&self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7FFF => self.ram[(address - 0x6000) as usize],
+            0x8000..=0xFFFF => {
+                let slot = ((address - 0x8000) as usize) / BANK_SIZE;
+                self.rom[self.banks[slot] * BANK_SIZE + (address as usize & 0xFFF)]
+            }
+            _ => 0,
+        }
+    }
+    fn set(&mut self, address: u16, val: u8) {
+        match address {
+            0x5FF8..=0x5FFF => {
+                if self.bankswitched {
+                    self.select_bank((address - 0x5FF8) as usize, val);
+                }
+            }
+            0x6000..=0x7FFF => self.ram[(address - 0x6000) as usize] = val,
+            _ => {
+                if let Some(vrc6) = self.vrc6.as_mut() {
+                    if Vrc6Audio::handles(address) {
+                        vrc6.write(address, val);
+                    }
+                }
+            }
+        }
+    }
+    fn get_chr(&self, _address: u16) -> u8 { 0 }
+    fn set_chr(&mut self, _address: u16, _val: u8) {}
+    fn step(&mut self) {
+        if let Some(vrc6) = self.vrc6.as_mut() {
+            vrc6.step();
+        }
+    }
+    fn expansion_audio(&self) -> f32 {
+        self.vrc6.as_ref().map_or(0.0, |v| v.output())
+    }
+}
+
+//~PLAYER~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+/// Drives INIT and PLAY. The CPU runs the routines like any other code,
+///  and sits idle (cycles still pass, so the APU keeps going) in between.
+pub struct NsfPlayer {
+    pub nsf:        Nsf,
+    /// 0-based.
+    pub track:      u8,
+    period:         f64,
+    next_play:      f64,
+}
+
+impl NsfPlayer {
+    pub fn new(nsf: Nsf) -> NsfPlayer {
+        let period = nsf.play_period();
+        let track = nsf.starting_song - 1;
+        NsfPlayer { nsf, track, period, next_play: 0.0 }
+    }
+
+    /// Resets the sound hardware and RAM, and calls INIT for a track.
+    pub fn start_track(&mut self, cpu: &mut CPU, track: u8) {
+        self.track = track % self.nsf.total_songs.max(1);
+
+        let mem = &mut cpu.memory;
+        mem.clear_ram();
+        for address in 0x6000..0x8000 {
+            mem.set(address, 0);
+        }
+        for address in 0x4000..0x4014 {
+            mem.set(address, 0);
+        }
+        mem.set(0x4015, 0x00);
+        mem.set(0x4015, 0x0F);
+        mem.set(0x4017, 0x40);
+        for (i, &bank) in self.nsf.bankswitch.iter().enumerate() {
+            mem.set(0x5FF8 + i as u16, bank);
+        }
+
+        cpu.a = self.track;
+        cpu.x = if self.nsf.is_pal() { 1 } else { 0 };
+        cpu.y = 0;
+        cpu.sp = 0xFD;
+        cpu.status = 0x24;
+        cpu.call(self.nsf.init_address, NSF_RETURN);
+        self.next_play = cpu.cycles as f64 + self.period;
+    }
+
+    /// True while INIT or PLAY is running.
+    pub fn is_busy(&self, cpu: &CPU) -> bool {
+        cpu.pc != NSF_RETURN
+    }
+
+    /// Called instead of CPU::step. Returns false when the CPU should run
+    ///  an instruction, otherwise it either called PLAY or let a cycle pass.
+    pub fn step(&mut self, cpu: &mut CPU) -> bool {
+        if self.is_busy(cpu) {
+            return false;
+        }
+        if cpu.cycles as f64 >= self.next_play {
+            //A PLAY that runs long delays the next one, like real players.
+            self.next_play += self.period;
+            cpu.call(self.nsf.play_address, NSF_RETURN);
+            return false;
+        }
+        //Idle, so nothing is there to stall.
+        cpu.memory.stall = 0;
+        cpu.cycles += 1;
+        true
+    }
+}
+
+#[cfg(test)]
+#[path = "./nsf_test.rs"]
+pub mod nsf_test;
@@ -0,0 +1,180 @@
+/*  Unit test module of the NSF player (nsf.rs), and VRC6 sound (expansion.rs).
+ */
+use crate::core::nsf::*;
+
+#[cfg(test)]
+pub mod nsf_test {
+    use super::*;
+    use crate::core::expansion::Vrc6Audio;
+    use crate::core::nes::NES;
+
+    /// Builds an NSF around some code loaded at $8000.
+    fn nsf_bytes(songs: u8, start: u8, init: u16, play: u16, code: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![0; 0x80];
+        bytes[..5].copy_from_slice(b"NESM\x1A");
+        bytes[5] = 1;
+        bytes[6] = songs;
+        bytes[7] = start;
+        bytes[0x08..0x0A].copy_from_slice(&0x8000u16.to_le_bytes());
+        bytes[0x0A..0x0C].copy_from_slice(&init.to_le_bytes());
+        bytes[0x0C..0x0E].copy_from_slice(&play.to_le_bytes());
+        bytes[0x0E..0x13].copy_from_slice(b"Title");
+        bytes[0x2E..0x34].copy_from_slice(b"Artist");
+        bytes[0x6E..0x70].copy_from_slice(&DEFAULT_NTSC_SPEED.to_le_bytes());
+        bytes.extend_from_slice(code);
+        bytes
+    }
+
+    #[test]
+    fn test_nsf_header(){
+        let nsf = Nsf::from_bytes(&nsf_bytes(3, 2, 0x8000, 0x8010, &[0x60])).unwrap();
+        assert_eq!(nsf.name, "Title");
+        assert_eq!(nsf.artist, "Artist");
+        assert_eq!(nsf.total_songs, 3);
+        assert_eq!(nsf.starting_song, 2);
+        assert_eq!(nsf.play_address, 0x8010);
+        assert_eq!(nsf.data, vec![0x60]);
+        assert!(!nsf.is_bankswitched());
+        assert!(!nsf.is_pal());
+        assert!((nsf.play_period() - 29780.5).abs() < 1.0);
+
+        assert!(Nsf::from_bytes(b"NES\x1A").is_err());
+        assert!(Nsf::from_bytes(b"NESM\x1A\x01").is_err());
+    }
+
+    #[test]
+    fn test_nsfe(){
+        fn chunk(out: &mut Vec<u8>, id: &[u8], data: &[u8]) {
+            out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            out.extend_from_slice(id);
+            out.extend_from_slice(data);
+        }
+        let mut bytes = b"NSFE".to_vec();
+        chunk(&mut bytes, b"INFO", &[0x00, 0x80, 0x00, 0x80, 0x10, 0x80, 0x00, EXPANSION_VRC6, 4, 1]);
+        chunk(&mut bytes, b"DATA", &[0x60]);
+        chunk(&mut bytes, b"auth", b"Game\0Composer\0\0Ripper\0");
+        chunk(&mut bytes, b"tlbl", b"Intro\0Boss\0");
+        chunk(&mut bytes, b"time", &[0xE8, 0x03, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF]);
+        chunk(&mut bytes, b"xtra", &[1, 2, 3]);
+        chunk(&mut bytes, b"NEND", &[]);
+
+        let nsf = Nsf::from_bytes(&bytes).unwrap();
+        assert_eq!(nsf.total_songs, 4);
+        assert_eq!(nsf.starting_song, 2);
+        assert_eq!(nsf.expansion, EXPANSION_VRC6);
+        assert_eq!(nsf.name, "Game");
+        assert_eq!(nsf.artist, "Composer");
+        assert_eq!(nsf.track_labels, vec!["Intro", "Boss"]);
+        assert_eq!(nsf.track_times, vec![1000, -1]);
+        assert_eq!(nsf.ntsc_speed, DEFAULT_NTSC_SPEED);
+        assert_eq!(nsf.unsupported_expansion(), 0);
+
+        //Unknown required chunks are an error, and so is missing DATA.
+        let mut bad = b"NSFE".to_vec();
+        chunk(&mut bad, b"INFO", &[0x00, 0x80, 0x00, 0x80, 0x10, 0x80, 0x00, 0x00]);
+        chunk(&mut bad, b"WHAT", &[]);
+        assert!(Nsf::from_bytes(&bad).is_err());
+        bad.truncate(bad.len() - 8);
+        assert!(Nsf::from_bytes(&bad).is_err());
+    }
+
+    #[test]
+    fn test_bankswitching(){
+        let mut nsf = Nsf {
+            load_address: 0x8100,
+            bankswitch: [0, 1, 2, 2, 2, 2, 2, 2],
+            data: vec![0xAA; 0x2000],
+            ..Nsf::default()
+        };
+        nsf.data[0x1000 - 0x100] = 0xBB;
+
+        //The data starts $100 into bank 0, which pushes it into 3 banks.
+        let mut mapper = NsfMapper::new(&nsf);
+        assert_eq!(mapper.get(0x8000), 0x00);
+        assert_eq!(mapper.get(0x8100), 0xAA);
+        assert_eq!(mapper.get(0x9000), 0xBB);
+        mapper.set(0x5FF8, 1);
+        assert_eq!(mapper.get(0x8000), 0xBB);
+        //Out of range banks wrap.
+        mapper.set(0x5FFF, 3);
+        assert_eq!(mapper.banks[7], 0);
+
+        //Without bankswitching, data just goes at the load address.
+        nsf.bankswitch = [0; 8];
+        let mut mapper = NsfMapper::new(&nsf);
+        assert_eq!(mapper.get(0x8100), 0xAA);
+        mapper.set(0x5FF8, 1);
+        assert_eq!(mapper.get(0x8100), 0xAA);
+        mapper.set(0x6123, 0x42);
+        assert_eq!(mapper.get(0x6123), 0x42);
+    }
+
+    #[test]
+    fn test_init_and_play(){
+        let mut code = vec![0xEA; 0x20];
+        //INIT: remember the track, and set up pulse 1.
+        code[..14].copy_from_slice(&[
+            0x8D, 0x00, 0x60,   //STA $6000
+            0xA9, 0x01,         //LDA #$01
+            0x8D, 0x15, 0x40,   //STA $4015
+            0xA9, 0xBF,         //LDA #$BF
+            0x8D, 0x00, 0x40,   //STA $4000
+            0x60,               //RTS
+        ]);
+        //PLAY: count the calls.
+        code[0x10..0x14].copy_from_slice(&[0xEE, 0x01, 0x60, 0x60]);   //INC $6001, RTS
+
+        let nsf = Nsf::from_bytes(&nsf_bytes(3, 2, 0x8000, 0x8010, &code)).unwrap();
+        let mut nes = NES::new_nsf(nsf);
+        assert_eq!(nes.nsf().unwrap().track, 1);
+        for _ in 0..10 {
+            nes.step_frame();
+        }
+        assert_eq!(nes.cpu.memory.get(0x6000), 1);
+        let plays = nes.cpu.memory.get(0x6001);
+        assert!((9..=11).contains(&plays), "PLAY ran {} times", plays);
+
+        //Changing tracks clears RAM and calls INIT again.
+        nes.select_track(2);
+        nes.step_frame();
+        assert_eq!(nes.cpu.memory.get(0x6000), 2);
+        assert!(nes.cpu.memory.get(0x6001) <= 1);
+    }
+
+    #[test]
+    fn test_vrc6(){
+        assert!(Vrc6Audio::handles(0x9000));
+        assert!(Vrc6Audio::handles(0xB002));
+        assert!(!Vrc6Audio::handles(0xB003));
+        assert!(!Vrc6Audio::handles(0x8000));
+
+        let mut vrc6 = Vrc6Audio::new();
+        vrc6.write(0x9000, 0x3F);   //Duty 3 (4/16), volume 15.
+        vrc6.write(0x9001, 0x00);
+        vrc6.write(0x9002, 0x80);   //Enabled, period 0.
+        let mut high = 0;
+        for _ in 0..16 {
+            vrc6.step();
+            if vrc6.pulse1.output() == 15 { high += 1; }
+        }
+        assert_eq!(high, 4);
+
+        //The saw climbs by the rate every other step, and resets after 7.
+        vrc6.write(0xB000, 0x08);
+        vrc6.write(0xB002, 0x80);
+        let mut peak = 0;
+        for _ in 0..14 {
+            vrc6.step();
+            peak = peak.max(vrc6.saw.accumulator);
+        }
+        assert_eq!(peak, 0x08 * 6);
+        assert_eq!(vrc6.saw.accumulator, 0);
+
+        //Halting stops everything.
+        vrc6.write(0x9003, 0x01);
+        let step = vrc6.pulse1.step;
+        vrc6.step();
+        assert_eq!(vrc6.pulse1.step, step);
+        assert!(vrc6.output() > 0.0);
+    }
+}
@@ -0,0 +1,106 @@
+/* PlayChoice-10 cartridges.
+ * The PlayChoice-10 is Nintendo's arcade cabinet for NES games. Each game
+ *  runs on an RGB PPU (RP2C03), and a Z80 running the cabinet's BIOS draws
+ *  the game's instructions on a second screen above it. Besides PRG and CHR,
+ *  a cart has:
+ *  - INST-ROM: 8kb the BIOS builds the instruction screen from.
+ *  - PROM: an RP5H01 security chip. Dumps hold its 16 bytes of data, then
+ *    16 bytes of CounterOut. The BIOS checks it before it'll run the game.
+ *
+ * The game itself can't see either, so to it the cabinet is an NES with a
+ *  different palette. The Z80 and BIOS aren't emulated: the INST-ROM and
+ *  PROM are handed to the frontend, which can show the instructions (or
+ *  not) however it likes. instruction_tiles() decodes the INST-ROM as 8x8
+ *  2-bit tiles, like CHR, and instruction_image() draws them in grays for
+ *  a frontend or --dump-ppu to show.
+ *
+ * https://wiki.nesdev.com/w/index.php/PlayChoice-10
+ */
+
+use crate::core::cartridge::CART;
+use crate::core::header::ConsoleType;
+use crate::core::image::Image;
+
+/// Bytes in one INST-ROM tile: two 8 byte bit planes, as in CHR.
+pub const INST_TILE_SIZE: usize = 16;
+/// Tiles per row of instruction_image(). 8kb is 512 tiles, a 256x128 image.
+pub const INST_TILES_PER_ROW: usize = 32;
+/// The grays instruction_image() draws pixel values 0-3 with.
+const INST_GRAYS: [u32; 4] = [0x000000, 0x555555, 0xAAAAAA, 0xFFFFFF];
+
+/// An 8x8 tile of pixel values 0-3, row by row.
+pub type InstTile = [[u8; 8]; 8];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Playchoice {
+    /// Empty if the dump left it out.
+    pub inst_rom:   Vec<u8>,
+    /// Security data then CounterOut, or less (or nothing) if the dump
+    ///  left some out.
+    pub prom:       Vec<u8>,
+}
+
+impl Playchoice {
+    /// The PlayChoice-10 parts of a cart, if it's one.
+    pub fn from_cart(cart: &CART) -> Option<Playchoice> {
+        if cart.header.console != ConsoleType::Playchoice10 {
+            return None;
+        }
+        Some(Playchoice {
+            inst_rom:   cart.INST_ROM[..cart.section_sizes[3] as usize].to_vec(),
+            prom:       cart.PROM[..cart.section_sizes[4] as usize].to_vec(),
+        })
+    }
+
+    /// The instruction screen's ROM, if the dump has it.
+    pub fn instructions(&self) -> Option<&[u8]> {
+        if self.inst_rom.is_empty() { None } else { Some(&self.inst_rom) }
+    }
+
+    /// The INST-ROM as 8x8 tiles, 16 bytes each. Empty without an INST-ROM.
+    pub fn instruction_tiles(&self) -> Vec<InstTile> {
+        self.inst_rom.chunks_exact(INST_TILE_SIZE).map(|planes| {
+            let mut tile = [[0; 8]; 8];
+            for (row, pixels) in tile.iter_mut().enumerate() {
+                for (col, pixel) in pixels.iter_mut().enumerate() {
+                    let bit = 7 - col;
+                    *pixel = (planes[row] >> bit & 1) | (planes[row + 8] >> bit & 1) << 1;
+                }
+            }
+            tile
+        }).collect()
+    }
+
+    /// The INST-ROM's tiles, INST_TILES_PER_ROW to a row, in grays. None
+    ///  without an INST-ROM.
+    pub fn instruction_image(&self) -> Option<Image> {
+        let tiles = self.instruction_tiles();
+        if tiles.is_empty() {
+            return None;
+        }
+        let rows = tiles.len().div_ceil(INST_TILES_PER_ROW);
+        let mut img = Image::new(INST_TILES_PER_ROW * 8, rows * 8);
+        for (i, tile) in tiles.iter().enumerate() {
+            let (x, y) = (i % INST_TILES_PER_ROW * 8, i / INST_TILES_PER_ROW * 8);
+            for (row, pixels) in tile.iter().enumerate() {
+                for (col, &pixel) in pixels.iter().enumerate() {
+                    img.set(x + col, y + row, INST_GRAYS[pixel as usize]);
+                }
+            }
+        }
+        Some(img)
+    }
+
+    /// The RP5H01's 16 bytes, as the BIOS reads them bit by bit.
+    pub fn security_data(&self) -> &[u8] {
+        &self.prom[..self.prom.len().min(16)]
+    }
+
+    pub fn counter_out(&self) -> &[u8] {
+        &self.prom[self.prom.len().min(16)..]
+    }
+}
+
+#[cfg(test)]
+#[path = "./playchoice_test.rs"]
+pub mod playchoice_test;
@@ -0,0 +1,83 @@
+/*  Unit test module of PlayChoice-10 carts (playchoice.rs).
+ */
+use crate::core::playchoice::*;
+
+#[cfg(test)]
+pub mod playchoice_test {
+    use super::*;
+    use crate::core::cartridge::CART;
+    use crate::core::filter::{NES_PALETTE, RGB_PPU_PALETTE};
+    use crate::core::nes::NES;
+
+    /// A PlayChoice-10 NROM, followed by whatever's given.
+    fn rom(after_chr: &[u8]) -> Vec<u8> {
+        let mut bytes = b"NES\x1A\x01\x01\x00\x02".to_vec();
+        bytes.resize(16, 0);
+        bytes.extend(vec![0xEA; 0x4000]);
+        bytes.extend(vec![0; 0x2000]);
+        bytes.extend_from_slice(after_chr);
+        bytes
+    }
+
+    #[test]
+    fn test_playchoice_cart(){
+        let mut extra = vec![0x11; 0x2000];
+        extra.extend(vec![0x22; 16]);
+        extra.extend(vec![0x33; 16]);
+        let nes = NES::from_cart(CART::from_bytes(rom(&extra)).unwrap()).unwrap();
+        let pc10 = nes.playchoice().unwrap();
+        assert_eq!(pc10.instructions().unwrap().len(), 0x2000);
+        assert_eq!(pc10.security_data(), &[0x22; 16]);
+        assert_eq!(pc10.counter_out(), &[0x33; 16]);
+        assert_eq!(nes.palette(), &RGB_PPU_PALETTE);
+        assert_eq!(nes.palette()[0x30], 0xFFFFFF);
+
+        //Without CounterOut, or the INST-ROM at all.
+        extra.truncate(0x2010);
+        let cart = CART::from_bytes(rom(&extra)).unwrap();
+        let pc10 = Playchoice::from_cart(&cart).unwrap();
+        assert_eq!(pc10.security_data().len(), 16);
+        assert!(pc10.counter_out().is_empty());
+        let pc10 = Playchoice::from_cart(&CART::from_bytes(rom(&[])).unwrap()).unwrap();
+        assert_eq!(pc10.instructions(), None);
+
+        //Home carts aren't, whatever comes after CHR.
+        let mut home = rom(&extra);
+        home[7] = 0;
+        let mut nes = NES::from_cart(CART::from_bytes(home).unwrap()).unwrap();
+        assert!(nes.playchoice().is_none());
+        assert_eq!(nes.palette(), &NES_PALETTE);
+        nes.set_palette(&RGB_PPU_PALETTE);
+        assert_eq!(nes.screenshot().pixels[0], RGB_PPU_PALETTE[0]);
+    }
+
+    #[test]
+    fn test_instruction_tiles(){
+        //Tile 1 has a plane 0 diagonal, and plane 1 set on its last row.
+        let mut inst = vec![0; 0x2000];
+        for row in 0..8 {
+            inst[16 + row] = 0x80 >> row;
+        }
+        inst[16 + 15] = 0xFF;
+        let cart = CART::from_bytes(rom(&inst)).unwrap();
+        let pc10 = Playchoice::from_cart(&cart).unwrap();
+
+        let tiles = pc10.instruction_tiles();
+        assert_eq!(tiles.len(), 512);
+        assert_eq!(tiles[0], [[0; 8]; 8]);
+        assert_eq!(tiles[1][0], [1, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(tiles[1][3][3], 1);
+        assert_eq!(tiles[1][7], [2, 2, 2, 2, 2, 2, 2, 3]);
+
+        let image = pc10.instruction_image().unwrap();
+        assert_eq!((image.width, image.height), (256, 128));
+        assert_eq!(image.get(8, 0), 0x555555);
+        assert_eq!(image.get(9, 0), 0);
+        assert_eq!(image.get(15, 7), 0xFFFFFF);
+
+        //Nothing to draw without an INST-ROM.
+        let pc10 = Playchoice::from_cart(&CART::from_bytes(rom(&[])).unwrap()).unwrap();
+        assert!(pc10.instruction_tiles().is_empty());
+        assert!(pc10.instruction_image().is_none());
+    }
+}
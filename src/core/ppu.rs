@@ -0,0 +1,728 @@
+/* Emulates the 2C02 Picture Processing Unit of the NES.
+ * The rendering loop follows fogleman/nes closely: one call to step() is one
+ *  PPU dot, and the CPU drives it at 3 dots per CPU cycle.
+ *
+ *--------------PPU Memory Map-----------------------------------------
+ * $0000-$1FFF     =      Pattern tables (CHR, through the mapper)
+ * $2000-$2FFF     =      Nametables (internal 2kb VRAM, mirrored)
+ * $3000-$3EFF     =      Mirror of $2000-$2EFF
+ * $3F00-$3FFF     =      Palette RAM
+ *---------------------------------------------------------------------
+ *
+ *--------------CPU Registers ($2000-$2007, mirrored to $3FFF)----------
+ * $2000 PPUCTRL  $2001 PPUMASK  $2002 PPUSTATUS  $2003 OAMADDR
+ * $2004 OAMDATA  $2005 PPUSCROLL $2006 PPUADDR   $2007 PPUDATA
+ *---------------------------------------------------------------------
+ *
+ * Quirks that test ROMs check for (see nesdev "PPU registers"):
+ *  - Reading a write-only register returns the I/O latch ("open bus"),
+ *    whose bits decay to 0 about 600ms after they were last driven.
+ *  - $3F10/$3F14/$3F18/$3F1C are mirrors of $3F00/$3F04/$3F08/$3F0C.
+ *  - PPUDATA palette reads skip the read buffer, the buffer gets the
+ *    nametable byte "underneath" the palette instead.
+ *  - With rendering on, the pre-render line is one dot short on odd frames.
+ */
+
+use crate::core::mapper::{MAP, Mirroring};
+use crate::core::filter::{NES_WIDTH, NES_HEIGHT};
+pub use ::log::*;
+
+#[allow(non_snake_case)]
+pub struct PPU {
+    /// Current dot on the scanline, 0-340.
+    pub cycle:          u16,
+    /// Current scanline, 0-239 visible, 241-260 vblank, 261 pre-render.
+    pub scanline:       u16,
+    /// Number of frames rendered so far.
+    pub frame:          u64,
+
+    /// Palette RAM, 32 entries.
+    pub palette:        [u8; 32],
+    /// Nametable RAM, 4kb so that four screen boards also work.
+    pub nametables:     [u8; 4096],
+    /// Object Attribute Memory, 64 sprites of 4 bytes.
+    pub oam:            [u8; 256],
+
+    /// Current VRAM address (15 bits).
+    pub v:              u16,
+    /// Temporary VRAM address (15 bits), top left onscreen tile.
+    pub t:              u16,
+    /// Fine x scroll (3 bits).
+    pub x:              u8,
+    /// Write toggle for $2005/$2006.
+    pub w:              bool,
+
+    /// I/O latch ("PPU open bus"), the value last driven on the data bus.
+    io_latch:           u8,
+    /// Dot count at which each latch bit was last driven high, for decay.
+    latch_refresh:      [u64; 8],
+    /// Total dots run since power on.
+    pub dots:           u64,
+    /// True while on an odd frame, for the skipped dot.
+    pub odd_frame:      bool,
+
+    //NMI flags.
+    nmi_occurred:       bool,
+    nmi_output:         bool,
+    nmi_previous:       bool,
+    /// Set on the rising edge of NMI, cleared by whoever services it.
+    pub nmi_pending:    bool,
+
+    //Background temporary variables.
+    nametable_byte:     u8,
+    attribute_byte:     u8,
+    low_tile_byte:      u8,
+    high_tile_byte:     u8,
+    tile_data:          u64,
+
+    //Sprite temporary variables.
+    sprite_count:       usize,
+    sprite_patterns:    [u32; 8],
+    sprite_positions:   [u8; 8],
+    sprite_priorities:  [u8; 8],
+    sprite_indexes:     [u8; 8],
+
+    /// $2000 PPUCTRL
+    pub ctrl:           u8,
+    /// $2001 PPUMASK
+    pub mask:           u8,
+    //$2002 PPUSTATUS
+    sprite_zero_hit:    bool,
+    sprite_overflow:    bool,
+    /// $2003 OAMADDR
+    pub oam_address:    u8,
+    /// $2007 PPUDATA read buffer.
+    buffered_data:      u8,
+
+    //Raw output (see filter.rs): front is the last full frame, back is the
+    // one being drawn.
+    front:              Vec<u16>,
+    back:               Vec<u16>,
+}
+
+impl PPU {
+    pub fn new() -> PPU {
+        PPU {
+            cycle:              340,
+            scanline:           240,
+            frame:              0,
+            palette:            [0; 32],
+            nametables:         [0; 4096],
+            oam:                [0; 256],
+            v:                  0,
+            t:                  0,
+            x:                  0,
+            w:                  false,
+            io_latch:           0,
+            latch_refresh:      [0; 8],
+            dots:               0,
+            odd_frame:          false,
+            nmi_occurred:       false,
+            nmi_output:         false,
+            nmi_previous:       false,
+            nmi_pending:        false,
+            nametable_byte:     0,
+            attribute_byte:     0,
+            low_tile_byte:      0,
+            high_tile_byte:     0,
+            tile_data:          0,
+            sprite_count:       0,
+            sprite_patterns:    [0; 8],
+            sprite_positions:   [0; 8],
+            sprite_priorities:  [0; 8],
+            sprite_indexes:     [0; 8],
+            ctrl:               0,
+            mask:               0,
+            sprite_zero_hit:    false,
+            sprite_overflow:    false,
+            oam_address:        0,
+            buffered_data:      0,
+            front:              vec![0; NES_WIDTH * NES_HEIGHT],
+            back:               vec![0; NES_WIDTH * NES_HEIGHT],
+        }
+    }
+
+    /// Resets the PPU, as in pressing the reset button.
+    pub fn reset(&mut self) {
+        self.cycle = 340;
+        self.scanline = 240;
+        self.frame = 0;
+        self.odd_frame = false;
+        self.write_control(0);
+        self.mask = 0;
+        self.oam_address = 0;
+    }
+
+    /// The last completed frame in raw PPU output format (see filter.rs).
+    pub fn frame_buffer(&self) -> &[u16] {
+        &self.front
+    }
+
+    /// The frame being drawn. Rows above the current scanline are from
+    ///  this frame, the rest are from two frames ago.
+    pub fn drawing_buffer(&self) -> &[u16] {
+        &self.back
+    }
+
+    //~PPU~MEMORY~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+
+    /// Reads from the PPU's own 14-bit address space.
+    pub fn read(&self, map: &dyn MAP, address: u16) -> u8 {
+        let address = address % 0x4000;
+        match address {
+            0x0000..=0x1FFF => map.get_chr(address),
+            0x2000..=0x3EFF => self.nametables[self.nametable_index(map, address)],
+            _               => self.palette[palette_index(address)],
+        }
+    }
+
+    /// Writes to the PPU's own 14-bit address space.
+    pub fn write(&mut self, map: &mut dyn MAP, address: u16, val: u8) {
+        let address = address % 0x4000;
+        match address {
+            0x0000..=0x1FFF => map.set_chr(address, val),
+            0x2000..=0x3EFF => {
+                let i = self.nametable_index(map, address);
+                self.nametables[i] = val;
+            }
+            _               => self.palette[palette_index(address)] = val & 0x3F,
+        }
+    }
+
+    /// Reads a palette entry, used when outputting pixels.
+    pub fn read_palette(&self, address: u16) -> u8 {
+        self.palette[palette_index(address)]
+    }
+
+    /// Maps $2000-$2FFF onto the nametable RAM, using the board mirroring.
+    fn nametable_index(&self, map: &dyn MAP, address: u16) -> usize {
+        let address = (address - 0x2000) % 0x1000;
+        let table = (address / 0x400) as usize;
+        let offset = (address % 0x400) as usize;
+        let physical = match map.mirroring() {
+            Mirroring::Horizontal   => [0, 0, 1, 1][table],
+            Mirroring::Vertical     => [0, 1, 0, 1][table],
+            Mirroring::SingleLow    => 0,
+            Mirroring::SingleHigh   => 1,
+            Mirroring::FourScreen   => table,
+        };
+        physical * 0x400 + offset
+    }
+
+    //~CPU~REGISTERS~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+
+    /// Handles a CPU read of $2000-$3FFF.
+    pub fn read_register(&mut self, map: &dyn MAP, address: u16) -> u8 {
+        self.decay_latch();
+        match 0x2000 + (address % 8) {
+            0x2002 => {
+                //Only the top 3 bits are driven.
+                let val = (self.read_status() & 0xE0) | (self.io_latch & 0x1F);
+                self.drive_latch(val, 0xE0);
+                val
+            }
+            0x2004 => {
+                let mut val = self.oam[self.oam_address as usize];
+                //The unimplemented attribute bits read back as 0.
+                if self.oam_address & 3 == 2 {
+                    val &= 0xE3;
+                }
+                self.drive_latch(val, 0xFF);
+                val
+            }
+            0x2007 => self.read_data(map),
+            //Write-only registers just return the latch.
+            _      => self.io_latch,
+        }
+    }
+
+    /// Handles a CPU write to $2000-$3FFF.
+    pub fn write_register(&mut self, map: &mut dyn MAP, address: u16, val: u8) {
+        self.decay_latch();
+        self.drive_latch(val, 0xFF);
+        match 0x2000 + (address % 8) {
+            0x2000 => self.write_control(val),
+            0x2001 => self.mask = val,
+            0x2003 => self.oam_address = val,
+            0x2004 => self.write_oam_data(val),
+            0x2005 => self.write_scroll(val),
+            0x2006 => self.write_address(val),
+            0x2007 => self.write_data(map, val),
+            _      => {}
+        }
+    }
+
+    /// $2000: PPUCTRL
+    fn write_control(&mut self, val: u8) {
+        self.ctrl = val;
+        self.nmi_output = val & 0x80 != 0;
+        self.nmi_change();
+        // t: ....BA.. ........ = d: ......BA
+        self.t = (self.t & 0xF3FF) | ((val as u16 & 0x03) << 10);
+    }
+
+    /// $2002: PPUSTATUS
+    fn read_status(&mut self) -> u8 {
+        let mut result = self.io_latch & 0x1F;
+        if self.sprite_overflow { result |= 1 << 5; }
+        if self.sprite_zero_hit { result |= 1 << 6; }
+        if self.nmi_occurred    { result |= 1 << 7; }
+        self.nmi_occurred = false;
+        self.nmi_change();
+        self.w = false;
+        result
+    }
+
+    /// $2004: OAMDATA (write)
+    fn write_oam_data(&mut self, val: u8) {
+        self.oam[self.oam_address as usize] = val;
+        self.oam_address = self.oam_address.wrapping_add(1);
+    }
+
+    /// $4014: OAMDMA, one byte of the 256 byte transfer.
+    pub fn write_dma(&mut self, val: u8) {
+        self.write_oam_data(val);
+    }
+
+    /// $2005: PPUSCROLL
+    fn write_scroll(&mut self, val: u8) {
+        if !self.w {
+            // t: ........ ...HGFED = d: HGFED...
+            // x:               CBA = d: .....CBA
+            self.t = (self.t & 0xFFE0) | (val as u16 >> 3);
+            self.x = val & 0x07;
+        }
+        else {
+            // t: .CBA..HG FED..... = d: HGFEDCBA
+            self.t = (self.t & 0x8FFF) | ((val as u16 & 0x07) << 12);
+            self.t = (self.t & 0xFC1F) | ((val as u16 & 0xF8) << 2);
+        }
+        self.w = !self.w;
+    }
+
+    /// $2006: PPUADDR
+    fn write_address(&mut self, val: u8) {
+        if !self.w {
+            // t: ..FEDCBA ........ = d: ..FEDCBA
+            self.t = (self.t & 0x80FF) | ((val as u16 & 0x3F) << 8);
+        }
+        else {
+            // t: ........ HGFEDCBA = d: HGFEDCBA
+            self.t = (self.t & 0xFF00) | val as u16;
+            self.v = self.t;
+        }
+        self.w = !self.w;
+    }
+
+    /// $2007: PPUDATA (read)
+    fn read_data(&mut self, map: &dyn MAP) -> u8 {
+        let address = self.v % 0x4000;
+        let value;
+        if address < 0x3F00 {
+            value = self.buffered_data;
+            self.buffered_data = self.read(map, address);
+            self.drive_latch(value, 0xFF);
+        }
+        else {
+            //Palette reads come straight out, the buffer gets the nametable
+            // byte that the palette is covering up.
+            let mut color = self.read_palette(address);
+            if self.mask & 0x01 != 0 {
+                color &= 0x30;
+            }
+            value = (self.io_latch & 0xC0) | color;
+            self.buffered_data = self.read(map, address - 0x1000);
+            self.drive_latch(value, 0x3F);
+        }
+        self.increment_address();
+        value
+    }
+
+    /// $2007: PPUDATA (write)
+    fn write_data(&mut self, map: &mut dyn MAP, val: u8) {
+        self.write(map, self.v, val);
+        self.increment_address();
+    }
+
+    /// Drives the bits in mask of the I/O latch with val.
+    fn drive_latch(&mut self, val: u8, mask: u8) {
+        self.io_latch = (self.io_latch & !mask) | (val & mask);
+        for bit in 0..8 {
+            if mask & val & (1 << bit) != 0 {
+                self.latch_refresh[bit] = self.dots;
+            }
+        }
+    }
+
+    /// Lets latch bits that haven't been driven high in a while fall to 0.
+    fn decay_latch(&mut self) {
+        for bit in 0..8 {
+            if self.dots - self.latch_refresh[bit] > LATCH_DECAY_DOTS {
+                self.io_latch &= !(1 << bit);
+            }
+        }
+    }
+
+    fn increment_address(&mut self) {
+        let step = if self.ctrl & 0x04 == 0 { 1 } else { 32 };
+        self.v = self.v.wrapping_add(step) & 0x7FFF;
+    }
+
+    fn nmi_change(&mut self) {
+        let nmi = self.nmi_output && self.nmi_occurred;
+        if nmi && !self.nmi_previous {
+            self.nmi_pending = true;
+        }
+        self.nmi_previous = nmi;
+    }
+
+    //~SCROLLING~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+    // See nesdev "PPU scrolling" for all of the v/t bit juggling.
+
+    fn increment_x(&mut self) {
+        if self.v & 0x001F == 31 {
+            self.v &= 0xFFE0;
+            self.v ^= 0x0400;
+        }
+        else {
+            self.v += 1;
+        }
+    }
+
+    fn increment_y(&mut self) {
+        if self.v & 0x7000 != 0x7000 {
+            self.v += 0x1000;
+        }
+        else {
+            self.v &= 0x8FFF;
+            let mut y = (self.v & 0x03E0) >> 5;
+            if y == 29 {
+                y = 0;
+                self.v ^= 0x0800;
+            }
+            else if y == 31 {
+                y = 0;
+            }
+            else {
+                y += 1;
+            }
+            self.v = (self.v & 0xFC1F) | (y << 5);
+        }
+    }
+
+    fn copy_x(&mut self) {
+        // v: .....F.. ...EDCBA = t: .....F.. ...EDCBA
+        self.v = (self.v & 0xFBE0) | (self.t & 0x041F);
+    }
+
+    fn copy_y(&mut self) {
+        // v: .IHGF.ED CBA..... = t: .IHGF.ED CBA.....
+        self.v = (self.v & 0x841F) | (self.t & 0x7BE0);
+    }
+
+    //~BACKGROUND~FETCHES~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+
+    fn fetch_nametable_byte(&mut self, map: &dyn MAP) {
+        let address = 0x2000 | (self.v & 0x0FFF);
+        self.nametable_byte = self.read(map, address);
+    }
+
+    fn fetch_attribute_byte(&mut self, map: &dyn MAP) {
+        let v = self.v;
+        let address = 0x23C0 | (v & 0x0C00) | ((v >> 4) & 0x38) | ((v >> 2) & 0x07);
+        let shift = ((v >> 4) & 4) | (v & 2);
+        self.attribute_byte = ((self.read(map, address) >> shift) & 3) << 2;
+    }
+
+    fn background_tile_address(&self) -> u16 {
+        let fine_y = (self.v >> 12) & 7;
+        let table = if self.ctrl & 0x10 == 0 { 0 } else { 0x1000 };
+        table + 16 * self.nametable_byte as u16 + fine_y
+    }
+
+    fn fetch_low_tile_byte(&mut self, map: &dyn MAP) {
+        let address = self.background_tile_address();
+        self.low_tile_byte = self.read(map, address);
+    }
+
+    fn fetch_high_tile_byte(&mut self, map: &dyn MAP) {
+        let address = self.background_tile_address();
+        self.high_tile_byte = self.read(map, address + 8);
+    }
+
+    fn store_tile_data(&mut self) {
+        let mut data: u32 = 0;
+        for _ in 0..8 {
+            let a = self.attribute_byte;
+            let p1 = (self.low_tile_byte & 0x80) >> 7;
+            let p2 = (self.high_tile_byte & 0x80) >> 6;
+            self.low_tile_byte <<= 1;
+            self.high_tile_byte <<= 1;
+            data <<= 4;
+            data |= (a | p1 | p2) as u32;
+        }
+        self.tile_data |= data as u64;
+    }
+
+    fn background_pixel(&self) -> u8 {
+        if self.mask & 0x08 == 0 {
+            return 0;
+        }
+        let data = (self.tile_data >> 32) as u32 >> ((7 - self.x) * 4);
+        (data & 0x0F) as u8
+    }
+
+    //~SPRITES~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+
+    /// Returns (sprite slot, pixel) of the first opaque sprite at the dot.
+    fn sprite_pixel(&self) -> (usize, u8) {
+        if self.mask & 0x10 == 0 {
+            return (0, 0);
+        }
+        for i in 0..self.sprite_count {
+            let offset = (self.cycle as i32 - 1) - self.sprite_positions[i] as i32;
+            if !(0..=7).contains(&offset) {
+                continue;
+            }
+            let offset = 7 - offset;
+            let color = ((self.sprite_patterns[i] >> (offset * 4) as u8) & 0x0F) as u8;
+            if color.is_multiple_of(4) {
+                continue;
+            }
+            return (i, color);
+        }
+        (0, 0)
+    }
+
+    /// Fetches the 8 pixels of one sprite row as 4-bit (attribute + pattern) values.
+    fn fetch_sprite_pattern(&self, map: &dyn MAP, i: usize, row: i32) -> u32 {
+        let mut row = row;
+        let tile = self.oam[i * 4 + 1];
+        let attributes = self.oam[i * 4 + 2];
+        let address = if self.ctrl & 0x20 == 0 {
+            if attributes & 0x80 != 0 {
+                row = 7 - row;
+            }
+            let table = if self.ctrl & 0x08 == 0 { 0 } else { 0x1000 };
+            table + 16 * tile as u16 + row as u16
+        }
+        else {
+            //8x16 sprites pick their table from bit 0 of the tile number.
+            if attributes & 0x80 != 0 {
+                row = 15 - row;
+            }
+            let table = 0x1000 * (tile as u16 & 1);
+            let mut tile = tile & 0xFE;
+            if row > 7 {
+                tile += 1;
+                row -= 8;
+            }
+            table + 16 * tile as u16 + row as u16
+        };
+
+        let a = (attributes & 3) << 2;
+        let mut low = self.read(map, address);
+        let mut high = self.read(map, address + 8);
+        let mut data: u32 = 0;
+        for _ in 0..8 {
+            let (p1, p2);
+            if attributes & 0x40 != 0 {
+                p1 = low & 1;
+                p2 = (high & 1) << 1;
+                low >>= 1;
+                high >>= 1;
+            }
+            else {
+                p1 = (low & 0x80) >> 7;
+                p2 = (high & 0x80) >> 6;
+                low <<= 1;
+                high <<= 1;
+            }
+            data <<= 4;
+            data |= (a | p1 | p2) as u32;
+        }
+        data
+    }
+
+    fn evaluate_sprites(&mut self, map: &dyn MAP) {
+        let h = if self.ctrl & 0x20 == 0 { 8 } else { 16 };
+        let mut count = 0;
+        for i in 0..64 {
+            let y = self.oam[i * 4];
+            let a = self.oam[i * 4 + 2];
+            let x = self.oam[i * 4 + 3];
+            let row = self.scanline as i32 - y as i32;
+            if !(0..h).contains(&row) {
+                continue;
+            }
+            if count < 8 {
+                self.sprite_patterns[count] = self.fetch_sprite_pattern(map, i, row);
+                self.sprite_positions[count] = x;
+                self.sprite_priorities[count] = (a >> 5) & 1;
+                self.sprite_indexes[count] = i as u8;
+            }
+            count += 1;
+        }
+        if count > 8 {
+            count = 8;
+            self.sprite_overflow = true;
+        }
+        self.sprite_count = count;
+    }
+
+    //~OUTPUT~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+
+    fn render_pixel(&mut self) {
+        let x = self.cycle as usize - 1;
+        let y = self.scanline as usize;
+        let mut background = self.background_pixel();
+        let (i, mut sprite) = self.sprite_pixel();
+        //Left 8 pixel clipping.
+        if x < 8 && self.mask & 0x02 == 0 { background = 0; }
+        if x < 8 && self.mask & 0x04 == 0 { sprite = 0; }
+
+        let b = !background.is_multiple_of(4);
+        let s = !sprite.is_multiple_of(4);
+        let color = match (b, s) {
+            (false, false) => 0,
+            (false, true)  => sprite | 0x10,
+            (true, false)  => background,
+            (true, true)   => {
+                if self.sprite_indexes[i] == 0 && x < 255 {
+                    self.sprite_zero_hit = true;
+                }
+                if self.sprite_priorities[i] == 0 { sprite | 0x10 } else { background }
+            }
+        };
+
+        let mut pixel = self.read_palette(color as u16) as u16 & 0x3F;
+        if self.mask & 0x01 != 0 {
+            pixel &= 0x30; //Grayscale
+        }
+        pixel |= ((self.mask as u16) >> 5) << 6; //Emphasis bits.
+        self.back[y * NES_WIDTH + x] = pixel;
+    }
+
+    fn set_vertical_blank(&mut self) {
+        std::mem::swap(&mut self.front, &mut self.back);
+        self.nmi_occurred = true;
+        self.nmi_change();
+    }
+
+    fn clear_vertical_blank(&mut self) {
+        self.nmi_occurred = false;
+        self.nmi_change();
+    }
+
+    /// True if either background or sprite rendering is enabled.
+    pub fn rendering_enabled(&self) -> bool {
+        self.mask & 0x18 != 0
+    }
+
+    /// Advances the cycle/scanline/frame counters.
+    fn tick(&mut self) {
+        self.dots += 1;
+
+        //Odd frames skip the last dot of the pre-render line when rendering.
+        if self.odd_frame && self.rendering_enabled() && self.scanline == 261 && self.cycle == 339 {
+            self.cycle = 0;
+            self.scanline = 0;
+            self.next_frame();
+            return;
+        }
+
+        self.cycle += 1;
+        if self.cycle > 340 {
+            self.cycle = 0;
+            self.scanline += 1;
+            if self.scanline > 261 {
+                self.scanline = 0;
+                self.next_frame();
+            }
+        }
+    }
+
+    fn next_frame(&mut self) {
+        self.frame += 1;
+        self.odd_frame = !self.odd_frame;
+    }
+
+    /// Runs a single PPU dot.
+    pub fn step(&mut self, map: &dyn MAP) {
+        self.tick();
+
+        let rendering = self.rendering_enabled();
+        let pre_line = self.scanline == 261;
+        let visible_line = self.scanline < 240;
+        let render_line = pre_line || visible_line;
+        let prefetch_cycle = self.cycle >= 321 && self.cycle <= 336;
+        let visible_cycle = self.cycle >= 1 && self.cycle <= 256;
+        let fetch_cycle = prefetch_cycle || visible_cycle;
+
+        if rendering {
+            if visible_line && visible_cycle {
+                self.render_pixel();
+            }
+            if render_line && fetch_cycle {
+                self.tile_data <<= 4;
+                match self.cycle % 8 {
+                    1 => self.fetch_nametable_byte(map),
+                    3 => self.fetch_attribute_byte(map),
+                    5 => self.fetch_low_tile_byte(map),
+                    7 => self.fetch_high_tile_byte(map),
+                    0 => self.store_tile_data(),
+                    _ => {}
+                }
+            }
+            if pre_line && self.cycle >= 280 && self.cycle <= 304 {
+                self.copy_y();
+            }
+            if render_line {
+                if fetch_cycle && self.cycle.is_multiple_of(8) {
+                    self.increment_x();
+                }
+                if self.cycle == 256 {
+                    self.increment_y();
+                }
+                if self.cycle == 257 {
+                    self.copy_x();
+                }
+            }
+            if self.cycle == 257 {
+                if visible_line {
+                    self.evaluate_sprites(map);
+                }
+                else {
+                    self.sprite_count = 0;
+                }
+            }
+        }
+
+        if self.scanline == 241 && self.cycle == 1 {
+            self.set_vertical_blank();
+        }
+        if pre_line && self.cycle == 1 {
+            self.clear_vertical_blank();
+            self.sprite_zero_hit = false;
+            self.sprite_overflow = false;
+        }
+    }
+}
+
+/// Roughly 600ms of PPU dots (5.37 MHz), how long an undriven latch bit lasts.
+const LATCH_DECAY_DOTS: u64 = 3_221_591;
+
+/// Maps a $3F00-$3FFF address onto the 32 bytes of palette RAM.
+/// $3F10/$3F14/$3F18/$3F1C are mirrors of $3F00/$3F04/$3F08/$3F0C.
+fn palette_index(address: u16) -> usize {
+    let index = (address % 32) as usize;
+    if index >= 16 && index.is_multiple_of(4) { index - 16 } else { index }
+}
+
+impl Default for PPU {
+    fn default() -> PPU { PPU::new() }
+}
+
+#[cfg(test)]
+#[path = "./ppu_test.rs"]
+pub mod ppu_test;
@@ -0,0 +1,162 @@
+/* PPU debug viewers.
+ * Renders what the PPU currently sees (pattern tables, nametables, OAM and
+ *  palette RAM) into Images, so they can be written to disk from the CLI.
+ * Nothing in here changes PPU state, so it's safe to call mid-frame.
+ * Colors come from whichever 64 color palette the frame is rendered with
+ *  (NES::palette()), so Vs. System and PlayChoice games look right.
+ */
+
+use crate::core::image::Image;
+use crate::core::mapper::MAP;
+use crate::core::ppu::PPU;
+
+/// Color of the scroll rectangle drawn over the nametable view.
+const SCROLL_RECT_COLOR: u32 = 0xFF00FF;
+
+/// Looks up the RGB color of a palette RAM entry.
+fn palette_rgb(ppu: &PPU, colors: &[u32; 64], entry: u16) -> u32 {
+    colors[(ppu.read_palette(entry) & 0x3F) as usize]
+}
+
+/// An 8x8 tile to draw: its pattern address, which 4 color palette to use
+///  (0-3 background, 4-7 sprite), and flipping.
+struct Tile {
+    address:    u16,
+    palette:    u16,
+    flip_h:     bool,
+    flip_v:     bool,
+}
+
+impl Tile {
+    fn plain(address: u16, palette: u16) -> Tile {
+        Tile { address, palette, flip_h: false, flip_v: false }
+    }
+}
+
+/// Draws one tile with its top left corner at (x, y).
+fn draw_tile(img: &mut Image, ppu: &PPU, colors: &[u32; 64], map: &dyn MAP, tile: Tile,
+             x: usize, y: usize) {
+    for row in 0..8u16 {
+        let src_row = if tile.flip_v { 7 - row } else { row };
+        let low = ppu.read(map, tile.address + src_row);
+        let high = ppu.read(map, tile.address + src_row + 8);
+        for col in 0..8u16 {
+            let bit = if tile.flip_h { col } else { 7 - col };
+            let value = ((low >> bit) & 1) as u16 | ((((high >> bit) & 1) as u16) << 1);
+            //Pixel value 0 is always the backdrop color.
+            let entry = if value == 0 { 0 } else { tile.palette * 4 + value };
+            img.set(x + col as usize, y + row as usize, palette_rgb(ppu, colors, entry));
+        }
+    }
+}
+
+/// One pattern table (0 = $0000, 1 = $1000) as a 128x128 image, drawn with
+///  palette 0-7 (0-3 background, 4-7 sprites).
+pub fn pattern_table_image(ppu: &PPU, colors: &[u32; 64], map: &dyn MAP, table: u16,
+                           palette: u16) -> Image {
+    let mut img = Image::new(128, 128);
+    let base = (table & 1) * 0x1000;
+    for tile in 0..256u16 {
+        let x = (tile % 16) as usize * 8;
+        let y = (tile / 16) as usize * 8;
+        draw_tile(&mut img, ppu, colors, map, Tile::plain(base + tile * 16, palette & 7), x, y);
+    }
+    img
+}
+
+/// The current scroll position, in the 512x480 space of all four
+///  nametables. Taken from t, which is what the next frame starts at.
+pub fn scroll_position(ppu: &PPU) -> (usize, usize) {
+    let t = ppu.t as usize;
+    let x = ((t & 0x1F) << 3) | ppu.x as usize;
+    let y = (((t >> 5) & 0x1F) << 3) | ((t >> 12) & 7);
+    (x + ((t >> 10) & 1) * 256, y + ((t >> 11) & 1) * 240)
+}
+
+/// All four nametables as a 512x480 image, laid out as $2000 $2400 / $2800
+///  $2C00 (so mirroring shows up as duplicated screens), with the visible
+///  256x240 scroll rectangle outlined.
+pub fn nametable_image(ppu: &PPU, colors: &[u32; 64], map: &dyn MAP) -> Image {
+    let mut img = Image::new(512, 480);
+    let pattern = if ppu.ctrl & 0x10 == 0 { 0 } else { 0x1000 };
+
+    for table in 0..4u16 {
+        let base = 0x2000 + table * 0x400;
+        let ox = (table % 2) as usize * 256;
+        let oy = (table / 2) as usize * 240;
+        for tile in 0..960u16 {
+            let (col, row) = (tile % 32, tile / 32);
+            let index = ppu.read(map, base + tile) as u16;
+            let attribute = ppu.read(map, base + 0x3C0 + (row / 4) * 8 + col / 4);
+            let shift = ((row & 2) << 1) | (col & 2);
+            let palette = ((attribute >> shift) & 3) as u16;
+            draw_tile(&mut img, ppu, colors, map, Tile::plain(pattern + index * 16, palette),
+                      ox + col as usize * 8, oy + row as usize * 8);
+        }
+    }
+
+    //Scroll rectangle, wrapping around the edges like the PPU does.
+    let (sx, sy) = scroll_position(ppu);
+    for i in 0..256 {
+        img.set((sx + i) % 512, sy % 480, SCROLL_RECT_COLOR);
+        img.set((sx + i) % 512, (sy + 239) % 480, SCROLL_RECT_COLOR);
+    }
+    for i in 0..240 {
+        img.set(sx % 512, (sy + i) % 480, SCROLL_RECT_COLOR);
+        img.set((sx + 255) % 512, (sy + i) % 480, SCROLL_RECT_COLOR);
+    }
+    img
+}
+
+/// All 64 OAM sprites, 8 per row, each in an 8x16 cell (8x8 sprites leave
+///  the bottom half as backdrop). Flipping and palettes are applied.
+pub fn oam_image(ppu: &PPU, colors: &[u32; 64], map: &dyn MAP) -> Image {
+    let mut img = Image::new(64, 128);
+    let tall = ppu.ctrl & 0x20 != 0;
+
+    for i in 0..64usize {
+        let tile = ppu.oam[i * 4 + 1] as u16;
+        let attributes = ppu.oam[i * 4 + 2];
+        let palette = 4 + (attributes & 3) as u16;
+        let flip_h = attributes & 0x40 != 0;
+        let flip_v = attributes & 0x80 != 0;
+        let x = (i % 8) * 8;
+        let y = (i / 8) * 16;
+
+        if tall {
+            //8x16: the table comes from bit 0, and vertical flip swaps halves.
+            let base = (tile & 1) * 0x1000 + (tile & 0xFE) * 16;
+            let (top, bottom) = if flip_v { (base + 16, base) } else { (base, base + 16) };
+            draw_tile(&mut img, ppu, colors, map, Tile { address: top, palette, flip_h, flip_v }, x, y);
+            draw_tile(&mut img, ppu, colors, map, Tile { address: bottom, palette, flip_h, flip_v }, x, y + 8);
+        }
+        else {
+            let table = if ppu.ctrl & 0x08 == 0 { 0 } else { 0x1000 };
+            let address = table + tile * 16;
+            draw_tile(&mut img, ppu, colors, map, Tile { address, palette, flip_h, flip_v }, x, y);
+            for row in 8..16 {
+                for col in 0..8 {
+                    img.set(x + col, y + row, palette_rgb(ppu, colors, 0));
+                }
+            }
+        }
+    }
+    img
+}
+
+/// The 32 palette RAM entries as 16x16 swatches: background palettes on the
+///  top row, sprite palettes on the bottom row.
+pub fn palette_image(ppu: &PPU, colors: &[u32; 64]) -> Image {
+    let mut img = Image::new(256, 32);
+    for entry in 0..32u16 {
+        let rgb = palette_rgb(ppu, colors, entry);
+        let x = (entry % 16) as usize * 16;
+        let y = (entry / 16) as usize * 16;
+        for dy in 0..16 {
+            for dx in 0..16 {
+                img.set(x + dx, y + dy, rgb);
+            }
+        }
+    }
+    img
+}
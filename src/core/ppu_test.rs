@@ -0,0 +1,407 @@
+/*  Unit test module of the PPU (ppu.rs).
+ */
+use crate::core::ppu::*;
+
+#[cfg(test)]
+pub mod ppu_test {
+    use super::*;
+    use crate::core::*;
+    use std::fs::File;
+    use std::io::Write;
+
+    /// Writes an NROM image with the given code at $8000 (reset vector)
+    ///  to a temp file, and returns the path for NES::new.
+    pub fn test_rom(name: &str, code: &[u8]) -> std::path::PathBuf {
+        let mut prg = vec![0xEA; 16384];
+        prg[..code.len()].copy_from_slice(code);
+        prg[0x3FFA..].copy_from_slice(&[0x00, 0x80, 0x00, 0x80, 0x00, 0x80]);
+
+        let mut rom = vec![0x4E, 0x45, 0x53, 0x1A, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        rom.extend_from_slice(&prg);
+        rom.extend_from_slice(&[0; 8192]);
+
+        let path = std::env::temp_dir().join(format!("soliloquy_{}_{}.nes", name, std::process::id()));
+        File::create(&path).unwrap().write_all(&rom).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_vram_access(){
+        let mut mem = MEM::new_empty();
+
+        //$2006 twice sets the address, then $2007 writes.
+        mem.set(0x2006, 0x21);
+        mem.set(0x2006, 0x08);
+        mem.set(0x2007, 0xAB);
+        mem.set(0x2007, 0xCD);
+        assert_eq!(mem.PPU.v, 0x210A);
+
+        //Reads are delayed by the read buffer.
+        mem.set(0x2006, 0x21);
+        mem.set(0x2006, 0x08);
+        mem.get(0x2007);
+        assert_eq!(mem.get(0x2007), 0xAB);
+        assert_eq!(mem.get(0x2007), 0xCD);
+
+        //Increment by 32 mode, through a register mirror.
+        mem.set(0x3FF8, 0x04);
+        mem.set(0x2006, 0x20);
+        mem.set(0x2006, 0x00);
+        mem.set(0x2007, 1);
+        assert_eq!(mem.PPU.v, 0x2020);
+    }
+
+    #[test]
+    fn test_vblank(){
+        let mut mem = MEM::new_empty();
+        mem.set(0x2000, 0x80);
+
+        //Power on is at the end of scanline 240, vblank starts at 241.
+        for _ in 0..2 {
+            mem.step_ppu();
+        }
+        assert!(mem.PPU.nmi_pending);
+        assert_eq!(mem.get(0x2002) & 0x80, 0x80);
+        //Reading status clears the flag.
+        assert_eq!(mem.get(0x2002) & 0x80, 0);
+    }
+
+    #[test]
+    fn test_oam_dma(){
+        let mut mem = MEM::new_empty();
+        for i in 0..256 {
+            mem.set(0x200 + i, i as u8);
+        }
+        mem.set(0x4014, 0x02);
+        assert_eq!(mem.PPU.oam[0], 0);
+        assert_eq!(mem.PPU.oam[255], 255);
+        assert_eq!(mem.stall, 513);
+    }
+
+    #[test]
+    fn test_screenshot_backdrop(){
+        let rom = test_rom("backdrop", &[
+            0x78,                   //SEI
+            0xA9, 0x3F,             //LDA #$3F
+            0x8D, 0x06, 0x20,       //STA $2006
+            0xA9, 0x00,             //LDA #$00
+            0x8D, 0x06, 0x20,       //STA $2006
+            0xA9, 0x16,             //LDA #$16
+            0x8D, 0x07, 0x20,       //STA $2007
+            0xA9, 0x0A,             //LDA #$0A
+            0x8D, 0x01, 0x20,       //STA $2001
+            0x4C, 0x15, 0x80,       //JMP $8015
+        ]);
+        let mut nes = NES::new(&rom).unwrap();
+        for _ in 0..3 {
+            nes.step_frame();
+        }
+
+        let shot = nes.screenshot();
+        assert_eq!((shot.width, shot.height), (256, 240));
+        assert_eq!(shot.get(128, 120), NES_PALETTE[0x16]);
+        assert!(shot.pixels.iter().all(|&p| p == NES_PALETTE[0x16]));
+    }
+
+    #[test]
+    fn test_debug_views(){
+        use crate::core::ppu_debug::*;
+        let mut mem = MEM::new_empty();
+
+        //Backdrop and the first sprite palette color.
+        mem.set(0x2006, 0x3F);
+        mem.set(0x2006, 0x00);
+        mem.set(0x2007, 0x16);
+        mem.set(0x2006, 0x3F);
+        mem.set(0x2006, 0x11);
+        mem.set(0x2007, 0x2A);
+
+        let pal = palette_image(&mem.PPU, &NES_PALETTE);
+        assert_eq!((pal.width, pal.height), (256, 32));
+        assert_eq!(pal.get(0, 0), NES_PALETTE[0x16]);
+        assert_eq!(pal.get(16, 16), NES_PALETTE[0x2A]);
+
+        //Colors come from the palette passed in, e.g. an arcade PPU's.
+        let pal = palette_image(&mem.PPU, &RP2C04_PALETTES[0]);
+        assert_eq!(pal.get(0, 0), RP2C04_PALETTES[0][0x16]);
+        assert_ne!(pal.get(0, 0), NES_PALETTE[0x16]);
+
+        //Empty CHR is all backdrop.
+        let pattern = pattern_table_image(&mem.PPU, &NES_PALETTE, &*mem.CART, 0, 0);
+        assert!(pattern.pixels.iter().all(|&p| p == NES_PALETTE[0x16]));
+        let pattern = pattern_table_image(&mem.PPU, &RGB_PPU_PALETTE, &*mem.CART, 0, 0);
+        assert!(pattern.pixels.iter().all(|&p| p == RGB_PPU_PALETTE[0x16]));
+        assert_eq!(oam_image(&mem.PPU, &NES_PALETTE, &*mem.CART).width, 64);
+
+        //Scroll to (12, 34) in the second nametable.
+        mem.set(0x2000, 0x01);
+        mem.set(0x2005, 12);
+        mem.set(0x2005, 34);
+        assert_eq!(scroll_position(&mem.PPU), (256 + 12, 34));
+        let nt = nametable_image(&mem.PPU, &NES_PALETTE, &*mem.CART);
+        assert_eq!(nt.get(256 + 12, 34), 0xFF00FF);
+        assert_eq!(nt.get(256 + 13, 35), NES_PALETTE[0x16]);
+    }
+
+    #[test]
+    fn test_palette_mirroring(){
+        let mut mem = MEM::new_empty();
+        mem.set(0x2006, 0x3F);
+        mem.set(0x2006, 0x10);
+        mem.set(0x2007, 0x21);
+        assert_eq!(mem.PPU.palette[0], 0x21);
+
+        //$3F14 and $3F04 are the same byte, $3F11 is not mirrored.
+        mem.set(0x2006, 0x3F);
+        mem.set(0x2006, 0x04);
+        mem.set(0x2007, 0x05);
+        mem.set(0x2007, 0x06);
+        assert_eq!(mem.PPU.read_palette(0x3F14), 0x05);
+        assert_eq!(mem.PPU.read_palette(0x3F15), 0);
+    }
+
+    #[test]
+    fn test_palette_read_bypasses_buffer(){
+        let mut mem = MEM::new_empty();
+        //Nametable byte under $3F00 is $2F00.
+        mem.set(0x2006, 0x2F);
+        mem.set(0x2006, 0x00);
+        mem.set(0x2007, 0x55);
+        mem.set(0x2006, 0x3F);
+        mem.set(0x2006, 0x00);
+        mem.set(0x2007, 0x2A);
+
+        mem.set(0x2006, 0x3F);
+        mem.set(0x2006, 0x00);
+        assert_eq!(mem.get(0x2007) & 0x3F, 0x2A);
+        //The buffer picked up the nametable byte.
+        mem.set(0x2006, 0x20);
+        mem.set(0x2006, 0x00);
+        assert_eq!(mem.get(0x2007), 0x55);
+    }
+
+    #[test]
+    fn test_open_bus(){
+        let mut mem = MEM::new_empty();
+        mem.set(0x2003, 0xA5);
+        //Write-only registers return the latch.
+        assert_eq!(mem.get(0x2000), 0xA5);
+        assert_eq!(mem.get(0x2005), 0xA5);
+        //Status only drives the top 3 bits.
+        assert_eq!(mem.get(0x2002) & 0x1F, 0x05);
+
+        //Bits fall off after ~600ms without being refreshed.
+        mem.set(0x2003, 0xFF);
+        for _ in 0..(341 * 262 * 40) {
+            mem.step_ppu();
+        }
+        assert_eq!(mem.get(0x2001), 0);
+    }
+
+    #[test]
+    fn test_odd_frame_skip(){
+        let mut mem = MEM::new_empty();
+        let frame_dots = |mem: &mut MEM| {
+            let (start, frame) = (mem.PPU.dots, mem.PPU.frame);
+            while mem.PPU.frame == frame {
+                mem.step_ppu();
+            }
+            mem.PPU.dots - start
+        };
+        frame_dots(&mut mem);
+
+        //Rendering off, every frame is full length.
+        assert_eq!(frame_dots(&mut mem), 341 * 262);
+        assert_eq!(frame_dots(&mut mem), 341 * 262);
+
+        //Rendering on, odd frames are a dot short.
+        mem.set(0x2001, 0x08);
+        let a = frame_dots(&mut mem);
+        let b = frame_dots(&mut mem);
+        assert_eq!(a + b, 2 * 341 * 262 - 1);
+    }
+
+    /// Holds the IRQ line low whenever `line` is set, and is an NROM
+    ///  otherwise.
+    struct IrqBoard {
+        nrom: Box<dyn MAP>,
+        line: std::rc::Rc<std::cell::Cell<bool>>,
+    }
+
+    impl MAP for IrqBoard {
+        fn get(&self, address: u16) -> u8 { self.nrom.get(address) }
+        fn set(&mut self, address: u16, val: u8) { self.nrom.set(address, val) }
+        fn get_chr(&self, address: u16) -> u8 { self.nrom.get_chr(address) }
+        fn set_chr(&mut self, address: u16, val: u8) { self.nrom.set_chr(address, val) }
+        fn irq(&self) -> bool { self.line.get() }
+    }
+
+    #[test]
+    fn test_mapper_irq(){
+        let rom = test_rom("mapper_irq", &[
+            0x58,                   //CLI
+            0x4C, 0x01, 0x80,       //JMP $8001
+        ]);
+        let mut nes = NES::new(&rom).unwrap();
+        let line = std::rc::Rc::new(std::cell::Cell::new(false));
+        let nrom = std::mem::replace(&mut nes.cpu.memory.CART, Box::new(EMPTY_MAP));
+        nes.cpu.memory.CART = Box::new(IrqBoard { nrom, line: line.clone() });
+
+        for _ in 0..10 { nes.step(); }
+        assert_eq!(nes.cpu.sp, 0xFD);
+
+        line.set(true);
+        nes.step();
+        nes.step();
+        assert_eq!(nes.cpu.sp, 0xFA);
+    }
+
+    #[test]
+    fn test_reset_resets_ppu_and_apu(){
+        let rom = test_rom("reset", &[
+            0xA9, 0x42,             //LDA #$42
+            0x85, 0x10,             //STA $10
+            0xA9, 0x04,             //LDA #$04
+            0x8D, 0x00, 0x20,       //STA $2000
+            0xA9, 0x1E,             //LDA #$1E
+            0x8D, 0x01, 0x20,       //STA $2001
+            0xA9, 0x01,             //LDA #$01
+            0x8D, 0x15, 0x40,       //STA $4015
+            0xA9, 0x08,             //LDA #$08
+            0x8D, 0x03, 0x40,       //STA $4003
+            0x4C, 0x1A, 0x80,       //JMP $801A
+        ]);
+        let mut nes = NES::new(&rom).unwrap();
+        for _ in 0..12 { nes.step(); }
+        assert_eq!(nes.cpu.memory.PPU.ctrl, 0x04);
+        assert_eq!(nes.cpu.memory.PPU.mask, 0x1E);
+        assert!(nes.cpu.memory.APU.pulse1.length_value > 0);
+
+        nes.reset();
+        assert_eq!(nes.cpu.memory.PPU.ctrl, 0);
+        assert_eq!(nes.cpu.memory.PPU.mask, 0);
+        assert_eq!(nes.cpu.memory.PPU.frame, 0);
+        assert_eq!(nes.cpu.memory.APU.pulse1.length_value, 0);
+        assert_eq!(nes.cpu.memory.APU.frame_cycle, 0);
+        //RAM survives a reset.
+        assert_eq!(nes.cpu.memory.get(0x10), 0x42);
+        assert_eq!(nes.cpu.pc, 0x8000);
+    }
+
+    //~BLARGG~TEST~ROMS~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+    // The test ROMs aren't redistributed with the source, so these are
+    //  ignored by default. Put them in test/roms/ (or point
+    //  SOLILOQUY_TEST_ROMS somewhere else) and run `cargo test -- --ignored`.
+    // They report through $6000: $80 while running, then a result code
+    //  (0 = pass), with DE B0 61 at $6001 and a message from $6004.
+
+    /// ppu_vbl_nmi singles that can't pass yet: the CPU takes its cycle
+    ///  counts from OP_SPEEDS alone (no page crossing penalties), and the
+    ///  PPU only catches up after each instruction, so NMI and frame timing
+    ///  is off by a few dots. They're run, and have to keep failing until
+    ///  they're moved out of here.
+    const VBL_NMI_KNOWN_FAILING: [&str; 6] = [
+        "ppu_vbl_nmi/rom_singles/05-nmi_timing.nes",
+        "ppu_vbl_nmi/rom_singles/06-suppression.nes",
+        "ppu_vbl_nmi/rom_singles/07-nmi_on_timing.nes",
+        "ppu_vbl_nmi/rom_singles/08-nmi_off_timing.nes",
+        "ppu_vbl_nmi/rom_singles/09-even_odd_frames.nes",
+        "ppu_vbl_nmi/rom_singles/10-even_odd_timing.nes",
+    ];
+
+    fn rom_dir() -> std::path::PathBuf {
+        std::env::var("SOLILOQUY_TEST_ROMS").map(Into::into)
+            .unwrap_or_else(|_| std::path::PathBuf::from("test/roms"))
+    }
+
+    /// Runs a ROM that reports blargg's way, returns its result code and
+    ///  message.
+    fn run_blargg(path: &std::path::Path) -> (u8, String) {
+        let mut nes = NES::new(path).unwrap();
+
+        let mut started = false;
+        for _ in 0..60 * 60 {
+            nes.step_frame();
+            let mem = &mut nes.cpu.memory;
+            let signature = [mem.get(0x6001), mem.get(0x6002), mem.get(0x6003)];
+            if signature != [0xDE, 0xB0, 0x61] {
+                continue;
+            }
+            let status = mem.get(0x6000);
+            if status == 0x80 {
+                started = true;
+            }
+            else if started && status < 0x80 {
+                let mut text = String::new();
+                let mut a = 0x6004;
+                while a < 0x7000 && mem.get(a) != 0 {
+                    text.push(mem.get(a) as char);
+                    a += 1;
+                }
+                return (status, text);
+            }
+        }
+        (0xFF, "Timed out.".to_string())
+    }
+
+    /// Runs blargg ROMs from rom_dir(): the ones given have to pass, the
+    ///  known failing ones have to fail. Panics if a ROM isn't there,
+    ///  rather than passing without running it.
+    fn check_blargg(roms: &[&str], known_failing: &[&str]) {
+        for &rom in roms.iter().chain(known_failing) {
+            let path = rom_dir().join(rom);
+            if !path.exists() {
+                panic!("{} not found. Put blargg's test ROMs in {} (or set \
+                        SOLILOQUY_TEST_ROMS to where they are).",
+                       path.display(), rom_dir().display());
+            }
+            let (code, text) = run_blargg(&path);
+            if known_failing.contains(&rom) {
+                assert_ne!(code, 0, "{} passes now, take it off the known failing list.", rom);
+            }
+            else {
+                assert_eq!(code, 0, "{} failed:\n{}", rom, text);
+            }
+        }
+    }
+
+    #[test]
+    fn test_blargg_harness(){
+        //Reports like a blargg ROM: starts, waits two vblanks, then passes.
+        let rom = test_rom("blargg", &[
+            0xA9, 0xDE, 0x8D, 0x01, 0x60,   //LDA #$DE, STA $6001
+            0xA9, 0xB0, 0x8D, 0x02, 0x60,   //LDA #$B0, STA $6002
+            0xA9, 0x61, 0x8D, 0x03, 0x60,   //LDA #$61, STA $6003
+            0xA9, 0x80, 0x8D, 0x00, 0x60,   //LDA #$80, STA $6000
+            0xAD, 0x02, 0x20,               //$8014: LDA $2002
+            0x29, 0x80, 0xD0, 0x03,         //AND #$80, BNE $801E
+            0x4C, 0x14, 0x80,               //JMP $8014
+            0xAD, 0x02, 0x20,               //$801E: LDA $2002
+            0x29, 0x80, 0xD0, 0x03,         //AND #$80, BNE $8028
+            0x4C, 0x1E, 0x80,               //JMP $801E
+            0xA9, 0x4F, 0x8D, 0x04, 0x60,   //$8028: LDA #'O', STA $6004
+            0xA9, 0x4B, 0x8D, 0x05, 0x60,   //LDA #'K', STA $6005
+            0xA9, 0x00, 0x8D, 0x06, 0x60,   //LDA #0, STA $6006
+            0x8D, 0x00, 0x60,               //STA $6000
+            0x4C, 0x3A, 0x80,               //$803A: JMP $803A
+        ]);
+        assert_eq!(run_blargg(&rom), (0, "OK".to_string()));
+    }
+
+    #[test]
+    #[ignore = "needs blargg's ppu_vbl_nmi ROMs in test/roms/"]
+    fn test_rom_ppu_vbl_nmi(){
+        check_blargg(&[
+            "ppu_vbl_nmi/rom_singles/01-vbl_basics.nes",
+            "ppu_vbl_nmi/rom_singles/02-vbl_set_time.nes",
+            "ppu_vbl_nmi/rom_singles/03-vbl_clear_time.nes",
+            "ppu_vbl_nmi/rom_singles/04-nmi_control.nes",
+        ], &VBL_NMI_KNOWN_FAILING);
+    }
+
+    #[test]
+    #[ignore = "needs blargg's ppu_open_bus ROM in test/roms/"]
+    fn test_rom_ppu_open_bus(){
+        check_blargg(&["ppu_open_bus/ppu_open_bus.nes"], &[]);
+    }
+}
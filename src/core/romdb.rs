@@ -0,0 +1,274 @@
+/* ROM database, for dumps whose iNES header is wrong.
+ * Plenty of old dumps have the wrong mapper, mirroring or RAM size in their
+ *  header, or leave out what iNES can't say at all (submappers, region).
+ *  The ROM data itself is fine, so games are looked up by the CRC-32 of
+ *  their PRG and CHR (confirmed by SHA-1 when the entry has one), and the
+ *  header is corrected from what's known about the real board.
+ *
+ * Entries use the format of the NES 2.0 header database (nes20db.xml):
+ *
+ *   <game>
+ *     <rom size="40960" crc32="3337EC46" sha1="..."/>
+ *     <prgrom size="32768"/>
+ *     <chrrom size="8192"/>
+ *     <prgram size="8192"/>           (also prgnvram, chrram, chrnvram)
+ *     <pcb mapper="0" submapper="0" mirroring="V" battery="0"/>
+ *     <console type="0" region="0"/>
+ *     <vs hardware="0" ppu="0"/>
+ *     <expansion type="1"/>
+ *   </game>
+ *
+ * The built in entries are in romdb.xml, so they can be copied straight out
+ *  of nes20db.xml, and a whole nes20db.xml can be loaded at runtime instead.
+ */
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+
+use crate::core::checksum::{crc32, sha1};
+use crate::core::header::{ConsoleType, Header, Timing};
+use crate::core::mapper::Mirroring;
+
+/// What the database knows about one ROM. Sizes are in bytes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DbEntry {
+    /// CRC-32 and SHA-1 of PRG then CHR.
+    pub crc32:          u32,
+    pub sha1:           Option<[u8; 20]>,
+    pub prg_rom_size:   usize,
+    pub chr_rom_size:   usize,
+    pub prg_ram_size:   usize,
+    pub prg_nvram_size: usize,
+    pub chr_ram_size:   usize,
+    pub chr_nvram_size: usize,
+    pub mapper:         u16,
+    pub submapper:      u8,
+    /// None when the mapper controls it.
+    pub mirroring:      Option<Mirroring>,
+    pub battery:        bool,
+    pub console:        ConsoleType,
+    pub timing:         Timing,
+    pub vs_ppu:         u8,
+    pub vs_hardware:    u8,
+    pub expansion:      u8,
+}
+
+impl DbEntry {
+    /// Overwrites everything in the header the database knows better.
+    pub fn apply(&self, header: &mut Header) {
+        header.prg_rom_size = self.prg_rom_size;
+        header.chr_rom_size = self.chr_rom_size;
+        header.prg_ram_size = self.prg_ram_size;
+        header.prg_nvram_size = self.prg_nvram_size;
+        header.chr_ram_size = self.chr_ram_size;
+        header.chr_nvram_size = self.chr_nvram_size;
+        header.mapper = self.mapper;
+        header.submapper = self.submapper;
+        if let Some(mirroring) = self.mirroring {
+            header.mirroring = mirroring;
+        }
+        header.battery = self.battery;
+        header.console = self.console;
+        header.timing = self.timing;
+        header.vs_ppu = self.vs_ppu;
+        header.vs_hardware = self.vs_hardware;
+        header.expansion = self.expansion;
+    }
+
+    /// Fills in what one element of a <game> says.
+    fn set(&mut self, tag: &str, attributes: &[(&str, &str)]) -> Result<(), String> {
+        let get = |name: &str| attributes.iter().find(|(k, _)| *k == name).map(|&(_, v)| v);
+        let number = |name: &str| -> Result<usize, String> {
+            get(name).map_or(Ok(0), |v| v.parse().map_err(|_| format!("Bad {} in <{}>: {}", name, tag, v)))
+        };
+        match tag {
+            "rom" => {
+                let crc = get("crc32").ok_or("A <rom> without a crc32.")?;
+                self.crc32 = u32::from_str_radix(crc, 16).map_err(|_| format!("Bad crc32: {}", crc))?;
+                self.sha1 = match get("sha1") {
+                    Some(hex) => Some(parse_sha1(hex).ok_or_else(|| format!("Bad sha1: {}", hex))?),
+                    None => None,
+                };
+            }
+            "prgrom"    => self.prg_rom_size = number("size")?,
+            "chrrom"    => self.chr_rom_size = number("size")?,
+            "prgram"    => self.prg_ram_size = number("size")?,
+            "prgnvram"  => self.prg_nvram_size = number("size")?,
+            "chrram"    => self.chr_ram_size = number("size")?,
+            "chrnvram"  => self.chr_nvram_size = number("size")?,
+            "pcb" => {
+                self.mapper = number("mapper")? as u16;
+                self.submapper = number("submapper")? as u8;
+                self.battery = number("battery")? != 0;
+                self.mirroring = match get("mirroring") {
+                    Some("H") => Some(Mirroring::Horizontal),
+                    Some("V") => Some(Mirroring::Vertical),
+                    Some("4") => Some(Mirroring::FourScreen),
+                    _ => None,
+                };
+            }
+            "console" => {
+                self.console = match number("type")? {
+                    0 => ConsoleType::Nes,
+                    1 => ConsoleType::VsSystem,
+                    2 => ConsoleType::Playchoice10,
+                    n => ConsoleType::Extended(n as u8),
+                };
+                self.timing = match number("region")? {
+                    0 => Timing::Ntsc,
+                    1 => Timing::Pal,
+                    2 => Timing::MultiRegion,
+                    _ => Timing::Dendy,
+                };
+            }
+            "vs" => {
+                self.vs_hardware = number("hardware")? as u8;
+                self.vs_ppu = number("ppu")? as u8;
+            }
+            "expansion" => self.expansion = number("type")? as u8,
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RomDb {
+    pub entries: Vec<DbEntry>,
+}
+
+impl RomDb {
+    /// The entries in romdb.xml, parsed once and shared.
+    pub fn builtin() -> Arc<RomDb> {
+        static BUILTIN: OnceLock<Arc<RomDb>> = OnceLock::new();
+        BUILTIN.get_or_init(|| {
+            Arc::new(RomDb::parse(include_str!("romdb.xml")).expect("romdb.xml is broken"))
+        }).clone()
+    }
+
+    /// Reads a database file, e.g. nes20db.xml.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<RomDb> {
+        let text = fs::read_to_string(path)?;
+        RomDb::parse(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Looks up a ROM by its PRG and CHR.
+    pub fn find(&self, rom: &[u8]) -> Option<&DbEntry> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        let crc = crc32(rom);
+        let mut sha = None;
+        self.entries.iter().filter(|e| e.crc32 == crc).find(|e| match e.sha1 {
+            Some(expected) => *sha.get_or_insert_with(|| sha1(rom)) == expected,
+            None => true,
+        })
+    }
+
+    /// Parses the <game> entries out of nes20db.xml style text. Anything
+    ///  else in the file is ignored.
+    pub fn parse(xml: &str) -> Result<RomDb, String> {
+        let mut db = RomDb::default();
+        let mut entry = None;
+        for (tag, attributes) in tags(xml) {
+            match tag {
+                "game" => entry = Some(DbEntry {
+                    crc32:          0,
+                    sha1:           None,
+                    prg_rom_size:   0,
+                    chr_rom_size:   0,
+                    prg_ram_size:   0,
+                    prg_nvram_size: 0,
+                    chr_ram_size:   0,
+                    chr_nvram_size: 0,
+                    mapper:         0,
+                    submapper:      0,
+                    mirroring:      None,
+                    battery:        false,
+                    console:        ConsoleType::Nes,
+                    timing:         Timing::Ntsc,
+                    vs_ppu:         0,
+                    vs_hardware:    0,
+                    expansion:      0,
+                }),
+                "/game" => match entry.take() {
+                    Some(game) if game.prg_rom_size > 0 => db.entries.push(game),
+                    Some(game) => return Err(format!("Game {:08X} has no PRG-ROM.", game.crc32)),
+                    None => return Err("</game> without <game>.".to_string()),
+                },
+                _ => if let Some(game) = entry.as_mut() {
+                    game.set(tag, &attributes)?;
+                },
+            }
+        }
+        if entry.is_some() {
+            return Err("Unclosed <game>.".to_string());
+        }
+        Ok(db)
+    }
+}
+
+/// Every tag in an XML document, with its attributes, in order. Closing
+///  tags come back as "/name". Comments are skipped.
+fn tags(xml: &str) -> Vec<(&str, Vec<(&str, &str)>)> {
+    let mut out = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        if let Some(comment) = rest.strip_prefix("!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        let end = rest.find('>').unwrap_or(rest.len());
+        let inside = rest[..end].trim_end_matches('/');
+        rest = rest.get(end + 1..).unwrap_or("");
+        let name_end = inside.find(char::is_whitespace).unwrap_or(inside.len());
+        out.push((&inside[..name_end], attributes(&inside[name_end..])));
+    }
+    out
+}
+
+/// name="value" pairs.
+fn attributes(mut text: &str) -> Vec<(&str, &str)> {
+    let mut out = Vec::new();
+    while let Some(eq) = text.find('=') {
+        let name = text[..eq].trim();
+        let after = text[eq + 1..].trim_start();
+        let quote = match after.chars().next() {
+            Some(q) if q == '"' || q == '\'' => q,
+            _ => break,
+        };
+        let value_end = match after[1..].find(quote) {
+            Some(end) => end + 1,
+            None => break,
+        };
+        out.push((name, &after[1..value_end]));
+        text = &after[value_end + 1..];
+    }
+    out
+}
+
+fn parse_sha1(hex: &str) -> Option<[u8; 20]> {
+    if hex.len() != 40 || !hex.is_ascii() {
+        return None;
+    }
+    let mut digest = [0; 20];
+    for (i, byte) in digest.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(digest)
+}
+
+#[cfg(test)]
+#[path = "./romdb_test.rs"]
+pub mod romdb_test;
@@ -0,0 +1,124 @@
+/*  Unit test module of the ROM database (romdb.rs).
+ */
+use crate::core::romdb::*;
+
+#[cfg(test)]
+pub mod romdb_test {
+    use super::*;
+    use crate::core::cartridge::CART;
+    use crate::core::checksum::{crc32, sha1};
+    use crate::core::header::{ConsoleType, Timing};
+    use crate::core::mapper::Mirroring;
+    use std::sync::Arc;
+
+    /// 16kb of PRG and 8kb of CHR, with a header that says NROM,
+    ///  horizontal, and however much PRG it's given.
+    fn rom(prg_banks: u8) -> (Vec<u8>, Vec<u8>) {
+        let mut data: Vec<u8> = (0..0x4000).map(|i| (i * 7) as u8).collect();
+        data.extend(vec![0x5A; 0x2000]);
+        let mut bytes = b"NES\x1A".to_vec();
+        bytes.extend_from_slice(&[prg_banks, 1]);
+        bytes.resize(16, 0);
+        bytes.extend_from_slice(&data);
+        (bytes, data)
+    }
+
+    fn database(data: &[u8]) -> String {
+        let sha: String = sha1(data).iter().map(|b| format!("{:02x}", b)).collect();
+        format!("<?xml version=\"1.0\"?>\n\
+                 <nes20db>\n\
+                 <!-- Some Game <not a tag> -->\n\
+                 <game>\n\
+                   <rom size=\"24576\" crc32=\"{:08X}\" sha1=\"{}\"/>\n\
+                   <prgrom size=\"16384\"/>\n\
+                   <chrrom size=\"8192\"/>\n\
+                   <prgnvram size=\"8192\"/>\n\
+                   <pcb mapper=\"1\" submapper=\"5\" mirroring=\"V\" battery=\"1\"/>\n\
+                   <console type='1' region='1'/>\n\
+                   <vs hardware=\"2\" ppu=\"3\"/>\n\
+                 </game>\n\
+                 <game>\n\
+                   <rom size=\"16384\" crc32=\"DEADBEEF\"/>\n\
+                   <prgrom size=\"16384\"/>\n\
+                   <pcb mapper=\"4\" mirroring=\"1\"/>\n\
+                 </game>\n\
+                 </nes20db>\n", crc32(data), sha)
+    }
+
+    #[test]
+    fn test_parse(){
+        let (_, data) = rom(1);
+        let db = RomDb::parse(&database(&data)).unwrap();
+        assert_eq!(db.len(), 2);
+        let game = &db.entries[0];
+        assert_eq!(game.sha1, Some(sha1(&data)));
+        assert_eq!((game.prg_rom_size, game.chr_rom_size, game.prg_nvram_size), (0x4000, 0x2000, 0x2000));
+        assert_eq!((game.mapper, game.submapper, game.battery), (1, 5, true));
+        assert_eq!(game.mirroring, Some(Mirroring::Vertical));
+        assert_eq!((game.console, game.timing), (ConsoleType::VsSystem, Timing::Pal));
+        assert_eq!((game.vs_hardware, game.vs_ppu), (2, 3));
+        assert_eq!(db.entries[1].crc32, 0xDEADBEEF);
+        assert_eq!(db.entries[1].mirroring, None);
+
+        assert_eq!(db.find(&data), Some(game));
+        assert_eq!(db.find(&data[1..]), None);
+        //Same CRC, different SHA-1.
+        let mut other = db.clone();
+        other.entries[0].sha1.as_mut().unwrap()[0] ^= 1;
+        assert_eq!(other.find(&data), None);
+
+        assert!(RomDb::parse("<game><prgrom size=\"x\"/></game>").is_err());
+        assert!(RomDb::parse("<game><rom crc32=\"1\"/></game>").is_err());
+        assert!(RomDb::parse("<game>").is_err());
+        assert!(!RomDb::builtin().is_empty());
+    }
+
+    #[test]
+    fn test_header_correction(){
+        let (bytes, data) = rom(1);
+        let db = Arc::new(RomDb::parse(&database(&data)).unwrap());
+        let mut cart = CART::new("");
+        cart.database = Some(db.clone());
+        cart.load_bytes(&bytes).unwrap();
+        assert!(cart.db_corrected);
+        assert_eq!(cart.header.mapper, 1);
+        assert_eq!(cart.header.mirroring, Mirroring::Vertical);
+        assert!(cart.header.battery);
+        assert_eq!(cart.PRG_RAM.len(), 0x2000);
+
+        //A header with the wrong PRG size still loads, found from the rest.
+        let (bytes, _) = rom(2);
+        let mut cart = CART::new("");
+        cart.database = Some(db);
+        cart.load_bytes(&bytes).unwrap();
+        assert_eq!(cart.PRG.len(), 0x4000);
+        assert_eq!(cart.CHR[0], 0x5A);
+
+        //And can be left alone.
+        let (bytes, _) = rom(1);
+        let mut cart = CART::new("");
+        cart.database = None;
+        cart.load_bytes(&bytes).unwrap();
+        assert!(!cart.db_corrected);
+        assert_eq!((cart.header.mapper, cart.header.mirroring), (0, Mirroring::Horizontal));
+    }
+
+    #[test]
+    fn test_builtin_correction(){
+        //Stands in for a Super Mario Bros. dump: not the game, but forged to
+        //  the same PRG+CHR CRC-32 (3337EC46) by its last 4 bytes. Its
+        //  header says MMC1 with horizontal mirroring.
+        let mut data: Vec<u8> = (0..0x8000).map(|i| i as u8).collect();
+        data.extend(vec![0; 0x2000 - 4]);
+        data.extend_from_slice(&[0x53, 0x8B, 0xBF, 0x32]);
+        assert_eq!(crc32(&data), 0x3337EC46);
+        let mut bytes = b"NES\x1A\x02\x01\x10".to_vec();
+        bytes.resize(16, 0);
+        bytes.extend_from_slice(&data);
+
+        //With default settings, the built in database fixes it.
+        let cart = CART::from_bytes(&bytes).unwrap();
+        assert!(cart.db_corrected);
+        assert_eq!((cart.header.mapper, cart.header.mirroring), (0, Mirroring::Vertical));
+    }
+}
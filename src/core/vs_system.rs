@@ -0,0 +1,162 @@
+/* Vs. System (Vs. Unisystem) arcade boards.
+ * The Vs. System runs NES games in a cabinet, with a few differences the
+ *  game can see:
+ *  - RGB PPUs. The RP2C03 and RC2C05 use the RGB_PPU_PALETTE. The RP2C04s
+ *    have the same colors in scrambled orders, one per chip, so a game only
+ *    looks right on its own PPU. The RC2C05s also swap $2000 and $2001, and
+ *    return an ID in the low bits of $2002, which some games check.
+ *  - Eight DIP switches for the operator (difficulty, lives, coinage, ...),
+ *    and a coin slot and service button.
+ *  - $4016/$4017 read all of that alongside the controllers:
+ *      $4016   76543210
+ *              ||||||++- Controller data
+ *              |||||+--- Service button
+ *              |||++---- DIP switches 1-2
+ *              |++------ Coin slots 1-2
+ *              +-------- 0 on the main CPU
+ *      $4017   DIP switches 3-8 in bits 2-7
+ *  - Writing bit 2 of $4016 switches banks on mapper 99 boards (see Vs99),
+ *    and $4020 drives the cabinet's coin counter.
+ *
+ * https://wiki.nesdev.com/w/index.php/Vs._System
+ */
+
+use crate::core::filter::{RGB_PPU_PALETTE, RP2C04_PALETTES};
+
+/// Frames a coin holds its slot's bit on for, about as long as a real coin
+///  takes to drop past the sensor.
+pub const COIN_FRAMES: u8 = 4;
+
+/// The PPUs Vs. System boards shipped with, as NES 2.0 byte 13 numbers them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VsPpu {
+    Rp2c03b,
+    Rp2c03g,
+    /// RP2C04-0001 to -0004.
+    Rp2c04(u8),
+    Rc2c03b,
+    Rc2c03c,
+    /// RC2C05-01 to -05.
+    Rc2c05(u8),
+}
+
+impl VsPpu {
+    /// From the low nibble of NES 2.0 byte 13. iNES headers don't say, and
+    ///  give 0, the RP2C03B.
+    pub fn from_header(ppu: u8) -> Option<VsPpu> {
+        match ppu {
+            0       => Some(VsPpu::Rp2c03b),
+            1       => Some(VsPpu::Rp2c03g),
+            2..=5   => Some(VsPpu::Rp2c04(ppu - 1)),
+            6       => Some(VsPpu::Rc2c03b),
+            7       => Some(VsPpu::Rc2c03c),
+            8..=12  => Some(VsPpu::Rc2c05(ppu - 7)),
+            _       => None,
+        }
+    }
+
+    /// The colors it outputs. None only for an RP2C04 that doesn't exist.
+    pub fn palette(&self) -> Option<&'static [u32; 64]> {
+        match self {
+            VsPpu::Rp2c04(n) => RP2C04_PALETTES.get((*n as usize).wrapping_sub(1)),
+            _ => Some(&RGB_PPU_PALETTE),
+        }
+    }
+
+    /// RC2C05s have $2000 and $2001 the other way around.
+    pub fn swaps_registers(&self) -> bool {
+        matches!(self, VsPpu::Rc2c05(_))
+    }
+
+    /// What the RC2C05s put in the low 5 bits of $2002.
+    pub fn status_id(&self) -> Option<u8> {
+        match self {
+            VsPpu::Rc2c05(1) | VsPpu::Rc2c05(4) => Some(0x1B),
+            VsPpu::Rc2c05(2) => Some(0x3D),
+            VsPpu::Rc2c05(3) => Some(0x1C),
+            _ => None,
+        }
+    }
+}
+
+/// The cabinet side of a Vs. System: DIP switches, coins, and the service
+///  button.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VsSystem {
+    pub ppu:        VsPpu,
+    /// DIP switches 1-8 in bits 0-7, set when on.
+    pub dip:        u8,
+    pub service:    bool,
+    /// Frames left on each coin slot's bit.
+    coins:          [u8; 2],
+    /// Coins the game has counted, through $4020.
+    pub coin_count: u32,
+    counter:        bool,
+}
+
+impl VsSystem {
+    pub fn new(ppu: VsPpu) -> VsSystem {
+        VsSystem { ppu, dip: 0, service: false, coins: [0; 2], coin_count: 0, counter: false }
+    }
+
+    /// Sets DIP switch 1-8.
+    pub fn set_dip_switch(&mut self, switch: u8, on: bool) {
+        if (1..=8).contains(&switch) {
+            let bit = 1 << (switch - 1);
+            if on { self.dip |= bit; } else { self.dip &= !bit; }
+        }
+    }
+
+    /// Drops a coin into slot 0 or 1.
+    pub fn insert_coin(&mut self, slot: usize) {
+        if let Some(coin) = self.coins.get_mut(slot) {
+            *coin = COIN_FRAMES;
+        }
+    }
+
+    /// Counts down coins, once a frame.
+    pub fn step_frame(&mut self) {
+        for coin in self.coins.iter_mut() {
+            *coin = coin.saturating_sub(1);
+        }
+    }
+
+    /// The cabinet's bits of $4016 or $4017 (register 0 or 1). The
+    ///  controller bits are left clear.
+    pub fn read(&self, register: usize) -> u8 {
+        if register == 0 {
+            (self.service as u8) << 2
+                | (self.dip & 0x03) << 3
+                | ((self.coins[0] > 0) as u8) << 5
+                | ((self.coins[1] > 0) as u8) << 6
+        }
+        else {
+            self.dip & 0xFC
+        }
+    }
+
+    /// $4020: the coin counter clicks as bit 0 goes on.
+    pub fn write_counter(&mut self, val: u8) {
+        let on = val & 1 != 0;
+        if on && !self.counter {
+            self.coin_count += 1;
+        }
+        self.counter = on;
+    }
+}
+
+/// Parses DIP switches written as 8 0s and 1s, switch 1 first.
+pub fn parse_dip_switches(text: &str) -> Option<u8> {
+    if text.len() != 8 {
+        return None;
+    }
+    text.chars().enumerate().try_fold(0u8, |dip, (i, c)| match c {
+        '0' => Some(dip),
+        '1' => Some(dip | 1 << i),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+#[path = "./vs_system_test.rs"]
+pub mod vs_system_test;
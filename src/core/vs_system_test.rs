@@ -0,0 +1,111 @@
+/*  Unit test module of the Vs. System (vs_system.rs).
+ */
+use crate::core::vs_system::*;
+
+#[cfg(test)]
+pub mod vs_system_test {
+    use super::*;
+    use crate::core::cartridge::CART;
+    use crate::core::filter::{RGB_PPU_PALETTE, RP2C04_PALETTES};
+    use crate::core::nes::NES;
+
+    /// A mapper 99 NES 2.0 ROM on a PPU, with 40kb PRG (each 8kb bank
+    ///  filled with its number) and two CHR banks (filled with $A0, $A1).
+    ///  It loops at $E000.
+    fn rom(ppu: u8) -> Vec<u8> {
+        let mut bytes = b"NES\x1A\x36\x02\x30\x69\x00\x0F\x00\x00\x00".to_vec();
+        bytes.push(ppu);
+        bytes.resize(16, 0);
+        for bank in 0..5 {
+            bytes.extend(vec![bank; 0x2000]);
+        }
+        bytes[16 + 0x6000..][..3].copy_from_slice(&[0x4C, 0x00, 0xE0]);       //JMP $E000
+        bytes[16 + 0x7FFA..][..6].copy_from_slice(&[0x00, 0xE0, 0x00, 0xE0, 0x00, 0xE0]);
+        bytes.extend(vec![0xA0; 0x2000]);
+        bytes.extend(vec![0xA1; 0x2000]);
+        bytes
+    }
+
+    #[test]
+    fn test_ppus(){
+        assert_eq!(VsPpu::from_header(0), Some(VsPpu::Rp2c03b));
+        assert_eq!(VsPpu::from_header(3), Some(VsPpu::Rp2c04(2)));
+        assert_eq!(VsPpu::from_header(12), Some(VsPpu::Rc2c05(5)));
+        assert_eq!(VsPpu::from_header(13), None);
+        assert_eq!(VsPpu::Rp2c04(5).palette(), None);
+        assert_eq!(VsPpu::Rc2c03b.palette(), Some(&RGB_PPU_PALETTE));
+        assert_eq!(VsPpu::Rc2c05(2).status_id(), Some(0x3D));
+        assert!(!VsPpu::Rp2c03b.swaps_registers());
+
+        assert_eq!(parse_dip_switches("10000001"), Some(0x81));
+        assert_eq!(parse_dip_switches("1000000"), None);
+        assert_eq!(parse_dip_switches("1000000x"), None);
+    }
+
+    #[test]
+    fn test_rp2c04_palettes(){
+        //Color 0 of each chip, and where it is on the RP2C03.
+        for (chip, rp2c03) in [(1, 0x35), (2, 0x2E), (3, 0x14), (4, 0x18)] {
+            let palette = VsPpu::Rp2c04(chip).palette().unwrap();
+            assert_eq!(palette[0], RGB_PPU_PALETTE[rp2c03], "RP2C04-000{}", chip);
+        }
+        //Same colors, different orders.
+        let mut colors: Vec<Vec<u32>> = RP2C04_PALETTES.iter().map(|p| p.to_vec()).collect();
+        colors.iter_mut().for_each(|c| c.sort_unstable());
+        assert!(colors.iter().all(|c| *c == colors[0]));
+
+        let nes = NES::from_cart(CART::from_bytes(rom(2)).unwrap()).unwrap();
+        assert_eq!(nes.palette(), &RP2C04_PALETTES[0]);
+    }
+
+    #[test]
+    fn test_cabinet(){
+        let mut nes = NES::from_cart(CART::from_bytes(rom(0)).unwrap()).unwrap();
+        assert_eq!(nes.palette(), &RGB_PPU_PALETTE);
+        let vs = nes.vs_system().unwrap();
+        vs.set_dip_switch(1, true);
+        vs.set_dip_switch(3, true);
+        vs.set_dip_switch(8, true);
+        vs.service = true;
+        assert_eq!(nes.cpu.memory.get(0x4016) & 0xFC, 0x0C);
+        assert_eq!(nes.cpu.memory.get(0x4017) & 0xFC, 0x84);
+
+        //A coin shows for a few frames, then it's gone.
+        nes.insert_coin(1);
+        assert_eq!(nes.cpu.memory.get(0x4016) & 0x60, 0x40);
+        for _ in 0..COIN_FRAMES {
+            nes.step_frame();
+        }
+        assert_eq!(nes.cpu.memory.get(0x4016) & 0x60, 0);
+
+        nes.cpu.memory.set(0x4020, 1);
+        nes.cpu.memory.set(0x4020, 1);
+        nes.cpu.memory.set(0x4020, 0);
+        nes.cpu.memory.set(0x4020, 1);
+        assert_eq!(nes.vs_system().unwrap().coin_count, 2);
+    }
+
+    #[test]
+    fn test_mapper_99(){
+        let mut nes = NES::from_cart(CART::from_bytes(rom(8)).unwrap()).unwrap();
+        let memory = &mut nes.cpu.memory;
+        assert_eq!((memory.get(0x8000), memory.get(0xA000), memory.get(0xFFF0)), (0, 1, 3));
+        assert_eq!(memory.CART.get_chr(0x1FFF), 0xA0);
+        memory.set(0x4016, 0x04);
+        assert_eq!((memory.get(0x9FFF), memory.get(0xA000)), (4, 1));
+        assert_eq!(memory.CART.get_chr(0x0000), 0xA1);
+        memory.set(0x4016, 0x00);
+        assert_eq!(memory.get(0x8000), 0);
+
+        //Off a Vs. System, controller strobes don't reach the board.
+        let vs = memory.VS.take();
+        memory.set(0x4016, 0x04);
+        assert_eq!(memory.get(0x8000), 0);
+        memory.VS = vs;
+
+        //The RC2C05-01 swaps $2000 and $2001, and has an ID in $2002.
+        memory.set(0x2001, 0x03);
+        assert_eq!(memory.PPU.t & 0x0C00, 0x0C00);
+        assert_eq!(memory.get(0x2002) & 0x1F, 0x1B);
+    }
+}
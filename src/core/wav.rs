@@ -0,0 +1,186 @@
+/* WAV recording of audio output.
+ * Writes 16-bit mono PCM. The RIFF sizes aren't known until the end, so
+ *  they're patched in by finish().
+ * Every writer also keeps a CRC-32 of the PCM data it wrote, so headless
+ *  runs can compare audio by hash, the same way screenshots do.
+ */
+
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::{BufWriter, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use crate::core::apu::{APU, CHANNEL_NAMES};
+use crate::core::audio::{nes_filters, Filter, Resampler, CPU_CLOCK_NTSC};
+use crate::core::checksum::Crc32;
+
+/// Size of the RIFF and fmt headers, before the PCM data.
+const HEADER_SIZE: u32 = 44;
+
+/// Streams samples into a WAV file.
+pub struct WavWriter<W: Write + Seek> {
+    out:            W,
+    sample_rate:    u32,
+    samples:        u32,
+    crc:            Crc32,
+}
+
+impl WavWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P, sample_rate: u32) -> io::Result<WavWriter<BufWriter<File>>> {
+        WavWriter::new(BufWriter::new(File::create(path)?), sample_rate)
+    }
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    /// Writes a header with empty sizes, finish() fills them in.
+    pub fn new(mut out: W, sample_rate: u32) -> io::Result<WavWriter<W>> {
+        out.write_all(&wav_header(sample_rate, 0))?;
+        Ok(WavWriter { out, sample_rate, samples: 0, crc: Crc32::new() })
+    }
+
+    /// Writes one sample, clipped to -1.0..1.0.
+    pub fn write_sample(&mut self, sample: f32) -> io::Result<()> {
+        let pcm = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        let bytes = pcm.to_le_bytes();
+        self.crc.update(&bytes);
+        self.samples += 1;
+        self.out.write_all(&bytes)
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Samples written so far.
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    /// CRC-32 of the PCM data written so far (not of the header).
+    pub fn crc32(&self) -> u32 {
+        self.crc.finish()
+    }
+
+    /// Patches the header sizes, and hands back the output.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.out.seek(SeekFrom::Start(0))?;
+        self.out.write_all(&wav_header(self.sample_rate, self.samples * 2))?;
+        self.out.seek(SeekFrom::End(0))?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/// RIFF/WAVE header for 16-bit mono PCM with data_size bytes of samples.
+fn wav_header(sample_rate: u32, data_size: u32) -> Vec<u8> {
+    let mut out = Vec::with_capacity(HEADER_SIZE as usize);
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&(HEADER_SIZE - 8 + data_size).to_le_bytes());
+    out.extend_from_slice(b"WAVEfmt ");
+    out.extend_from_slice(&16u32.to_le_bytes());
+    out.extend_from_slice(&1u16.to_le_bytes());                 //PCM
+    out.extend_from_slice(&1u16.to_le_bytes());                 //Mono
+    out.extend_from_slice(&sample_rate.to_le_bytes());
+    out.extend_from_slice(&(sample_rate * 2).to_le_bytes());    //Byte rate
+    out.extend_from_slice(&2u16.to_le_bytes());                 //Block align
+    out.extend_from_slice(&16u16.to_le_bytes());                //Bits per sample
+    out.extend_from_slice(b"data");
+    out.extend_from_slice(&data_size.to_le_bytes());
+    out
+}
+
+/// What a finished recording wrote.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedFile {
+    pub path:       PathBuf,
+    pub samples:    u32,
+    pub crc32:      u32,
+}
+
+/// One channel recorded on its own, with its own resampler and filters.
+struct ChannelTrack {
+    resampler:  Resampler,
+    filters:    Vec<Filter>,
+    path:       PathBuf,
+    writer:     WavWriter<BufWriter<File>>,
+}
+
+/// Records the mixed output, and optionally each APU channel to its own
+///  file. The mixed file gets the samples the audio pipeline outputs, so
+///  it's exactly what a frontend would have played.
+pub struct AudioRecorder {
+    path:       PathBuf,
+    mixed:      WavWriter<BufWriter<File>>,
+    channels:   Vec<ChannelTrack>,
+}
+
+impl AudioRecorder {
+    /// Starts recording to path. With per_channel, each channel also goes
+    ///  to its own file next to it, e.g. song.wav -> song_pulse1.wav.
+    pub fn new<P: AsRef<Path>>(path: P, sample_rate: u32, per_channel: bool) -> io::Result<AudioRecorder> {
+        let path = path.as_ref().to_path_buf();
+        let mut channels = Vec::new();
+        if per_channel {
+            for name in CHANNEL_NAMES.iter() {
+                let channel_path = channel_path(&path, name);
+                channels.push(ChannelTrack {
+                    resampler:  Resampler::new(CPU_CLOCK_NTSC, sample_rate),
+                    filters:    nes_filters(sample_rate),
+                    writer:     WavWriter::create(&channel_path, sample_rate)?,
+                    path:       channel_path,
+                });
+            }
+        }
+        Ok(AudioRecorder {
+            mixed: WavWriter::create(&path, sample_rate)?,
+            path,
+            channels,
+        })
+    }
+
+    /// Called once per CPU cycle, with the sample the audio pipeline
+    ///  finished this cycle (if any).
+    pub fn record(&mut self, apu: &APU, mixed: Option<f32>) -> io::Result<()> {
+        if let Some(sample) = mixed {
+            self.mixed.write_sample(sample)?;
+        }
+        if !self.channels.is_empty() {
+            let levels = apu.channel_levels();
+            for (track, &level) in self.channels.iter_mut().zip(levels.iter()) {
+                if let Some(mut sample) = track.resampler.push(level) {
+                    for filter in &mut track.filters {
+                        sample = filter.step(sample);
+                    }
+                    track.writer.write_sample(sample)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Finishes every file. The mixed recording comes first.
+    pub fn finish(self) -> io::Result<Vec<RecordedFile>> {
+        let mut files = vec![finish_writer(self.path, self.mixed)?];
+        for track in self.channels {
+            files.push(finish_writer(track.path, track.writer)?);
+        }
+        Ok(files)
+    }
+}
+
+fn finish_writer(path: PathBuf, writer: WavWriter<BufWriter<File>>) -> io::Result<RecordedFile> {
+    let (samples, crc32) = (writer.samples(), writer.crc32());
+    writer.finish()?;
+    Ok(RecordedFile { path, samples, crc32 })
+}
+
+/// song.wav -> song_<channel>.wav
+fn channel_path(path: &Path, channel: &str) -> PathBuf {
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("audio");
+    path.with_file_name(format!("{}_{}.wav", stem, channel))
+}
+
+#[cfg(test)]
+#[path = "./wav_test.rs"]
+pub mod wav_test;
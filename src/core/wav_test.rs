@@ -0,0 +1,84 @@
+/*  Unit test module of WAV recording (wav.rs).
+ */
+use crate::core::wav::*;
+
+#[cfg(test)]
+pub mod wav_test {
+    use super::*;
+    use crate::core::nes::NES;
+    use crate::core::ppu::ppu_test::ppu_test::test_rom;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_wav_header(){
+        let mut wav = WavWriter::new(Cursor::new(Vec::new()), 44_100).unwrap();
+        wav.write_sample(0.0).unwrap();
+        wav.write_sample(1.0).unwrap();
+        wav.write_sample(-2.0).unwrap();
+        assert_eq!(wav.samples(), 3);
+        let bytes = wav.finish().unwrap().into_inner();
+
+        assert_eq!(bytes.len(), 44 + 6);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(&bytes[4..8], &(36u32 + 6).to_le_bytes());
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(&bytes[24..28], &44_100u32.to_le_bytes());
+        assert_eq!(&bytes[36..40], b"data");
+        assert_eq!(&bytes[40..44], &6u32.to_le_bytes());
+        //Samples are clipped.
+        assert_eq!(&bytes[44..], &[0x00, 0x00, 0xFF, 0x7F, 0x01, 0x80]);
+    }
+
+    /// Runs the ROM for a few frames while recording, returns what was written.
+    fn record(rom: &std::path::Path, path: &std::path::Path) -> Vec<RecordedFile> {
+        let mut nes = NES::new(rom).unwrap();
+        //The first frame is cut short, start on a frame boundary.
+        nes.step_frame();
+        nes.start_recording(path, true).unwrap();
+        assert!(nes.is_recording());
+        for _ in 0..10 {
+            nes.step_frame();
+        }
+        let files = nes.stop_recording().unwrap();
+        assert!(!nes.is_recording());
+        files
+    }
+
+    #[test]
+    fn test_nes_recording(){
+        //Pulse 1 at a constant volume 15, forever.
+        let rom = test_rom("wav", &[
+            0xA9, 0x01, 0x8D, 0x15, 0x40,   //LDA #$01, STA $4015
+            0xA9, 0xBF, 0x8D, 0x00, 0x40,   //LDA #$BF, STA $4000
+            0xA9, 0xFD, 0x8D, 0x02, 0x40,   //LDA #$FD, STA $4002
+            0xA9, 0x00, 0x8D, 0x03, 0x40,   //LDA #$00, STA $4003
+            0x4C, 0x14, 0x80,               //JMP $8014
+        ]);
+        let dir = std::env::temp_dir().join(format!("soliloquy_wav_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let files = record(&rom, &dir.join("song.wav"));
+        let names: Vec<_> = files.iter()
+            .map(|f| f.path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(names, ["song.wav", "song_pulse1.wav", "song_pulse2.wav",
+                           "song_triangle.wav", "song_noise.wav", "song_dmc.wav"]);
+
+        //About 735 samples per frame at 44.1 kHz, in every file.
+        assert!(files[0].samples > 7000 && files[0].samples < 7500);
+        //Channel resamplers start fresh, so they may be a sample out.
+        assert!(files.iter().all(|f| (f.samples as i64 - files[0].samples as i64).abs() <= 1));
+        let on_disk = std::fs::metadata(&files[0].path).unwrap().len();
+        assert_eq!(on_disk, 44 + files[0].samples as u64 * 2);
+
+        //Only pulse 1 makes any sound, so pulse 2 and noise are both silence.
+        //(The triangle holds its last level, so it isn't.)
+        assert_ne!(files[1].crc32, files[2].crc32);
+        assert_eq!(files[2].crc32, files[4].crc32);
+
+        //Same ROM, same audio.
+        let again = record(&rom, &dir.join("again.wav"));
+        assert_eq!(files[0].crc32, again[0].crc32);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,6 +1,40 @@
-// Author: Spalynx 
+// Author: Spalynx
 // INIT Date: 9/13/17
 // This should only be used for testing of the virtual hardware.
+//
+// Usage: soliloquy [ROM] [--frames N] [--screenshot FILE]
+//                  [--dump-ppu DIR] [--pattern-palette N]
+//                  [--record-wav FILE] [--wav-channels]
+//                  [--mute CH,..] [--solo CH,..] [--volume CH=V]
+//                  [--track N] [--seconds S]
+//                  [--port1 DEV] [--port2 DEV] [--expansion DEV]
+//                  [--record-movie FILE] [--play-movie FILE] [--expect-hash H]
+//                  [--input-map FILE] [--hold KEY] [--dip BITS]
+//                  [--rom-db FILE] [--no-rom-db]
+//   With --screenshot, runs the ROM headless for N frames (default 60),
+//   writes the last frame (PNG, or PPM by extension), and prints its CRC32.
+//   With --dump-ppu, writes the pattern tables (drawn with palette 0-7),
+//   nametables, OAM and palette RAM as PNGs into DIR after N frames, and
+//   a PlayChoice-10 game's INST-ROM as tiles.
+//   With --record-wav, records the audio of those N frames (or of the movie
+//   being played or recorded), and prints the CRC32 of its samples. --wav-channels also writes one file per channel.
+//   --mute, --solo and --volume (repeatable) set the channel controls, for
+//   pulse1, pulse2, triangle, noise, dmc and expansion.
+//   NSF/NSFe files are played instead of run, starting at --track N (from 1).
+//   --seconds S is a shorthand for --frames S*60, to render tracks to WAV.
+//   --port1, --port2 and --expansion plug in input devices for the game:
+//   controller, four-score, zapper, paddle, power-pad (ports), or
+//   zapper, famicom-paddle, keyboard, family-trainer (expansion).
+//   --record-movie saves the input of the N frames as an FM2 movie.
+//   --play-movie replays an FM2 movie headless to its last frame, and
+//   prints a CRC32 of RAM and the last frame. With --expect-hash, exits
+//   with an error if that hash doesn't match.
+//   --input-map loads a host key to NES button map (see keymap.rs), and
+//   --hold (repeatable) holds a host key down for the whole run.
+//   --dip sets a Vs. System game's DIP switches, as 8 0s and 1s from
+//   switch 1 to 8.
+//   --rom-db looks ROMs up in another header database (e.g. nes20db.xml)
+//   instead of the built in one, --no-rom-db trusts the header as it is.
 
 extern crate soliloquy;
 pub mod core;
@@ -9,16 +43,314 @@ pub mod core;
 extern crate log;
 extern crate env_logger;
 
+use std::env;
+use std::process;
+use std::sync::Arc;
+
+use crate::core::apu::Channel;
+use crate::core::cartridge::CART;
+use crate::core::input::{DeviceKind, InputConfig, PORT_EXPANSION};
+use crate::core::movie::Movie;
+use crate::core::nes::NES;
+use crate::core::keymap::InputMap;
+use crate::core::nsf::Nsf;
+use crate::core::romdb::RomDb;
+use crate::core::vs_system::parse_dip_switches;
+
+/// Command line options.
+struct Options {
+    rom:        String,
+    frames:     u64,
+    screenshot: Option<String>,
+    dump_ppu:   Option<String>,
+    pattern_palette: u16,
+    record_wav: Option<String>,
+    wav_channels: bool,
+    mute:       Vec<Channel>,
+    solo:       Vec<Channel>,
+    volume:     Vec<(Channel, f32)>,
+    track:      Option<u8>,
+    input:      InputConfig,
+    record_movie: Option<String>,
+    play_movie: Option<String>,
+    expect_hash: Option<u32>,
+    input_map:  Option<String>,
+    hold:       Vec<String>,
+    dip:        Option<u8>,
+    rom_db:     Option<String>,
+    no_rom_db:  bool,
+}
+
+fn usage() -> ! {
+    eprintln!("Usage: soliloquy [ROM] [--frames N] [--screenshot FILE]");
+    eprintln!("                 [--dump-ppu DIR] [--pattern-palette N]");
+    eprintln!("                 [--record-wav FILE] [--wav-channels]");
+    eprintln!("                 [--mute CH,..] [--solo CH,..] [--volume CH=V]");
+    eprintln!("                 [--track N] [--seconds S]");
+    eprintln!("                 [--port1 DEV] [--port2 DEV] [--expansion DEV]");
+    eprintln!("                 [--record-movie FILE] [--play-movie FILE] [--expect-hash H]");
+    eprintln!("                 [--input-map FILE] [--hold KEY] [--dip BITS]");
+    eprintln!("                 [--rom-db FILE] [--no-rom-db]");
+    process::exit(2);
+}
+
+fn parse_args() -> Options {
+    let mut opts = Options {
+        rom:        "example/nestest.nes".to_string(),
+        frames:     60,
+        screenshot: None,
+        dump_ppu:   None,
+        pattern_palette: 0,
+        record_wav: None,
+        wav_channels: false,
+        mute:       Vec::new(),
+        solo:       Vec::new(),
+        volume:     Vec::new(),
+        track:      None,
+        input:      InputConfig::new(),
+        record_movie: None,
+        play_movie: None,
+        expect_hash: None,
+        input_map:  None,
+        hold:       Vec::new(),
+        dip:        None,
+        rom_db:     None,
+        no_rom_db:  false,
+    };
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--frames"      => {
+                opts.frames = args.next().and_then(|n| n.parse().ok())
+                                  .unwrap_or_else(|| usage());
+            }
+            "--screenshot"  => opts.screenshot = Some(args.next().unwrap_or_else(|| usage())),
+            "--dump-ppu"    => opts.dump_ppu = Some(args.next().unwrap_or_else(|| usage())),
+            "--pattern-palette" => {
+                opts.pattern_palette = args.next().and_then(|n| n.parse().ok())
+                                           .filter(|&n| n < 8)
+                                           .unwrap_or_else(|| usage());
+            }
+            "--record-wav"  => opts.record_wav = Some(args.next().unwrap_or_else(|| usage())),
+            "--wav-channels" => opts.wav_channels = true,
+            "--mute"        => opts.mute.extend(parse_channels(args.next())),
+            "--solo"        => opts.solo.extend(parse_channels(args.next())),
+            "--volume"      => opts.volume.push(parse_volume(args.next())),
+            "--track"       => {
+                opts.track = Some(args.next().and_then(|n| n.parse().ok())
+                                      .filter(|&n: &u8| n > 0)
+                                      .unwrap_or_else(|| usage()));
+            }
+            "--seconds"     => {
+                let seconds: u64 = args.next().and_then(|n| n.parse().ok())
+                                       .unwrap_or_else(|| usage());
+                opts.frames = seconds * 60;
+            }
+            "--port1"       => plug(&mut opts.input, 0, args.next()),
+            "--port2"       => plug(&mut opts.input, 1, args.next()),
+            "--expansion"   => plug(&mut opts.input, PORT_EXPANSION, args.next()),
+            "--record-movie" => opts.record_movie = Some(args.next().unwrap_or_else(|| usage())),
+            "--play-movie"  => opts.play_movie = Some(args.next().unwrap_or_else(|| usage())),
+            "--expect-hash" => {
+                let hash = args.next().unwrap_or_else(|| usage());
+                opts.expect_hash = Some(u32::from_str_radix(hash.trim_start_matches("0x"), 16)
+                                        .unwrap_or_else(|_| usage()));
+            }
+            "--input-map"   => opts.input_map = Some(args.next().unwrap_or_else(|| usage())),
+            "--hold"        => opts.hold.push(args.next().unwrap_or_else(|| usage())),
+            "--dip"         => {
+                opts.dip = Some(args.next().as_deref().and_then(parse_dip_switches)
+                                    .unwrap_or_else(|| usage()));
+            }
+            "--rom-db"      => opts.rom_db = Some(args.next().unwrap_or_else(|| usage())),
+            "--no-rom-db"   => opts.no_rom_db = true,
+            "-h" | "--help" => usage(),
+            _ if arg.starts_with("--") => usage(),
+            _               => opts.rom = arg,
+        }
+    }
+    opts
+}
+
+/// "pulse1,noise" -> [Pulse1, Noise]
+fn parse_channels(arg: Option<String>) -> Vec<Channel> {
+    let arg = arg.unwrap_or_else(|| usage());
+    arg.split(',')
+       .map(|name| Channel::from_name(name.trim()).unwrap_or_else(|| usage()))
+       .collect()
+}
+
+/// "--port2 zapper" -> a Zapper in port 2.
+fn plug(config: &mut InputConfig, port: usize, arg: Option<String>) {
+    let arg = arg.unwrap_or_else(|| usage());
+    let kind = DeviceKind::from_name(&arg).unwrap_or_else(|| usage());
+    if let Err(e) = config.set(port, kind) {
+        eprintln!("{}", e);
+        process::exit(2);
+    }
+}
+
+/// "dmc=0.5" -> (Dmc, 0.5)
+fn parse_volume(arg: Option<String>) -> (Channel, f32) {
+    let arg = arg.unwrap_or_else(|| usage());
+    let mut parts = arg.splitn(2, '=');
+    let channel = parts.next().and_then(Channel::from_name);
+    let volume = parts.next().and_then(|v| v.parse().ok());
+    match (channel, volume) {
+        (Some(c), Some(v)) => (c, v),
+        _ => usage(),
+    }
+}
+
 fn main() {
     env_logger::from_env(env_logger::Env::default().default_filter_or("debug"))
         .init();
+    let opts = parse_args();
 
     debug!("COMPLETE -> Logger init.");
-    let mut nes_main = 
-        core::nes::NES::new("example/nestest.nes");
+    let is_nsf = opts.rom.to_ascii_lowercase().ends_with(".nsf")
+              || opts.rom.to_ascii_lowercase().ends_with(".nsfe");
+    let mut nes_main = if is_nsf {
+        let nsf = Nsf::load(&opts.rom).unwrap_or_else(|e| {
+            eprintln!("Could not load {}: {}", opts.rom, e);
+            process::exit(1);
+        });
+        println!("{} - {} ({} tracks)", nsf.name, nsf.artist, nsf.total_songs);
+        let mut nes = core::nes::NES::new_nsf(nsf);
+        if let Some(track) = opts.track {
+            nes.select_track(track - 1);
+        }
+        nes
+    }
+    else {
+        let mut cart = CART::new(&opts.rom);
+        if opts.no_rom_db {
+            cart.database = None;
+        }
+        else if let Some(path) = &opts.rom_db {
+            cart.database = Some(Arc::new(RomDb::load(path).unwrap_or_else(|e| {
+                eprintln!("Could not load {}: {}", path, e);
+                process::exit(1);
+            })));
+        }
+        cart.read_cart().and_then(|_| core::nes::NES::from_cart(cart)).unwrap_or_else(|e| {
+            eprintln!("Could not load {}: {}", opts.rom, e);
+            process::exit(1);
+        })
+    };
     debug!("COMPLETE -> NES boot/CPU boot");
-    for i in 1..=200 { 
-        debug!("INSTRUCTION: #{}", i );
-        nes_main.step();
+    nes_main.configure_input(&opts.input);
+    if opts.input_map.is_some() || !opts.hold.is_empty() {
+        let map = match &opts.input_map {
+            Some(path) => InputMap::load(path).unwrap_or_else(|e| {
+                eprintln!("Could not load {}: {}", path, e);
+                process::exit(1);
+            }),
+            None => InputMap::default(),
+        };
+        nes_main.set_input_map(map);
+        for key in &opts.hold {
+            nes_main.key_down(key);
+        }
+    }
+
+    if let Some(dip) = opts.dip {
+        match nes_main.vs_system() {
+            Some(vs) => vs.dip = dip,
+            None => warn!("--dip is only for Vs. System games."),
+        }
+    }
+
+    for &channel in &opts.mute {
+        nes_main.set_channel_muted(channel, true);
+    }
+    for &channel in &opts.solo {
+        nes_main.set_channel_solo(channel, true);
+    }
+    for &(channel, volume) in &opts.volume {
+        nes_main.set_channel_volume(channel, volume);
+    }
+
+    if let Some(path) = &opts.record_wav {
+        if let Err(e) = nes_main.start_recording(path, opts.wav_channels) {
+            fail(&mut nes_main, format!("Could not write {}: {}", path, e));
+        }
     }
+
+    if let Some(path) = &opts.play_movie {
+        let movie = Movie::load(path).unwrap_or_else(|e| {
+            fail(&mut nes_main, format!("Could not load {}: {}", path, e))
+        });
+        if let Err(e) = nes_main.play_movie(movie) {
+            fail(&mut nes_main, e);
+        }
+        while !nes_main.movie_finished() {
+            nes_main.step_frame();
+        }
+        let hash = nes_main.replay_hash();
+        println!("{:08x}  {} ({} frames)", hash, path, nes_main.frame_count());
+        if let Some(image) = &opts.screenshot {
+            if let Err(e) = nes_main.save_screenshot(image) {
+                fail(&mut nes_main, format!("Could not write {}: {}", image, e));
+            }
+        }
+        match opts.expect_hash {
+            Some(expected) if expected != hash => {
+                fail(&mut nes_main, format!("Replay hash mismatch: expected {:08x}, got {:08x}.",
+                                            expected, hash));
+            }
+            _ => {}
+        }
+    }
+    else if let Some(path) = &opts.record_movie {
+        nes_main.record_movie();
+        for _ in 0..opts.frames {
+            nes_main.step_frame();
+        }
+        let movie = nes_main.stop_movie().unwrap_or_default();
+        if let Err(e) = movie.save(path) {
+            fail(&mut nes_main, format!("Could not write {}: {}", path, e));
+        }
+        println!("{:08x}  {} ({} frames)", nes_main.replay_hash(), path, movie.len());
+    }
+    else if opts.screenshot.is_some() || opts.dump_ppu.is_some() || opts.record_wav.is_some() {
+        for _ in 0..opts.frames {
+            nes_main.step_frame();
+        }
+        if let Some(path) = &opts.screenshot {
+            let image = nes_main.screenshot();
+            if let Err(e) = image.save(path) {
+                fail(&mut nes_main, format!("Could not write {}: {}", path, e));
+            }
+            println!("{:08x}  {}", image.crc32(), path);
+        }
+        if let Some(dir) = &opts.dump_ppu {
+            if let Err(e) = nes_main.dump_ppu(dir, opts.pattern_palette) {
+                fail(&mut nes_main, format!("Could not write PPU views to {}: {}", dir, e));
+            }
+        }
+    }
+    else {
+        for i in 1..=200 {
+            debug!("INSTRUCTION: #{}", i );
+            nes_main.step();
+        }
+    }
+
+    match nes_main.stop_recording() {
+        Ok(files) => for f in files {
+            println!("{:08x}  {}", f.crc32, f.path.display());
+        },
+        Err(e) => fail(&mut nes_main, format!("Could not finish WAV recording: {}", e)),
+    }
+    nes_main.shutdown();
+}
+
+/// Exits with an error once the NES has run: an unfinished WAV is closed
+///  and battery RAM saved first, the same as a run that went fine.
+fn fail(nes: &mut NES, message: String) -> ! {
+    eprintln!("{}", message);
+    nes.shutdown();
+    process::exit(1);
 }